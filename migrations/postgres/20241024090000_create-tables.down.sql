@@ -0,0 +1,9 @@
+DROP TABLE job_runs;
+DROP TABLE api_tokens;
+DROP TABLE user_sessions;
+DROP TABLE states;
+DROP TABLE payouts;
+DROP TABLE historical_weather;
+DROP TABLE forecasts;
+DROP TABLE bets;
+DROP TABLE users;
@@ -0,0 +1,188 @@
+-- Equivalent to the SQLite schema as of `20241023090000_create-job-runs`. Timestamps are stored in
+-- UTC without a time zone, matching SQLite's `CURRENT_TIMESTAMP`.
+
+CREATE TABLE users (
+    -- Unique ID for the user
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- User's balance
+    balance DOUBLE PRECISION NOT NULL,
+
+    -- Last time user was logged in
+    last_login TIMESTAMP NOT NULL,
+
+    -- Date that the user was first created
+    created TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- Unique authentication provider and identifier
+    auth_provider TEXT NOT NULL,
+    auth_identifier TEXT NOT NULL,
+
+    -- Role of the user, either 'user' or 'admin'
+    role TEXT NOT NULL DEFAULT 'user',
+
+    UNIQUE (auth_provider, auth_identifier)
+);
+
+CREATE TABLE bets (
+    -- User that placed the bet
+    "user" BIGINT NOT NULL,
+
+    -- Date the bet is for
+    date DATE NOT NULL,
+
+    -- Values for the bet
+    temperature DOUBLE PRECISION NOT NULL,
+    range DOUBLE PRECISION NOT NULL,
+    rain BOOLEAN NOT NULL,
+
+    -- Money wagered by the user
+    wager DOUBLE PRECISION NOT NULL,
+
+    -- When the bet was placed or updated
+    time_placed TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- Payout amounts for the different components of the bet
+    rain_payout DOUBLE PRECISION NOT NULL,
+    temperature_payout DOUBLE PRECISION NOT NULL,
+
+    -- Each user can only place a bet on one date
+    PRIMARY KEY ("user", date),
+
+    FOREIGN KEY ("user") REFERENCES users(id)
+);
+
+CREATE TABLE forecasts (
+    -- Date that the forecast is for
+    date DATE NOT NULL,
+
+    -- Date that the forecast was retrieved
+    date_retrieved TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- Whether rain is forecast for this day
+    rain DOUBLE PRECISION NOT NULL,
+
+    -- Minimum and maximum temperature for this day
+    minimum_temperature DOUBLE PRECISION NOT NULL,
+    maximum_temperature DOUBLE PRECISION NOT NULL,
+
+    -- WMO weather code for this day
+    weather_code BIGINT NOT NULL,
+
+    PRIMARY KEY (date, date_retrieved)
+);
+
+-- Only one forecast per date per day that it was retrieved
+CREATE UNIQUE INDEX forecasts_retrieval_day ON forecasts (date, CAST(date_retrieved AS DATE));
+
+CREATE TABLE historical_weather (
+    -- Date that the weather was for
+    date DATE NOT NULL PRIMARY KEY,
+
+    -- Average temperature for this day
+    temperature DOUBLE PRECISION NOT NULL,
+
+    -- Whether it rained on this day
+    rain BOOLEAN NOT NULL,
+
+    -- Date that the historical weather was pulled
+    date_retrieved TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC')
+);
+
+CREATE TABLE payouts (
+    -- User and date of the bet
+    "user" BIGINT NOT NULL,
+    date DATE NOT NULL,
+
+    -- Date that the payout occurred
+    payout_date TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- Whether the rain and temperature were correct
+    rain_correct BOOLEAN NOT NULL,
+    temperature_correct BOOLEAN NOT NULL,
+
+    PRIMARY KEY ("user", date),
+    FOREIGN KEY ("user", date) REFERENCES bets("user", date),
+    FOREIGN KEY ("user") REFERENCES users(id)
+);
+
+CREATE TABLE states (
+    -- Namespace for the state value
+    namespace TEXT NOT NULL,
+
+    -- Actual state value
+    value TEXT NOT NULL,
+
+    -- When the value was generated
+    generated TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- When the value was used
+    redeemed TIMESTAMPTZ,
+
+    -- Value must be unique within a namespace
+    PRIMARY KEY (namespace, value)
+);
+
+CREATE TABLE user_sessions (
+    -- Identifier of the underlying session in the session store
+    id TEXT NOT NULL PRIMARY KEY,
+
+    -- User that the session belongs to
+    "user" BIGINT NOT NULL,
+
+    -- User agent that the session was last seen with
+    user_agent TEXT,
+
+    -- When the session was first seen
+    created TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- Last time the session was used
+    last_seen TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    FOREIGN KEY ("user") REFERENCES users(id)
+);
+
+CREATE TABLE api_tokens (
+    -- Unique ID for the token
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- User that the token authenticates as
+    "user" BIGINT NOT NULL,
+
+    -- Name given to the token by the user
+    name TEXT NOT NULL,
+
+    -- Start of the token, so that it can be identified by the user
+    prefix TEXT NOT NULL,
+
+    -- Hash of the token
+    token_hash TEXT NOT NULL UNIQUE,
+
+    -- When the token was created
+    created TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- Last time the token was used
+    last_used TIMESTAMP,
+
+    FOREIGN KEY ("user") REFERENCES users(id)
+);
+
+CREATE TABLE job_runs (
+    -- Unique ID for the run
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- Name of the job that was run
+    job TEXT NOT NULL,
+
+    -- When the run started
+    started TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- When the run finished, if it has
+    finished TIMESTAMP,
+
+    -- Whether the run succeeded, once it has finished
+    succeeded BOOLEAN,
+
+    -- Summary of the run, or the error it failed with
+    message TEXT
+);
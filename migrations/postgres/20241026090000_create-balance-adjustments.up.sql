@@ -0,0 +1,22 @@
+CREATE TABLE balance_adjustments (
+    -- Unique ID for the adjustment
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- User whose balance was adjusted
+    "user" BIGINT NOT NULL,
+
+    -- Amount added to the balance, which is negative if money was taken away
+    amount DOUBLE PRECISION NOT NULL,
+
+    -- Why the balance was adjusted
+    reason TEXT NOT NULL,
+
+    -- Admin that made the adjustment, or NULL if it was made from the CLI
+    admin BIGINT,
+
+    -- When the adjustment was made
+    created TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    FOREIGN KEY ("user") REFERENCES users(id),
+    FOREIGN KEY (admin) REFERENCES users(id)
+);
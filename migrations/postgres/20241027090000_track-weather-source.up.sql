@@ -0,0 +1,31 @@
+-- Where the observed weather came from, either 'archive', 'forecast' or 'import'. Weather saved
+-- before this was tracked is 'unknown'.
+ALTER TABLE historical_weather ADD COLUMN source TEXT NOT NULL DEFAULT 'unknown';
+
+-- Whether the weather may still change, such as when it was filled in from the forecast because the
+-- archive didn't have the day yet. Weather of unknown origin is re-checked against the archive.
+ALTER TABLE historical_weather ADD COLUMN provisional BOOLEAN NOT NULL DEFAULT TRUE;
+
+CREATE TABLE weather_corrections (
+    -- Unique ID for the correction
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- Date that the weather was corrected for
+    date DATE NOT NULL,
+
+    -- Weather that bets were previously settled with
+    previous_temperature DOUBLE PRECISION NOT NULL,
+    previous_rain BOOLEAN NOT NULL,
+    previous_source TEXT NOT NULL,
+
+    -- Weather that replaced it
+    temperature DOUBLE PRECISION NOT NULL,
+    rain BOOLEAN NOT NULL,
+    source TEXT NOT NULL,
+
+    -- Number of paid out bets whose outcome changed
+    bets_resettled BIGINT NOT NULL,
+
+    -- When the correction was made
+    created TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC')
+);
@@ -0,0 +1,4 @@
+DROP TABLE sent_notifications;
+DROP TABLE notification_preferences;
+
+ALTER TABLE users DROP COLUMN email;
@@ -0,0 +1,33 @@
+-- Email address provided by the login provider, which notifications are sent to
+ALTER TABLE users ADD COLUMN email TEXT;
+
+CREATE TABLE notification_preferences (
+    -- User that the preference is for
+    "user" BIGINT NOT NULL,
+
+    -- Kind of notification, such as 'bet_settled'
+    kind TEXT NOT NULL,
+
+    -- Whether the user wants to receive the notification
+    enabled BOOLEAN NOT NULL,
+
+    PRIMARY KEY ("user", kind),
+    FOREIGN KEY ("user") REFERENCES users(id)
+);
+
+CREATE TABLE sent_notifications (
+    -- User that the notification was sent to
+    "user" BIGINT NOT NULL,
+
+    -- Kind of notification that was sent
+    kind TEXT NOT NULL,
+
+    -- Day that the notification was about, so that each is only sent once
+    date DATE NOT NULL,
+
+    -- When the notification was sent
+    sent TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    PRIMARY KEY ("user", kind, date),
+    FOREIGN KEY ("user") REFERENCES users(id)
+);
@@ -0,0 +1,70 @@
+CREATE TABLE webhooks (
+    -- Unique ID for the webhook
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- User whose events are sent to the webhook
+    "user" BIGINT NOT NULL,
+
+    -- URL that events are posted to
+    url TEXT NOT NULL,
+
+    -- Secret that payloads are signed with
+    secret TEXT NOT NULL,
+
+    -- When the webhook was created
+    created TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    FOREIGN KEY ("user") REFERENCES users(id)
+);
+
+CREATE TABLE webhook_outbox (
+    -- Unique ID for the message
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- Webhook that the message is sent to
+    webhook BIGINT NOT NULL,
+
+    -- Name of the event, such as 'bet.placed'
+    event TEXT NOT NULL,
+
+    -- JSON body that is posted
+    payload TEXT NOT NULL,
+
+    -- Number of times delivery has been attempted
+    attempts BIGINT NOT NULL DEFAULT 0,
+
+    -- When delivery should next be attempted
+    next_attempt TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- When the event happened
+    created TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- When the message was delivered or given up on, if it has been
+    completed TIMESTAMP,
+
+    -- Whether the message was delivered, once it has completed
+    delivered BOOLEAN,
+
+    FOREIGN KEY (webhook) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX webhook_outbox_pending ON webhook_outbox (next_attempt) WHERE completed IS NULL;
+
+CREATE TABLE webhook_deliveries (
+    -- Unique ID for the delivery attempt
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- Message that delivery was attempted for
+    message BIGINT NOT NULL,
+
+    -- When delivery was attempted
+    attempted TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    -- HTTP status that the endpoint responded with, if it responded
+    status_code BIGINT,
+
+    -- Why delivery failed, if it did
+    error TEXT,
+
+    FOREIGN KEY (message) REFERENCES webhook_outbox(id) ON DELETE CASCADE
+);
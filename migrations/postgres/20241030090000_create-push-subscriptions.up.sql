@@ -0,0 +1,21 @@
+CREATE TABLE push_subscriptions (
+    -- Unique ID for the subscription
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- User that notifications are pushed to
+    "user" BIGINT NOT NULL,
+
+    -- URL of the push service that messages are posted to, which is unique to the browser
+    endpoint TEXT NOT NULL UNIQUE,
+
+    -- Browser's public key that messages are encrypted for, base64url encoded
+    p256dh TEXT NOT NULL,
+
+    -- Browser's authentication secret, base64url encoded
+    auth TEXT NOT NULL,
+
+    -- When the browser subscribed
+    created TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    FOREIGN KEY ("user") REFERENCES users(id)
+);
@@ -0,0 +1,38 @@
+CREATE TABLE audit_events (
+    -- Unique ID for the event
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+
+    -- User the event concerns, if it's known
+    "user" BIGINT,
+
+    -- Admin that acted on the user's behalf, if it wasn't the user themselves
+    actor BIGINT,
+
+    -- Kind of event, such as `login` or `bet_placed`
+    kind TEXT NOT NULL,
+
+    -- Description of what happened
+    details TEXT NOT NULL,
+
+    -- Address and user agent of the client that caused the event, if it came from a request
+    ip TEXT,
+    user_agent TEXT,
+
+    -- When the event happened
+    created TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+
+    FOREIGN KEY ("user") REFERENCES users(id),
+    FOREIGN KEY (actor) REFERENCES users(id)
+);
+
+CREATE INDEX audit_events_user ON audit_events("user", created);
+
+-- Events may only ever be added
+CREATE FUNCTION reject_audit_event_change() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'audit events are append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_event_change();
@@ -0,0 +1,23 @@
+-- When the user deleted their account, after which their details have been removed and only their
+-- bets and balance remain
+ALTER TABLE users ADD COLUMN deleted TIMESTAMP;
+
+-- Events may still only be added, except that the client details of a deleted user are cleared
+CREATE OR REPLACE FUNCTION reject_audit_event_change() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'UPDATE'
+        AND NEW.id = OLD.id
+        AND NEW."user" IS NOT DISTINCT FROM OLD."user"
+        AND NEW.actor IS NOT DISTINCT FROM OLD.actor
+        AND NEW.kind = OLD.kind
+        AND NEW.details = OLD.details
+        AND NEW.created = OLD.created
+        AND NEW.ip IS NULL
+        AND NEW.user_agent IS NULL
+    THEN
+        RETURN NEW;
+    END IF;
+
+    RAISE EXCEPTION 'audit events are append-only';
+END;
+$$ LANGUAGE plpgsql;
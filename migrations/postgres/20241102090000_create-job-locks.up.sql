@@ -0,0 +1,7 @@
+CREATE TABLE job_locks (
+    -- Name of the job
+    job TEXT NOT NULL PRIMARY KEY,
+
+    -- Until when the replica that last started the job holds on to it
+    locked_until TIMESTAMP NOT NULL
+);
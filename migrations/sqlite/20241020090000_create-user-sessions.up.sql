@@ -0,0 +1,18 @@
+CREATE TABLE user_sessions (
+    -- Identifier of the underlying session in the session store
+    id TEXT NOT NULL PRIMARY KEY,
+
+    -- User that the session belongs to
+    user INTEGER NOT NULL,
+
+    -- User agent that the session was last seen with
+    user_agent TEXT,
+
+    -- When the session was first seen
+    created DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    -- Last time the session was used
+    last_seen DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (user) REFERENCES users(id)
+);
@@ -0,0 +1,24 @@
+CREATE TABLE api_tokens (
+    -- Unique ID for the token
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+
+    -- User that the token authenticates as
+    user INTEGER NOT NULL,
+
+    -- Name given to the token by the user
+    name TEXT NOT NULL,
+
+    -- Start of the token, so that it can be identified by the user
+    prefix TEXT NOT NULL,
+
+    -- Hash of the token
+    token_hash TEXT NOT NULL UNIQUE,
+
+    -- When the token was created
+    created DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    -- Last time the token was used
+    last_used DATETIME,
+
+    FOREIGN KEY (user) REFERENCES users(id)
+);
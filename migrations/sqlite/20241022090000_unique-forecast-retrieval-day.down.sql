@@ -0,0 +1 @@
+DROP INDEX forecasts_retrieval_day;
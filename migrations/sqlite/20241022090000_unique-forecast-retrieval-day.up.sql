@@ -0,0 +1,10 @@
+-- Remove any duplicate forecasts retrieved on the same day, keeping the first
+DELETE FROM forecasts
+    WHERE rowid NOT IN (
+        SELECT MIN(rowid)
+            FROM forecasts
+            GROUP BY date, DATE(date_retrieved)
+    );
+
+-- Only one forecast per date per day that it was retrieved
+CREATE UNIQUE INDEX forecasts_retrieval_day ON forecasts (date, DATE(date_retrieved));
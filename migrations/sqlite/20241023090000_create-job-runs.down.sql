@@ -0,0 +1,3 @@
+DROP TABLE job_runs;
+
+ALTER TABLE users DROP COLUMN role;
@@ -0,0 +1,22 @@
+-- Role of the user, either 'user' or 'admin'
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
+
+CREATE TABLE job_runs (
+    -- Unique ID for the run
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+
+    -- Name of the job that was run
+    job TEXT NOT NULL,
+
+    -- When the run started
+    started DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    -- When the run finished, if it has
+    finished DATETIME,
+
+    -- Whether the run succeeded, once it has finished
+    succeeded BOOLEAN,
+
+    -- Summary of the run, or the error it failed with
+    message TEXT
+);
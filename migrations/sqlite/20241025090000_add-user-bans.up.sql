@@ -0,0 +1,2 @@
+-- Banned users can't log in, and have had their sessions and tokens revoked
+ALTER TABLE users ADD COLUMN banned BOOLEAN NOT NULL DEFAULT FALSE;
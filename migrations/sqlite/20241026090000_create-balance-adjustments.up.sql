@@ -0,0 +1,22 @@
+CREATE TABLE balance_adjustments (
+    -- Unique ID for the adjustment
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+
+    -- User whose balance was adjusted
+    user INTEGER NOT NULL,
+
+    -- Amount added to the balance, which is negative if money was taken away
+    amount FLOAT NOT NULL,
+
+    -- Why the balance was adjusted
+    reason TEXT NOT NULL,
+
+    -- Admin that made the adjustment, or NULL if it was made from the CLI
+    admin INTEGER,
+
+    -- When the adjustment was made
+    created DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (user) REFERENCES users(id),
+    FOREIGN KEY (admin) REFERENCES users(id)
+);
@@ -0,0 +1,4 @@
+DROP TABLE weather_corrections;
+
+ALTER TABLE historical_weather DROP COLUMN provisional;
+ALTER TABLE historical_weather DROP COLUMN source;
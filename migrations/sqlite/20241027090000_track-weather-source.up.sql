@@ -0,0 +1,31 @@
+-- Where the observed weather came from, either 'archive', 'forecast' or 'import'. Weather saved
+-- before this was tracked is 'unknown'.
+ALTER TABLE historical_weather ADD COLUMN source TEXT NOT NULL DEFAULT 'unknown';
+
+-- Whether the weather may still change, such as when it was filled in from the forecast because the
+-- archive didn't have the day yet. Weather of unknown origin is re-checked against the archive.
+ALTER TABLE historical_weather ADD COLUMN provisional BOOLEAN NOT NULL DEFAULT TRUE;
+
+CREATE TABLE weather_corrections (
+    -- Unique ID for the correction
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+
+    -- Date that the weather was corrected for
+    date DATE NOT NULL,
+
+    -- Weather that bets were previously settled with
+    previous_temperature FLOAT NOT NULL,
+    previous_rain BOOLEAN NOT NULL,
+    previous_source TEXT NOT NULL,
+
+    -- Weather that replaced it
+    temperature FLOAT NOT NULL,
+    rain BOOLEAN NOT NULL,
+    source TEXT NOT NULL,
+
+    -- Number of paid out bets whose outcome changed
+    bets_resettled INTEGER NOT NULL,
+
+    -- When the correction was made
+    created DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,70 @@
+CREATE TABLE webhooks (
+    -- Unique ID for the webhook
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+
+    -- User whose events are sent to the webhook
+    user INTEGER NOT NULL,
+
+    -- URL that events are posted to
+    url TEXT NOT NULL,
+
+    -- Secret that payloads are signed with
+    secret TEXT NOT NULL,
+
+    -- When the webhook was created
+    created DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (user) REFERENCES users(id)
+);
+
+CREATE TABLE webhook_outbox (
+    -- Unique ID for the message
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+
+    -- Webhook that the message is sent to
+    webhook INTEGER NOT NULL,
+
+    -- Name of the event, such as 'bet.placed'
+    event TEXT NOT NULL,
+
+    -- JSON body that is posted
+    payload TEXT NOT NULL,
+
+    -- Number of times delivery has been attempted
+    attempts INTEGER NOT NULL DEFAULT 0,
+
+    -- When delivery should next be attempted
+    next_attempt DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    -- When the event happened
+    created DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    -- When the message was delivered or given up on, if it has been
+    completed DATETIME,
+
+    -- Whether the message was delivered, once it has completed
+    delivered BOOLEAN,
+
+    FOREIGN KEY (webhook) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX webhook_outbox_pending ON webhook_outbox (next_attempt) WHERE completed IS NULL;
+
+CREATE TABLE webhook_deliveries (
+    -- Unique ID for the delivery attempt
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+
+    -- Message that delivery was attempted for
+    message INTEGER NOT NULL,
+
+    -- When delivery was attempted
+    attempted DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    -- HTTP status that the endpoint responded with, if it responded
+    status_code INTEGER,
+
+    -- Why delivery failed, if it did
+    error TEXT,
+
+    FOREIGN KEY (message) REFERENCES webhook_outbox(id) ON DELETE CASCADE
+);
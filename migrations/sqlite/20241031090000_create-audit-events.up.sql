@@ -0,0 +1,39 @@
+CREATE TABLE audit_events (
+    -- Unique ID for the event
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+
+    -- User the event concerns, if it's known
+    user INTEGER,
+
+    -- Admin that acted on the user's behalf, if it wasn't the user themselves
+    actor INTEGER,
+
+    -- Kind of event, such as `login` or `bet_placed`
+    kind TEXT NOT NULL,
+
+    -- Description of what happened
+    details TEXT NOT NULL,
+
+    -- Address and user agent of the client that caused the event, if it came from a request
+    ip TEXT,
+    user_agent TEXT,
+
+    -- When the event happened
+    created DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (user) REFERENCES users(id),
+    FOREIGN KEY (actor) REFERENCES users(id)
+);
+
+CREATE INDEX audit_events_user ON audit_events(user, created);
+
+-- Events may only ever be added
+CREATE TRIGGER audit_events_no_update BEFORE UPDATE ON audit_events
+BEGIN
+    SELECT RAISE(ABORT, 'audit events are append-only');
+END;
+
+CREATE TRIGGER audit_events_no_delete BEFORE DELETE ON audit_events
+BEGIN
+    SELECT RAISE(ABORT, 'audit events are append-only');
+END;
@@ -0,0 +1,8 @@
+DROP TRIGGER audit_events_no_update;
+
+CREATE TRIGGER audit_events_no_update BEFORE UPDATE ON audit_events
+BEGIN
+    SELECT RAISE(ABORT, 'audit events are append-only');
+END;
+
+ALTER TABLE users DROP COLUMN deleted;
@@ -0,0 +1,21 @@
+-- When the user deleted their account, after which their details have been removed and only their
+-- bets and balance remain
+ALTER TABLE users ADD COLUMN deleted DATETIME;
+
+-- Events may still only be added, except that the client details of a deleted user are cleared
+DROP TRIGGER audit_events_no_update;
+
+CREATE TRIGGER audit_events_no_update BEFORE UPDATE ON audit_events
+    WHEN NOT (
+        NEW.id IS OLD.id
+        AND NEW.user IS OLD.user
+        AND NEW.actor IS OLD.actor
+        AND NEW.kind IS OLD.kind
+        AND NEW.details IS OLD.details
+        AND NEW.created IS OLD.created
+        AND NEW.ip IS NULL
+        AND NEW.user_agent IS NULL
+    )
+BEGIN
+    SELECT RAISE(ABORT, 'audit events are append-only');
+END;
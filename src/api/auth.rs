@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        request::Parts,
+        HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    services::{bet::BetError, rate_limit::retry_after, weather::WeatherError, DbError},
+    user::UserId,
+    Ctx,
+};
+
+/// Body of all error responses.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Description of the error
+    error: String,
+}
+
+/// Errors that can be returned from the API.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("a valid personal access token must be provided as a bearer token")]
+    Unauthorized,
+
+    #[error("not found")]
+    NotFound,
+
+    #[error("too many requests, please try again in {} seconds", retry_after(.0))]
+    RateLimited(Duration),
+
+    #[error(transparent)]
+    Bet(BetError),
+
+    #[error("weather service is unavailable")]
+    Weather(#[from] WeatherError),
+
+    #[error("internal error")]
+    Db(#[from] DbError),
+}
+
+impl From<BetError> for ApiError {
+    fn from(bet_error: BetError) -> Self {
+        // Pull out any underlying service errors
+        match bet_error {
+            BetError::Weather(e) => Self::Weather(e),
+            BetError::Db(e) => Self::Db(e),
+            bet_error => Self::Bet(bet_error),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::NotFound | ApiError::Bet(BetError::NotFound) => StatusCode::NOT_FOUND,
+            ApiError::Weather(WeatherError::Db(_)) | ApiError::Db(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            ApiError::Weather(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Bet(_) => StatusCode::BAD_REQUEST,
+        };
+
+        if status.is_server_error() {
+            tracing::error!("error handling api request: {self:?}");
+        }
+
+        let mut response = (
+            status,
+            Json(ErrorBody {
+                error: self.to_string(),
+            }),
+        )
+            .into_response();
+
+        if let ApiError::RateLimited(wait) = &self {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after(wait)));
+        }
+
+        response
+    }
+}
+
+/// Token from an `Authorization` header value.
+pub fn bearer_token(header: &str) -> Option<&str> {
+    header.strip_prefix("Bearer ").map(str::trim)
+}
+
+/// User authenticated with a personal access token.
+#[derive(Clone, Copy, Debug)]
+pub struct ApiUser(pub UserId);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ApiUser
+where
+    Ctx: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(user) = parts.extensions.get::<Self>() {
+            return Ok(*user);
+        }
+
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(bearer_token)
+            .ok_or(ApiError::Unauthorized)?;
+
+        Ctx::from_ref(state)
+            .services
+            .token
+            .authenticate(token)
+            .await?
+            .map(Self)
+            .ok_or(ApiError::Unauthorized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body;
+
+    use super::*;
+
+    #[test]
+    fn reads_bearer_token() {
+        assert_eq!(bearer_token("Bearer cc_token"), Some("cc_token"));
+        assert_eq!(bearer_token("Bearer  cc_token "), Some("cc_token"));
+        assert_eq!(bearer_token("Basic dXNlcjpwYXNz"), None);
+        assert_eq!(bearer_token("cc_token"), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_with_json_error() {
+        let response = ApiError::Unauthorized.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()["error"],
+            ApiError::Unauthorized.to_string()
+        );
+    }
+}
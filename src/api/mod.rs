@@ -0,0 +1,276 @@
+mod auth;
+mod openapi;
+mod rate_limit;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{get, put},
+    Json, Router,
+};
+use chrono::{Duration, NaiveDate, Utc};
+use chrono_tz::Australia::Melbourne;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    services::{
+        bet::{Bet, BetHistory, BetOutcome, Payout},
+        rate_limit::Action,
+        weather::Forecast,
+        Services,
+    },
+    Ctx, MELBOURNE,
+};
+
+use self::auth::{ApiError, ApiUser};
+
+#[derive(Serialize, ToSchema)]
+pub struct ForecastDay {
+    date: NaiveDate,
+    forecast: Forecast,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PlacedBet {
+    date: NaiveDate,
+    bet: Bet,
+    payout: Payout,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Balance {
+    balance: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PendingPayout {
+    date: NaiveDate,
+    outcome: BetOutcome,
+}
+
+/// Get the forecast for the coming week.
+#[utoipa::path(
+    get,
+    path = "/forecast",
+    tag = "weather",
+    responses(
+        (status = 200, description = "Forecast for each day", body = [ForecastDay]),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 503, description = "Weather service is unavailable", body = ErrorBody),
+    ),
+)]
+async fn get_forecast(
+    State(ctx): State<Ctx>,
+    _: ApiUser,
+) -> Result<Json<Vec<ForecastDay>>, ApiError> {
+    let today = Utc::now().with_timezone(&Melbourne).naive_local().date();
+    let next_week = today + Duration::weeks(1);
+
+    Ok(Json(
+        ctx.services
+            .weather
+            .get_forecast(today, next_week)
+            .await?
+            .into_iter()
+            .map(|(date, forecast)| ForecastDay { date, forecast })
+            .collect(),
+    ))
+}
+
+/// Get the bet placed for a date.
+#[utoipa::path(
+    get,
+    path = "/bets/{date}",
+    tag = "bets",
+    params(("date" = NaiveDate, Path, description = "Date the bet is for")),
+    responses(
+        (status = 200, description = "Bet placed for the date", body = Bet),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 404, description = "No bet has been placed for the date", body = ErrorBody),
+    ),
+)]
+async fn get_bet(
+    State(ctx): State<Ctx>,
+    ApiUser(user_id): ApiUser,
+    Path(date): Path<NaiveDate>,
+) -> Result<Json<Bet>, ApiError> {
+    ctx.services
+        .bet
+        .find_bet(user_id, date)
+        .await?
+        .map(Json)
+        .ok_or(ApiError::NotFound)
+}
+
+/// Place a bet for a date, replacing any existing bet.
+#[utoipa::path(
+    put,
+    path = "/bets/{date}",
+    tag = "bets",
+    params(("date" = NaiveDate, Path, description = "Date the bet is for")),
+    request_body = Bet,
+    responses(
+        (status = 200, description = "Bet was placed", body = PlacedBet),
+        (status = 400, description = "Bet is invalid, or its date is locked in", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 429, description = "Too many bets have been placed recently", body = ErrorBody),
+        (status = 503, description = "Weather service is unavailable", body = ErrorBody),
+    ),
+)]
+async fn place_bet(
+    State(ctx): State<Ctx>,
+    ApiUser(user_id): ApiUser,
+    Path(date): Path<NaiveDate>,
+    Json(bet): Json<Bet>,
+) -> Result<Json<PlacedBet>, ApiError> {
+    // Determine the forecast for the day
+    let forecast = ctx
+        .services
+        .weather
+        .get_daily_forecast(date, MELBOURNE)
+        .await?;
+    let payout = Payout::max_payout(&ctx.config.game, &bet, date, &forecast);
+
+    ctx.services
+        .bet
+        .place(user_id, date, bet.clone(), payout.clone())
+        .await?;
+
+    Ok(Json(PlacedBet { date, bet, payout }))
+}
+
+/// Cancel the bet for a date, refunding the wager.
+#[utoipa::path(
+    delete,
+    path = "/bets/{date}",
+    tag = "bets",
+    params(("date" = NaiveDate, Path, description = "Date the bet is for")),
+    responses(
+        (status = 204, description = "Bet was cancelled"),
+        (status = 400, description = "Bet can no longer be cancelled", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 404, description = "No bet has been placed for the date", body = ErrorBody),
+    ),
+)]
+async fn cancel_bet(
+    State(ctx): State<Ctx>,
+    ApiUser(user_id): ApiUser,
+    Path(date): Path<NaiveDate>,
+) -> Result<StatusCode, ApiError> {
+    ctx.services.bet.cancel(user_id, date).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get the current balance.
+#[utoipa::path(
+    get,
+    path = "/balance",
+    tag = "account",
+    responses(
+        (status = 200, description = "Current balance", body = Balance),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+)]
+async fn get_balance(
+    State(ctx): State<Ctx>,
+    ApiUser(user_id): ApiUser,
+) -> Result<Json<Balance>, ApiError> {
+    Ok(Json(Balance {
+        balance: ctx.services.bet.get_balance(user_id).await?,
+    }))
+}
+
+/// Get all payouts that are ready to be claimed.
+#[utoipa::path(
+    get,
+    path = "/payouts",
+    tag = "payouts",
+    responses(
+        (status = 200, description = "Payouts ready to be claimed", body = [PendingPayout]),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+)]
+async fn get_payouts(
+    State(ctx): State<Ctx>,
+    ApiUser(user_id): ApiUser,
+) -> Result<Json<Vec<PendingPayout>>, ApiError> {
+    let mut payouts = ctx
+        .services
+        .bet
+        .get_ready(user_id)
+        .await?
+        .into_iter()
+        .map(|(date, outcome)| PendingPayout { date, outcome })
+        .collect::<Vec<_>>();
+
+    payouts.sort_unstable_by_key(|payout| payout.date);
+
+    Ok(Json(payouts))
+}
+
+/// Claim all payouts that are ready.
+#[utoipa::path(
+    post,
+    path = "/payouts",
+    tag = "payouts",
+    responses(
+        (status = 200, description = "Balance after claiming payouts", body = Balance),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+)]
+async fn claim_payouts(
+    State(ctx): State<Ctx>,
+    ApiUser(user_id): ApiUser,
+) -> Result<Json<Balance>, ApiError> {
+    ctx.services.bet.payout(user_id).await?;
+
+    Ok(Json(Balance {
+        balance: ctx.services.bet.get_balance(user_id).await?,
+    }))
+}
+
+/// Get every bet that has been placed, most recent first.
+#[utoipa::path(
+    get,
+    path = "/history",
+    tag = "bets",
+    responses(
+        (status = 200, description = "All bets and their outcomes", body = [BetHistory]),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+)]
+async fn get_history(
+    State(ctx): State<Ctx>,
+    ApiUser(user_id): ApiUser,
+) -> Result<Json<Vec<BetHistory>>, ApiError> {
+    Ok(Json(ctx.services.bet.history(user_id).await?))
+}
+
+fn v1(services: &Services) -> Router<Ctx> {
+    Router::new()
+        .route("/forecast", get(get_forecast))
+        .route(
+            "/bets/:date",
+            put(place_bet).route_layer(middleware::from_fn_with_state(
+                (
+                    services.rate_limit.clone(),
+                    services.token.clone(),
+                    Action::Bet,
+                ),
+                rate_limit::enforce,
+            )),
+        )
+        .route("/bets/:date", get(get_bet).delete(cancel_bet))
+        .route("/balance", get(get_balance))
+        .route("/payouts", get(get_payouts).post(claim_payouts))
+        .route("/history", get(get_history))
+}
+
+pub fn init(services: &Services) -> Router<Ctx> {
+    Router::new()
+        .route("/openapi.json", get(openapi::serve))
+        .nest("/v1", v1(services))
+}
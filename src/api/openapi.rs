@@ -0,0 +1,91 @@
+use axum::Json;
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::services::{
+    bet::{Bet, BetHistory, BetOutcome, Payout},
+    weather::{Forecast, Weather, WeatherCode},
+};
+
+use super::{auth::ErrorBody, Balance, ForecastDay, PendingPayout, PlacedBet};
+
+/// Registers personal access tokens as the authentication method for the API.
+struct PersonalAccessToken;
+
+impl Modify for PersonalAccessToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "token",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Cloud Casino", description = "Bet (fake) money on the weather!"),
+    servers((url = "/api/v1")),
+    paths(
+        super::get_forecast,
+        super::get_bet,
+        super::place_bet,
+        super::cancel_bet,
+        super::get_balance,
+        super::get_payouts,
+        super::claim_payouts,
+        super::get_history,
+    ),
+    components(schemas(
+        Bet,
+        BetHistory,
+        BetOutcome,
+        Payout,
+        Forecast,
+        Weather,
+        WeatherCode,
+        ForecastDay,
+        PlacedBet,
+        Balance,
+        PendingPayout,
+        ErrorBody,
+    )),
+    modifiers(&PersonalAccessToken),
+    security(("token" = [])),
+)]
+pub struct ApiDoc;
+
+pub async fn serve() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    const DOCUMENT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    /// The checked in document must match what is generated from the handlers. Run with
+    /// `UPDATE_OPENAPI=1` to regenerate it after changing the API.
+    #[test]
+    fn document_is_up_to_date() {
+        let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+
+        if env::var_os("UPDATE_OPENAPI").is_some() {
+            fs::write(DOCUMENT_PATH, &generated).unwrap();
+            return;
+        }
+
+        let existing = fs::read_to_string(DOCUMENT_PATH).unwrap_or_default();
+        assert!(
+            existing == generated,
+            "openapi.json is out of date, regenerate it with `just openapi`"
+        );
+    }
+}
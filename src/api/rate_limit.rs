@@ -0,0 +1,54 @@
+use axum::{
+    extract::{Request, State},
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    app::client,
+    services::{
+        rate_limit::{Action, Client, RateLimitService},
+        token::TokenService,
+    },
+};
+
+use super::auth::{bearer_token, ApiError, ApiUser};
+
+/// Limit how often `action` can be performed, both by the client's IP address and by the user
+/// their token authenticates as. Requests with an invalid token are only limited by IP address,
+/// and are rejected later when the user is extracted.
+pub async fn enforce(
+    State((rate_limit, token, action)): State<(RateLimitService, TokenService, Action)>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let mut clients = Vec::with_capacity(2);
+    if let Some(ip) = client::client_ip(
+        request.headers(),
+        request.extensions(),
+        rate_limit.trust_forwarded_for(),
+    ) {
+        clients.push(Client::Ip(ip));
+    }
+
+    let bearer = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(bearer_token);
+    if let Some(bearer) = bearer {
+        if let Some(user_id) = token.authenticate(bearer).await? {
+            clients.push(Client::User(user_id));
+
+            // Saves authenticating the token again in the handler
+            request.extensions_mut().insert(ApiUser(user_id));
+        }
+    }
+
+    rate_limit
+        .check(action, &clients)
+        .map_err(ApiError::RateLimited)?;
+
+    Ok(next.run(request).await)
+}
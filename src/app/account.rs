@@ -0,0 +1,250 @@
+use std::{borrow::Cow, cmp::Reverse};
+
+use axum::{
+    extract::State,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use axum_htmx::HxLocation;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use tower_sessions::Session;
+
+use crate::{
+    services::{
+        audit::AuditRecord,
+        bet::BetHistory,
+        user::{AdjustmentRecord, UserRecord},
+        DbError,
+    },
+    user::UserId,
+    Ctx,
+};
+
+use super::error::AppError;
+
+/// Everything that is kept about the user.
+#[derive(Serialize)]
+struct Export {
+    profile: UserRecord,
+    bets: Vec<BetHistory>,
+    adjustments: Vec<AdjustmentRecord>,
+    sessions: Vec<ExportSession>,
+    tokens: Vec<ExportToken>,
+    webhooks: Vec<ExportWebhook>,
+    notifications: Vec<ExportNotification>,
+    activity: Vec<AuditRecord>,
+}
+
+/// Session, without the identifier that would allow it to be used.
+#[derive(Serialize)]
+struct ExportSession {
+    user_agent: Option<String>,
+    created: NaiveDateTime,
+    last_seen: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct ExportToken {
+    name: String,
+    prefix: String,
+    created: NaiveDateTime,
+    last_used: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+struct ExportWebhook {
+    url: String,
+    created: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct ExportNotification {
+    kind: &'static str,
+    enabled: bool,
+}
+
+async fn load_export(ctx: &Ctx, user_id: UserId) -> Result<Export, AppError> {
+    let profile = ctx
+        .services
+        .user
+        .find(user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Export {
+        profile,
+        bets: ctx.services.bet.history(user_id).await?,
+        adjustments: ctx.services.user.adjustments(user_id).await?,
+        sessions: ctx
+            .services
+            .session
+            .list(user_id)
+            .await?
+            .into_iter()
+            .map(|record| ExportSession {
+                user_agent: record.user_agent,
+                created: record.created,
+                last_seen: record.last_seen,
+            })
+            .collect(),
+        tokens: ctx
+            .services
+            .token
+            .list(user_id)
+            .await?
+            .into_iter()
+            .map(|record| ExportToken {
+                name: record.name,
+                prefix: record.prefix,
+                created: record.created,
+                last_used: record.last_used,
+            })
+            .collect(),
+        webhooks: ctx
+            .services
+            .webhooks
+            .list(user_id)
+            .await?
+            .into_iter()
+            .map(|record| ExportWebhook {
+                url: record.url,
+                created: record.created,
+            })
+            .collect(),
+        notifications: ctx
+            .services
+            .notifications
+            .preferences(user_id)
+            .await?
+            .into_iter()
+            .map(|(kind, enabled)| ExportNotification {
+                kind: kind.name(),
+                enabled,
+            })
+            .collect(),
+        activity: ctx.services.audit.for_user(user_id).await?,
+    })
+}
+
+/// Headers to download the export as a file, rather than showing it.
+fn attachment(content_type: &'static str, extension: &str) -> [(&'static str, String); 2] {
+    [
+        (CONTENT_TYPE.as_str(), content_type.to_string()),
+        (
+            CONTENT_DISPOSITION.as_str(),
+            format!("attachment; filename=\"cloud-casino.{extension}\""),
+        ),
+    ]
+}
+
+async fn export_json(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+) -> Result<impl IntoResponse, AppError> {
+    let export = load_export(&ctx, user_id).await?;
+
+    Ok((attachment("application/json", "json"), Json(export)))
+}
+
+/// Quote a CSV field if it needs to be. Text that a spreadsheet would treat as a formula is
+/// prefixed so that it is shown as-is.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        Cow::Owned(format!("'{value}"))
+    } else {
+        Cow::Borrowed(value)
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        value
+    }
+}
+
+/// Every change to the user's balance, as CSV: wagers, payouts and adjustments.
+async fn export_csv(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+) -> Result<impl IntoResponse, AppError> {
+    let export = load_export(&ctx, user_id).await?;
+
+    // Rows of the date, kind, amount and description
+    let mut rows = Vec::new();
+    for history in &export.bets {
+        let date = history.date.and_hms_opt(0, 0, 0).unwrap();
+
+        rows.push((
+            date,
+            "wager",
+            -history.bet.wager,
+            format!(
+                "{}° ± {}°, {}",
+                history.bet.temperature,
+                history.bet.range,
+                if history.bet.rain { "rain" } else { "no rain" }
+            ),
+        ));
+
+        if let Some(outcome) = &history.outcome {
+            rows.push((
+                date,
+                "payout",
+                outcome.payout,
+                format!(
+                    "temperature {}, rain {}",
+                    if outcome.temperature {
+                        "correct"
+                    } else {
+                        "incorrect"
+                    },
+                    if outcome.rain { "correct" } else { "incorrect" }
+                ),
+            ));
+        }
+    }
+    for adjustment in &export.adjustments {
+        rows.push((
+            adjustment.created,
+            "adjustment",
+            adjustment.amount,
+            adjustment.reason.clone(),
+        ));
+    }
+    rows.sort_by_key(|(date, ..)| Reverse(*date));
+
+    let mut csv = String::from("date,kind,amount,description\n");
+    for (date, kind, amount, description) in rows {
+        csv.push_str(&format!(
+            "{},{kind},{amount:.2},{}\n",
+            date.format("%Y-%m-%d %H:%M:%S"),
+            csv_field(&description)
+        ));
+    }
+
+    Ok((attachment("text/csv; charset=utf-8", "csv"), csv))
+}
+
+/// Delete the user's account, logging them out.
+async fn delete_account(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    session: Session,
+) -> Result<(HxLocation, &'static str), AppError> {
+    ctx.services.user.delete(user_id).await?;
+
+    // The session has already been revoked, but may still be cached
+    session.flush().await.map_err(DbError::from)?;
+
+    Ok((HxLocation::from_str("/").unwrap(), "redirecting"))
+}
+
+pub fn init() -> Router<Ctx> {
+    Router::new()
+        .route("/export.json", get(export_json))
+        .route("/export.csv", get(export_csv))
+        .route("/delete", post(delete_account))
+}
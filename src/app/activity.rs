@@ -0,0 +1,46 @@
+use axum::{extract::State, routing::get, Router};
+use maud::Markup;
+
+use crate::{user::UserId, Ctx};
+
+use super::{csrf::CsrfToken, error::AppError, views};
+
+async fn render_activity(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    csrf: CsrfToken,
+) -> Result<Markup, AppError> {
+    let balance = ctx.services.bet.get_balance(user_id).await?;
+    let ready_payouts = ctx.services.bet.get_ready(user_id).await?;
+
+    let activity = ctx
+        .services
+        .audit
+        .for_user(user_id)
+        .await?
+        .into_iter()
+        .map(|record| views::activity::Activity {
+            kind: record.kind,
+            details: record.details,
+            ip: record.ip,
+            user_agent: record.user_agent,
+            by_admin: record.actor.is_some(),
+            created: record.created,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(views::page(
+        Some(&csrf),
+        views::shell::render(
+            format!("${balance:.2}"),
+            ready_payouts.len(),
+            true,
+            true,
+            views::activity::render(&activity),
+        ),
+    ))
+}
+
+pub fn init() -> Router<Ctx> {
+    Router::new().route("/", get(render_activity))
+}
@@ -0,0 +1,336 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Form, Router,
+};
+use chrono::{Duration, NaiveDate, Utc};
+use chrono_tz::Australia::Melbourne;
+use maud::Markup;
+use serde::Deserialize;
+
+use crate::{
+    services::{
+        audit::{AuditFilter, AuditKind},
+        user::UserRecord,
+    },
+    user::{AdminUser, UserId},
+    Ctx,
+};
+
+use super::{csrf::CsrfToken, error::AppError, views};
+
+/// Days either side of today to show in the weather cache.
+const CACHE_DAYS: i64 = 7;
+
+/// Render a page of the admin area, with the admin's own balance in the header.
+async fn render_page(
+    ctx: &Ctx,
+    admin: UserId,
+    csrf: &CsrfToken,
+    content: Markup,
+) -> Result<Markup, AppError> {
+    let balance = ctx.services.bet.get_balance(admin).await?;
+    let ready_payouts = ctx.services.bet.get_ready(admin).await?;
+
+    Ok(views::page(
+        Some(csrf),
+        views::shell::render(
+            format!("${balance:.2}"),
+            ready_payouts.len(),
+            true,
+            true,
+            content,
+        ),
+    ))
+}
+
+async fn render_dashboard(
+    ctx: &Ctx,
+    admin: UserId,
+    csrf: &CsrfToken,
+    voided: Option<(NaiveDate, usize)>,
+) -> Result<Markup, AppError> {
+    let jobs = ctx
+        .services
+        .jobs
+        .status()
+        .await?
+        .into_iter()
+        .map(|status| views::admin::Job {
+            name: status.job.name(),
+            last_started: status.last_run.as_ref().map(|run| run.started),
+            last_finished: status.last_run.as_ref().and_then(|run| run.finished),
+            succeeded: status.last_run.as_ref().and_then(|run| run.succeeded),
+            message: status.last_run.and_then(|run| run.message),
+            next_run: status.next_run.map(|next_run| next_run.naive_utc()),
+        })
+        .collect::<Vec<_>>();
+
+    let today = Utc::now().with_timezone(&Melbourne).naive_local().date();
+    let (forecasts, observations) = ctx
+        .services
+        .weather
+        .cached(
+            today - Duration::days(CACHE_DAYS),
+            today + Duration::days(CACHE_DAYS),
+        )
+        .await?;
+
+    let forecasts = forecasts
+        .into_iter()
+        .map(|cached| views::admin::CachedForecast {
+            date: cached.date,
+            retrieved: cached.date_retrieved,
+            rain: cached.forecast.rain,
+            minimum_temperature: cached.forecast.minimum_temperature,
+            maximum_temperature: cached.forecast.maximum_temperature,
+        })
+        .collect::<Vec<_>>();
+
+    let observations = observations
+        .into_iter()
+        .map(|cached| views::admin::Observation {
+            date: cached.date,
+            retrieved: cached.date_retrieved,
+            rain: cached.weather.rain,
+            temperature: cached.weather.temperature,
+            source: cached.source.name(),
+            provisional: cached.provisional,
+        })
+        .collect::<Vec<_>>();
+
+    render_page(
+        ctx,
+        admin,
+        csrf,
+        views::admin::render(csrf, &jobs, &forecasts, &observations, voided),
+    )
+    .await
+}
+
+async fn dashboard(
+    State(ctx): State<Ctx>,
+    AdminUser(admin): AdminUser,
+    csrf: CsrfToken,
+) -> Result<Markup, AppError> {
+    render_dashboard(&ctx, admin, &csrf, None).await
+}
+
+fn user_view(user: UserRecord) -> views::admin::User {
+    views::admin::User {
+        admin: user.is_admin(),
+        id: user.id,
+        provider: user.auth_provider,
+        identifier: user.auth_identifier,
+        balance: user.balance,
+        banned: user.banned,
+        deleted: user.deleted.is_some(),
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+async fn search_users(
+    State(ctx): State<Ctx>,
+    AdminUser(_): AdminUser,
+    Query(query): Query<SearchQuery>,
+) -> Result<Markup, AppError> {
+    let users = if query.q.trim().is_empty() {
+        Vec::new()
+    } else {
+        ctx.services
+            .user
+            .search(&query.q)
+            .await?
+            .into_iter()
+            .map(user_view)
+            .collect()
+    };
+
+    Ok(views::admin::render_users(&users))
+}
+
+async fn render_user(
+    ctx: &Ctx,
+    admin: UserId,
+    csrf: &CsrfToken,
+    user_id: UserId,
+) -> Result<Markup, AppError> {
+    let user = ctx
+        .services
+        .user
+        .find(user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let bets = ctx
+        .services
+        .bet
+        .history(user_id)
+        .await?
+        .into_iter()
+        .map(|history| views::admin::Bet {
+            date: history.date,
+            wager: history.bet.wager,
+            payout: history.outcome.map(|outcome| outcome.payout),
+        })
+        .collect::<Vec<_>>();
+
+    let adjustments = ctx
+        .services
+        .user
+        .adjustments(user_id)
+        .await?
+        .into_iter()
+        .map(|record| views::admin::Adjustment {
+            amount: record.amount,
+            reason: record.reason,
+            admin: record.admin,
+            created: record.created,
+        })
+        .collect::<Vec<_>>();
+
+    render_page(
+        ctx,
+        admin,
+        csrf,
+        views::admin::render_user(csrf, &user_view(user), &bets, &adjustments),
+    )
+    .await
+}
+
+async fn user(
+    State(ctx): State<Ctx>,
+    AdminUser(admin): AdminUser,
+    csrf: CsrfToken,
+    Path(id): Path<i64>,
+) -> Result<Markup, AppError> {
+    render_user(&ctx, admin, &csrf, UserId::from(id)).await
+}
+
+#[derive(Deserialize)]
+struct AdjustmentForm {
+    amount: f64,
+    reason: String,
+}
+
+async fn adjust_balance(
+    State(ctx): State<Ctx>,
+    AdminUser(admin): AdminUser,
+    csrf: CsrfToken,
+    Path(id): Path<i64>,
+    Form(form): Form<AdjustmentForm>,
+) -> Result<Markup, AppError> {
+    // Infinity and NaN parse as numbers, but would corrupt the balance
+    if !form.amount.is_finite() || form.amount == 0.0 {
+        return Err(AppError::InvalidForm(
+            "the amount must be a non-zero number",
+        ));
+    }
+
+    let reason = form.reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::InvalidForm("a reason is required"));
+    }
+
+    let user_id = UserId::from(id);
+    ctx.services
+        .user
+        .adjust_balance(user_id, form.amount, reason, Some(admin))
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    render_user(&ctx, admin, &csrf, user_id).await
+}
+
+#[derive(Deserialize)]
+struct VoidForm {
+    date: NaiveDate,
+    reason: String,
+}
+
+async fn void_bets(
+    State(ctx): State<Ctx>,
+    AdminUser(admin): AdminUser,
+    csrf: CsrfToken,
+    Form(form): Form<VoidForm>,
+) -> Result<Markup, AppError> {
+    let reason = form.reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::InvalidForm("a reason is required"));
+    }
+
+    let refunds = ctx
+        .services
+        .bet
+        .void(form.date, reason, Some(admin))
+        .await?;
+
+    render_dashboard(&ctx, admin, &csrf, Some((form.date, refunds.len()))).await
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    #[serde(default)]
+    kind: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+}
+
+async fn audit(
+    State(ctx): State<Ctx>,
+    AdminUser(admin): AdminUser,
+    csrf: CsrfToken,
+    Query(query): Query<AuditQuery>,
+) -> Result<Markup, AppError> {
+    // The filter form submits empty values for anything that isn't filtered on
+    let kind = query.kind.as_deref().and_then(AuditKind::from_name);
+    let user = query
+        .user
+        .as_deref()
+        .and_then(|user| user.trim().parse::<i64>().ok());
+
+    let entries = ctx
+        .services
+        .audit
+        .list(&AuditFilter {
+            user: user.map(UserId::from),
+            kind,
+        })
+        .await?
+        .into_iter()
+        .map(|record| views::admin::AuditEntry {
+            user: record.user,
+            identifier: record.identifier,
+            actor: record.actor,
+            kind: record.kind,
+            details: record.details,
+            ip: record.ip,
+            user_agent: record.user_agent,
+            created: record.created,
+        })
+        .collect::<Vec<_>>();
+
+    let kinds = AuditKind::ALL.map(AuditKind::name);
+
+    render_page(
+        &ctx,
+        admin,
+        &csrf,
+        views::admin::render_audit(&kinds, kind.map(AuditKind::name), user, &entries),
+    )
+    .await
+}
+
+pub fn init() -> Router<Ctx> {
+    Router::new()
+        .route("/", get(dashboard))
+        .route("/users", get(search_users))
+        .route("/users/:id", get(user))
+        .route("/users/:id/adjustments", post(adjust_balance))
+        .route("/bets/void", post(void_bets))
+        .route("/audit", get(audit))
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use axum::{
+    extract::Path as UrlPath,
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+        StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use sha2::{Digest, Sha256};
+
+use crate::Ctx;
+
+/// Files within the static directory that pages link to. They're served with a hash of their
+/// contents in the name, so browsers can cache them forever. Files in `vendor` are pinned in
+/// `vendor.txt` and downloaded with `just vendor`, so that nothing is loaded from third parties.
+const ASSETS: &[&str] = &[
+    "app.css",
+    "main.js",
+    "vendor/open-props.min.css",
+    "vendor/normalize.light.min.css",
+    "vendor/htmx.min.js",
+    "vendor/htmx-ext-sse.js",
+];
+
+/// Number of hex characters of the hash included in the name.
+const HASH_LEN: usize = 16;
+
+/// Hashed names never change content, so can be cached for as long as browsers allow.
+const CACHE_CONTROL_VALUE: &str = "public, max-age=31536000, immutable";
+
+static ASSETS_LOADED: OnceLock<Assets> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error)]
+pub enum AssetError {
+    #[error("unable to read asset `{0}` (run `just vendor` to download vendored files): {1}")]
+    Read(PathBuf, io::Error),
+
+    #[error("assets have already been loaded")]
+    AlreadyLoaded,
+}
+
+struct Asset {
+    content: Vec<u8>,
+    content_type: &'static str,
+}
+
+struct Assets {
+    /// URL for each asset, keyed by its path within the static directory
+    urls: HashMap<&'static str, String>,
+
+    /// Contents of each asset, keyed by its hashed name
+    files: HashMap<String, Asset>,
+}
+
+/// Read and hash every asset in the static directory. Must be called before any page is rendered.
+pub fn load(static_dir: &Path) -> Result<(), AssetError> {
+    let mut assets = Assets {
+        urls: HashMap::new(),
+        files: HashMap::new(),
+    };
+
+    for &path in ASSETS {
+        let full_path = static_dir.join(path);
+        let content = fs::read(&full_path).map_err(|e| AssetError::Read(full_path, e))?;
+
+        let hash = hex::encode(Sha256::digest(&content));
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        let (stem, extension) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+        let hashed_name = format!("{stem}.{}.{extension}", &hash[..HASH_LEN]);
+
+        assets.urls.insert(path, format!("/assets/{hashed_name}"));
+        assets.files.insert(
+            hashed_name,
+            Asset {
+                content,
+                content_type: match extension {
+                    "css" => "text/css",
+                    "js" => "text/javascript",
+                    _ => "application/octet-stream",
+                },
+            },
+        );
+    }
+
+    ASSETS_LOADED
+        .set(assets)
+        .map_err(|_| AssetError::AlreadyLoaded)
+}
+
+/// URL that an asset is served from, given its path within the static directory.
+pub fn url(path: &str) -> &'static str {
+    ASSETS_LOADED
+        .get()
+        .expect("assets are loaded before rendering")
+        .urls
+        .get(path)
+        .unwrap_or_else(|| panic!("`{path}` isn't a known asset"))
+}
+
+async fn asset(UrlPath(name): UrlPath<String>) -> Response {
+    match ASSETS_LOADED
+        .get()
+        .and_then(|assets| assets.files.get(&name))
+    {
+        Some(asset) => (
+            [
+                (CONTENT_TYPE, asset.content_type),
+                (CACHE_CONTROL, CACHE_CONTROL_VALUE),
+            ],
+            asset.content.clone(),
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub fn init() -> Router<Ctx> {
+    Router::new().route("/:name", get(asset))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    /// Paths within the static directory that `vendor.txt` pins.
+    fn pinned() -> Vec<String> {
+        fs::read_to_string("vendor.txt")
+            .unwrap()
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| Some(line.split_once(' ')?.0.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn every_asset_is_available() {
+        let pinned = pinned();
+
+        for path in ASSETS {
+            if path.starts_with("vendor/") {
+                assert!(
+                    pinned.iter().any(|pinned| pinned == path),
+                    "{path} isn't pinned"
+                );
+            } else {
+                assert!(
+                    Path::new("static").join(path).is_file(),
+                    "{path} is missing"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn serves_assets_by_hashed_name() {
+        // The vendor files aren't checked in, so stand in for them
+        let static_dir = env::temp_dir().join(format!("cloud-casino-assets-{}", process::id()));
+        for path in ASSETS {
+            let target = static_dir.join(path);
+            fs::create_dir_all(target.parent().unwrap()).unwrap();
+
+            if path.starts_with("vendor/") {
+                fs::write(target, format!("/* {path} */")).unwrap();
+            } else {
+                fs::copy(Path::new("static").join(path), target).unwrap();
+            }
+        }
+
+        load(&static_dir).unwrap();
+        assert!(matches!(load(&static_dir), Err(AssetError::AlreadyLoaded)));
+        fs::remove_dir_all(&static_dir).unwrap();
+
+        let content = fs::read("static/app.css").unwrap();
+        let hash = hex::encode(Sha256::digest(&content));
+        let app_url = url("app.css");
+        assert_eq!(app_url, format!("/assets/app.{}.css", &hash[..HASH_LEN]));
+
+        let asset = &ASSETS_LOADED.get().unwrap().files[app_url.trim_start_matches("/assets/")];
+        assert_eq!(asset.content, content);
+        assert_eq!(asset.content_type, "text/css");
+        assert!(url("vendor/htmx.min.js").ends_with(".js"));
+    }
+}
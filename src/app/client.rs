@@ -0,0 +1,114 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts, Extensions, HeaderMap},
+};
+
+use crate::Ctx;
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Address of the client, if it can be determined. Requests over a unix socket only have one
+/// through `X-Forwarded-For`, where only the last address is used: it was appended by the trusted
+/// proxy, whereas anything before it was sent by the client and could be made up.
+pub fn client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    trust_forwarded_for: bool,
+) -> Option<IpAddr> {
+    if trust_forwarded_for {
+        // The header may also be repeated, with the proxy's being the last
+        let forwarded = headers
+            .get_all(FORWARDED_FOR)
+            .iter()
+            .next_back()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|address| address.trim().parse().ok());
+
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.ip())
+}
+
+/// Details of the client making the request, as far as they can be determined.
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    Ctx: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let trust_forwarded_for = Ctx::from_ref(state).config.server.trust_forwarded_for;
+
+        Ok(Self {
+            ip: client_ip(&parts.headers, &parts.extensions, trust_forwarded_for),
+            user_agent: parts
+                .headers
+                .get(USER_AGENT)
+                .and_then(|user_agent| user_agent.to_str().ok())
+                .map(str::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn extensions() -> Extensions {
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(SocketAddr::from((Ipv4Addr::LOCALHOST, 1234))));
+        extensions
+    }
+
+    #[test]
+    fn uses_address_appended_by_proxy() {
+        let mut headers = HeaderMap::new();
+        headers.append(FORWARDED_FOR, HeaderValue::from_static("1.1.1.1, 2.2.2.2"));
+        headers.append(FORWARDED_FOR, HeaderValue::from_static("3.3.3.3, 4.4.4.4"));
+
+        assert_eq!(
+            client_ip(&headers, &extensions(), true),
+            Some(Ipv4Addr::new(4, 4, 4, 4).into())
+        );
+        assert_eq!(
+            client_ip(&headers, &extensions(), false),
+            Some(Ipv4Addr::LOCALHOST.into())
+        );
+    }
+
+    #[test]
+    fn falls_back_to_peer_address() {
+        let mut headers = HeaderMap::new();
+        headers.insert(FORWARDED_FOR, HeaderValue::from_static("1.1.1.1, nonsense"));
+
+        assert_eq!(
+            client_ip(&headers, &extensions(), true),
+            Some(Ipv4Addr::LOCALHOST.into())
+        );
+        assert_eq!(client_ip(&HeaderMap::new(), &Extensions::new(), true), None);
+    }
+}
@@ -0,0 +1,281 @@
+//! Synchronizer token protection against cross-site request forgery. Each session is given a
+//! random token, which must accompany every request that could change something, either as the
+//! [`HEADER`] (which htmx sends for every request on the page) or as the [`FIELD`] of a form.
+
+use axum::{
+    async_trait,
+    body::{self, Body},
+    extract::{FromRequestParts, Request},
+    http::{header::CONTENT_TYPE, request::Parts, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use maud::{html, Markup};
+use rand::{thread_rng, RngCore};
+use subtle::ConstantTimeEq;
+use tower_sessions::Session;
+
+use crate::services::DbError;
+
+use super::error::AppError;
+
+/// Header that htmx requests include the token in.
+pub const HEADER: &str = "x-csrf-token";
+
+/// Name of the form field that forms include the token in.
+pub const FIELD: &str = "csrf_token";
+
+const SESSION_KEY: &str = "csrf_token";
+
+static RANDOM_BUFFER_LEN: usize = 32;
+
+/// Largest form body that will be read whilst looking for the token.
+const FORM_LIMIT: usize = 64 * 1024;
+
+/// Token for the current session, which must be included with any form or htmx request.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    /// Fetch the token for the session, generating one if it doesn't have one yet.
+    pub async fn from_session(session: &Session) -> Result<Self, DbError> {
+        if let Some(token) = session.get::<String>(SESSION_KEY).await? {
+            return Ok(Self(token));
+        }
+
+        let token = {
+            let mut bytes = [0; RANDOM_BUFFER_LEN];
+            thread_rng().fill_bytes(&mut bytes);
+
+            URL_SAFE_NO_PAD.encode(bytes)
+        };
+        session.insert(SESSION_KEY, &token).await?;
+
+        Ok(Self(token))
+    }
+
+    /// Hidden input to include the token when a form is submitted.
+    pub fn input(&self) -> Markup {
+        html! {
+            input type="hidden" name=(FIELD) value=(self.0);
+        }
+    }
+
+    /// Value for `hx-headers`, so that htmx includes the token with every request.
+    pub fn hx_headers(&self) -> String {
+        serde_json::json!({ HEADER: self.0 }).to_string()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CsrfToken
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|e| e.into_response())?;
+
+        Self::from_session(&session)
+            .await
+            .map_err(|e| AppError::from(e).into_response())
+    }
+}
+
+/// Reject any request that could change something unless it includes the session's token.
+pub async fn verify(session: Session, request: Request, next: Next) -> Result<Response, AppError> {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        return Ok(next.run(request).await);
+    }
+
+    let Some(expected) = session
+        .get::<String>(SESSION_KEY)
+        .await
+        .map_err(DbError::from)?
+    else {
+        return Err(AppError::InvalidCsrfToken);
+    };
+
+    let header = request
+        .headers()
+        .get(HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let (token, request) = match header {
+        Some(token) => (Some(token), request),
+        None if is_form(&request) => {
+            // Forms submitted without htmx can only include the token in their body, which must
+            // be put back for the handler to read
+            let (parts, body) = request.into_parts();
+            let bytes = body::to_bytes(body, FORM_LIMIT)
+                .await
+                .map_err(|_| AppError::InvalidForm("form is too large"))?;
+
+            let token = url::form_urlencoded::parse(&bytes)
+                .find(|(name, _)| name == FIELD)
+                .map(|(_, value)| value.into_owned());
+
+            (token, Request::from_parts(parts, Body::from(bytes)))
+        }
+        None => (None, request),
+    };
+
+    match token {
+        Some(token) if bool::from(token.as_bytes().ct_eq(expected.as_bytes())) => {
+            Ok(next.run(request).await)
+        }
+        _ => Err(AppError::InvalidCsrfToken),
+    }
+}
+
+fn is_form(request: &Request) -> bool {
+    request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded"))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::{header::COOKIE, header::SET_COOKIE, StatusCode},
+        middleware,
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    use super::*;
+
+    const FORM: &str = "application/x-www-form-urlencoded";
+
+    fn app() -> Router {
+        Router::new()
+            .route("/token", get(|token: CsrfToken| async move { token.0 }))
+            .route(
+                "/",
+                get(|| async { "read" }).post(|body: String| async move { body }),
+            )
+            .layer(middleware::from_fn(verify))
+            .layer(SessionManagerLayer::new(MemoryStore::default()))
+    }
+
+    async fn text(response: Response) -> String {
+        let bytes = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    /// Start a session, producing its cookie and token.
+    async fn start_session(app: &Router) -> (String, String) {
+        let response = app
+            .clone()
+            .oneshot(Request::get("/token").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let cookie = response.headers()[SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+
+        (cookie, text(response).await)
+    }
+
+    fn post(cookie: &str) -> axum::http::request::Builder {
+        Request::post("/").header(COOKIE, cookie)
+    }
+
+    #[tokio::test]
+    async fn accepts_token_in_header_or_form() {
+        let app = app();
+        let (cookie, token) = start_session(&app).await;
+
+        let response = app
+            .clone()
+            .oneshot(
+                post(&cookie)
+                    .header(HEADER, &token)
+                    .body(Body::from("from htmx"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(text(response).await, "from htmx");
+
+        // The handler still receives the whole form
+        let form = format!("amount=10&{FIELD}={token}");
+        let response = app
+            .oneshot(
+                post(&cookie)
+                    .header(CONTENT_TYPE, FORM)
+                    .body(Body::from(form.clone()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(text(response).await, form);
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_or_wrong_token() {
+        let app = app();
+        let (cookie, token) = start_session(&app).await;
+
+        let requests = [
+            post(&cookie).body(Body::empty()),
+            post(&cookie).header(HEADER, "wrong").body(Body::empty()),
+            post(&cookie)
+                .header(CONTENT_TYPE, FORM)
+                .body(Body::from(format!("{FIELD}=wrong"))),
+            // Only forms are searched for the field
+            post(&cookie)
+                .header(CONTENT_TYPE, "text/plain")
+                .body(Body::from(format!("{FIELD}={token}"))),
+            // A session without a token can't match anything
+            Request::post("/").header(HEADER, "").body(Body::empty()),
+        ];
+
+        for request in requests {
+            let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[tokio::test]
+    async fn allows_safe_methods_without_token() {
+        let app = app();
+
+        for method in [Method::GET, Method::HEAD] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri("/")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    }
+}
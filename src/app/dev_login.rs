@@ -0,0 +1,47 @@
+use axum::{extract::State, response::Redirect, routing::get, Form, Router};
+use maud::Markup;
+use serde::Deserialize;
+use tower_sessions::Session;
+
+use crate::Ctx;
+
+use super::{
+    client::ClientInfo,
+    csrf::CsrfToken,
+    error::AppError,
+    login::begin_session,
+    views::{self, page},
+};
+
+/// Identifier for users created with the development login provider.
+const PROVIDER: &str = "dev";
+
+async fn render_dev_login(csrf: CsrfToken) -> Markup {
+    page(Some(&csrf), views::login::render_dev(&csrf))
+}
+
+#[derive(Deserialize)]
+struct DevLoginForm {
+    username: String,
+}
+
+async fn dev_login(
+    State(ctx): State<Ctx>,
+    session: Session,
+    client: ClientInfo,
+    Form(form): Form<DevLoginForm>,
+) -> Result<Redirect, AppError> {
+    let username = form.username.trim();
+    if username.is_empty() {
+        return Ok(Redirect::to("/login/dev"));
+    }
+
+    let user_id = ctx.services.oauth.upsert_user(PROVIDER, username).await?;
+    begin_session(&ctx, &session, user_id, PROVIDER, client).await?;
+
+    Ok(Redirect::to("/"))
+}
+
+pub fn init() -> Router<Ctx> {
+    Router::new().route("/", get(render_dev_login).post(dev_login))
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use axum::{
+    extract::Request,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_htmx::{HxRequest, HxReswap, HxRetarget, SwapOption};
+
+use crate::services::{
+    bet::BetError, oauth::OAuthError, push::PushError, rate_limit::retry_after,
+    weather::WeatherError, webhooks::WebhookError, DbError,
+};
+
+use super::views;
+
+/// Errors that can occur whilst handling a request for the app.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("user doesn't have access to this page")]
+    Forbidden,
+
+    #[error("requested item doesn't exist")]
+    NotFound,
+
+    #[error("request is missing a valid CSRF token")]
+    InvalidCsrfToken,
+
+    #[error("rate limited for {0:?}")]
+    RateLimited(Duration),
+
+    #[error("invalid form submitted: {0}")]
+    InvalidForm(&'static str),
+
+    #[error(transparent)]
+    Bet(BetError),
+
+    #[error(transparent)]
+    Webhook(WebhookError),
+
+    #[error(transparent)]
+    Push(PushError),
+
+    #[error(transparent)]
+    Weather(#[from] WeatherError),
+
+    #[error(transparent)]
+    OAuth(#[from] OAuthError),
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+impl From<BetError> for AppError {
+    fn from(bet_error: BetError) -> Self {
+        // Pull out any underlying service errors
+        match bet_error {
+            BetError::Weather(e) => Self::Weather(e),
+            BetError::Db(e) => Self::Db(e),
+            bet_error => Self::Bet(bet_error),
+        }
+    }
+}
+
+impl From<WebhookError> for AppError {
+    fn from(webhook_error: WebhookError) -> Self {
+        match webhook_error {
+            WebhookError::Db(e) => Self::Db(e),
+            webhook_error => Self::Webhook(webhook_error),
+        }
+    }
+}
+
+impl From<PushError> for AppError {
+    fn from(push_error: PushError) -> Self {
+        match push_error {
+            PushError::Db(e) => Self::Db(e),
+            push_error => Self::Push(push_error),
+        }
+    }
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::Forbidden
+            | AppError::InvalidCsrfToken
+            | AppError::OAuth(OAuthError::Banned) => StatusCode::FORBIDDEN,
+            AppError::Bet(_)
+            | AppError::Webhook(_)
+            | AppError::Push(_)
+            | AppError::InvalidForm(_)
+            | AppError::OAuth(OAuthError::InvalidState) => StatusCode::BAD_REQUEST,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::NotFound | AppError::OAuth(OAuthError::NotConfigured(_)) => {
+                StatusCode::NOT_FOUND
+            }
+            AppError::OAuth(_) => StatusCode::BAD_GATEWAY,
+            AppError::Weather(WeatherError::Db(_)) | AppError::Db(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AppError::Weather(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Message that is safe to show to the user.
+    fn message(&self) -> String {
+        match self {
+            AppError::Forbidden => "you don't have access to this page".to_string(),
+            AppError::NotFound => "couldn't find what you were looking for".to_string(),
+            AppError::InvalidCsrfToken => {
+                "this page has expired, please reload it and try again".to_string()
+            }
+            AppError::RateLimited(wait) => format!(
+                "too many requests, please try again in {} seconds",
+                retry_after(wait)
+            ),
+            AppError::InvalidForm(message) => message.to_string(),
+            AppError::Bet(bet_error) => bet_error.to_string(),
+            AppError::Webhook(webhook_error) => webhook_error.to_string(),
+            AppError::Push(push_error) => push_error.to_string(),
+            AppError::OAuth(OAuthError::InvalidState) => {
+                "login took too long, please try again".to_string()
+            }
+            AppError::OAuth(OAuthError::NotConfigured(_)) => {
+                "that login provider isn't available".to_string()
+            }
+            AppError::OAuth(OAuthError::Banned) => "this account has been banned".to_string(),
+            AppError::OAuth(_) => "couldn't complete login with the provider".to_string(),
+            AppError::Weather(WeatherError::Db(_)) | AppError::Db(_) => {
+                "something went wrong, please try again".to_string()
+            }
+            AppError::Weather(_) => {
+                "couldn't reach the weather service, please try again shortly".to_string()
+            }
+        }
+    }
+}
+
+/// Whole seconds until a rate limited request can be retried.
+/// Error details attached to a response, so that it can be rendered to suit the request.
+#[derive(Clone, Debug)]
+struct ErrorMessage(String);
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+
+        if status.is_server_error() {
+            tracing::error!("error handling request: {self}");
+        }
+
+        let mut response = status.into_response();
+        response
+            .extensions_mut()
+            .insert(ErrorMessage(self.message()));
+
+        if let AppError::RateLimited(wait) = &self {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after(wait)));
+        }
+
+        response
+    }
+}
+
+/// Render any errors from handlers. Requests from htmx receive a fragment that is swapped into the
+/// page, whilst all other requests receive a full error page.
+pub async fn render_errors(
+    HxRequest(hx_request): HxRequest,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+
+    let Some(ErrorMessage(message)) = response.extensions().get::<ErrorMessage>().cloned() else {
+        return response;
+    };
+
+    let mut rendered = if hx_request {
+        (
+            HxRetarget("#error".to_string()),
+            HxReswap(SwapOption::InnerHtml),
+            views::error::render(&message),
+        )
+            .into_response()
+    } else {
+        (
+            response.status(),
+            views::page(None, views::error::render_page(response.status(), &message)),
+        )
+            .into_response()
+    };
+
+    // Let clients know when they can try again after being rate limited
+    if let Some(retry_after) = response.headers().get(RETRY_AFTER) {
+        rendered
+            .headers_mut()
+            .insert(RETRY_AFTER, retry_after.clone());
+    }
+
+    rendered
+}
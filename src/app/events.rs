@@ -0,0 +1,67 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::{Query, State},
+    response::sse::{self, KeepAlive, Sse},
+};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+
+use crate::{services::events::Event, user::UserId, Ctx};
+
+use super::{error::AppError, views};
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// Whether the page shows the payout button in the head.
+    #[serde(default)]
+    show_payout: bool,
+}
+
+/// Render the SSE message that should be sent for an event.
+async fn render(
+    ctx: &Ctx,
+    user_id: UserId,
+    show_payout: bool,
+    event: Event,
+) -> Result<sse::Event, AppError> {
+    Ok(match event {
+        Event::Balance(_) | Event::PayoutsReady => {
+            let balance = ctx.services.bet.get_balance(user_id).await?;
+            let ready_payouts = ctx.services.bet.get_ready(user_id).await?;
+
+            sse::Event::default().event("head").data(
+                views::head::render(format!("${balance:.2}"), ready_payouts.len(), show_payout)
+                    .into_string(),
+            )
+        }
+        Event::Forecast => sse::Event::default().event("forecast").data("refresh"),
+    })
+}
+
+/// Stream updates for the user's open pages, which are swapped in with the htmx SSE extension.
+pub async fn events(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    Query(query): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let stream = ctx
+        .services
+        .events
+        .subscribe(user_id)
+        .filter_map(move |event| {
+            let ctx = ctx.clone();
+
+            async move {
+                match render(&ctx, user_id, query.show_payout, event).await {
+                    Ok(event) => Some(Ok(event)),
+                    Err(e) => {
+                        tracing::error!("failed to render {event:?} event: {e}");
+                        None
+                    }
+                }
+            }
+        });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
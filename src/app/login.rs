@@ -1,5 +1,6 @@
 use axum::{
-    extract::{Query, State},
+    extract::{Path, Query, State},
+    middleware,
     response::Redirect,
     routing::get,
     Router,
@@ -8,22 +9,106 @@ use maud::Markup;
 use serde::Deserialize;
 use tower_sessions::Session;
 
-use crate::Ctx;
+use crate::{
+    config::Config,
+    services::{
+        audit::{AuditEvent, AuditKind},
+        oauth::OAuthError,
+        rate_limit::{Action, RateLimitService},
+        DbError,
+    },
+    user::UserId,
+    Ctx,
+};
+
+use super::{
+    client::ClientInfo,
+    error::AppError,
+    views::{self, login::Provider, page},
+};
+
+/// All providers that have been configured and can be used to log in.
+pub fn providers(config: &Config) -> Vec<Provider> {
+    let mut providers = Vec::new();
+
+    if config.oauth.contains_key("github") {
+        providers.push(Provider {
+            name: "GitHub".to_string(),
+            icon: "github".to_string(),
+            url: "/login/github".to_string(),
+        });
+    }
+
+    #[cfg(feature = "dev-login")]
+    providers.push(Provider {
+        name: "Development".to_string(),
+        icon: "terminal".to_string(),
+        url: "/login/dev".to_string(),
+    });
 
-use super::views::{self, login::Provider, page};
+    providers
+}
 
 async fn render_login(State(ctx): State<Ctx>) -> Markup {
-    page(views::login::render(&[Provider {
-        name: "GitHub".to_string(),
-        icon: "github".to_string(),
-        url: ctx
-            .services
-            .oauth
-            .generate_authorization_url("github")
-            .await
-            .unwrap()
-            .to_string(),
-    }]))
+    page(None, views::login::render(&providers(&ctx.config)))
+}
+
+/// Begin the OAuth flow for a provider. State is only generated once the user has chosen to log
+/// in with the provider.
+async fn authorize(
+    State(ctx): State<Ctx>,
+    Path(provider): Path<String>,
+) -> Result<Redirect, AppError> {
+    let authorization_url = ctx
+        .services
+        .oauth
+        .generate_authorization_url(provider)
+        .await?;
+
+    Ok(Redirect::to(authorization_url.as_str()))
+}
+
+/// Log the user in to the provided session, recording which provider and client they used.
+pub async fn begin_session(
+    ctx: &Ctx,
+    session: &Session,
+    user_id: i64,
+    provider: &str,
+    client: ClientInfo,
+) -> Result<(), DbError> {
+    // Give the session a new ID, so that one planted before logging in can't be used afterwards
+    session.cycle_id().await?;
+
+    // Insert the user ID in the session
+    session.insert("user_id", user_id).await?;
+    session.save().await?;
+
+    // Keep track of the session against the user, so that it can be revoked
+    if let Some(session_id) = session.id() {
+        ctx.services
+            .session
+            .begin(
+                session_id,
+                UserId::from(user_id),
+                client.user_agent.as_deref(),
+            )
+            .await?;
+    }
+
+    ctx.services
+        .audit
+        .record(AuditEvent {
+            ip: client.ip,
+            user_agent: client.user_agent,
+            ..AuditEvent::new(
+                AuditKind::Login,
+                Some(UserId::from(user_id)),
+                format!("logged in with {provider}"),
+            )
+        })
+        .await;
+
+    Ok(())
 }
 
 #[derive(Deserialize)]
@@ -36,29 +121,41 @@ async fn callback_github(
     State(ctx): State<Ctx>,
     Query(params): Query<OAuthCallbackParams>,
     session: Session,
-) -> Redirect {
-    let Some(user_id) = ctx
+    client: ClientInfo,
+) -> Result<Redirect, AppError> {
+    let user_id = match ctx
         .services
         .oauth
         .complete_flow("github", params.state, params.code)
         .await
-    else {
-        // Try again, something went wrong
-        return Redirect::temporary("/login");
+    {
+        Ok(user_id) => user_id,
+        // Try again, the state has likely expired
+        Err(OAuthError::InvalidState) => return Ok(Redirect::temporary("/login")),
+        Err(e) => return Err(e.into()),
     };
 
-    // Insert the user ID in the session
-    session.insert("user_id", user_id).await.unwrap();
-    session.save().await.unwrap();
+    begin_session(&ctx, &session, user_id, "github", client).await?;
 
     // Redirect to the main page
-    Redirect::temporary("/")
+    Ok(Redirect::temporary("/"))
 }
 
-pub fn init() -> Router<Ctx> {
+pub fn init(rate_limit: &RateLimitService) -> Router<Ctx> {
+    // Every step of logging in is limited, as each creates state or calls the provider
+    let attempts = Router::new()
+        .route("/:provider", get(authorize))
+        .route("/callback/github", get(callback_github));
+
+    #[cfg(feature = "dev-login")]
+    let attempts = attempts.nest("/dev", super::dev_login::init());
+
     Router::new()
         .route("/", get(render_login))
-        .route("/callback/github", get(callback_github))
+        .merge(attempts.route_layer(middleware::from_fn_with_state(
+            (rate_limit.clone(), Action::Login),
+            super::rate_limit::enforce,
+        )))
         // TODO: Remove
         .route(
             "/whoami",
@@ -1,101 +1,149 @@
+mod account;
+mod activity;
+mod admin;
+pub mod assets;
+pub mod client;
+mod csrf;
+#[cfg(feature = "dev-login")]
+mod dev_login;
+pub mod error;
+mod events;
 mod login;
+mod rate_limit;
+mod sessions;
+mod settings;
 mod views;
 
-use std::str::FromStr;
-
 use axum::{
     extract::{Path, Query, State},
-    response::Redirect,
+    middleware,
+    response::{IntoResponse, Redirect, Response},
     routing::{get, post},
     Form, Router,
 };
 use axum_htmx::{HxLocation, HxRetarget};
 use chrono::{Duration, NaiveDate, Utc};
 use chrono_tz::Australia::Melbourne;
-use futures::{stream::FuturesUnordered, StreamExt};
+use futures::{stream::FuturesUnordered, TryStreamExt};
 use maud::{html, Markup};
 use serde::Deserialize;
+use tower_sessions::Session;
 
 use crate::{
-    app::views::{bet_form::BetFormVariant, login::Provider},
-    services::bet::{Bet, Payout},
+    app::views::bet_form::BetFormVariant,
+    services::{
+        bet::{Bet, BetError, Payout},
+        rate_limit::{Action, RateLimitService},
+        DbError,
+    },
     user::UserId,
     Ctx, MELBOURNE,
 };
 
-use self::views::{bet_form::BetForm, forecast::ForecastDay};
-
-async fn index(State(ctx): State<Ctx>, user_id: Option<UserId>) -> Markup {
-    let timezone = "Australia/Melbourne";
+use self::{
+    csrf::CsrfToken,
+    error::AppError,
+    views::{bet_form::BetForm, forecast::ForecastDay},
+};
 
+/// Load the forecast for the coming week, along with any bets the user has placed.
+async fn load_forecast(ctx: &Ctx, user_id: Option<UserId>) -> Result<Vec<ForecastDay>, AppError> {
     // Work out what 'today' is in the local timezone
-    let today = Utc::now()
-        .with_timezone(&chrono_tz::Tz::from_str(&timezone).unwrap())
-        .naive_local()
-        .date();
+    let today = Utc::now().with_timezone(&Melbourne).naive_local().date();
     let next_week = today + Duration::weeks(1);
 
-    let forecast = ctx
-        .services
+    ctx.services
         .weather
         .get_forecast(today, next_week)
-        .await
+        .await?
         .into_iter()
         .map(|(date, forecast)| {
             let bet = user_id.map(|user_id| ctx.services.bet.find_bet(user_id, date));
 
             async move {
-                ForecastDay {
+                Ok::<_, AppError>(ForecastDay {
                     date,
                     forecast,
                     user_bet: if let Some(bet) = bet {
-                        Some(bet.await.map(|bet| bet.wager).unwrap_or_default())
+                        Some(bet.await?.map(|bet| bet.wager).unwrap_or_default())
                     } else {
                         None
                     },
-                }
+                })
             }
         })
         .collect::<futures::stream::FuturesOrdered<_>>()
-        .collect::<Vec<_>>()
-        .await;
+        .try_collect::<Vec<_>>()
+        .await
+}
 
-    let (hero, ready_payouts) = if let Some(user_id) = user_id {
+async fn index(
+    State(ctx): State<Ctx>,
+    user_id: Option<UserId>,
+    session: Session,
+) -> Result<Markup, AppError> {
+    let forecast = load_forecast(&ctx, user_id).await?;
+
+    // Visitors that aren't logged in can't change anything, so don't need a session
+    let csrf = match user_id {
+        Some(_) => Some(CsrfToken::from_session(&session).await?),
+        None => None,
+    };
+
+    let (hero, ready_payouts, is_admin) = if let Some(user_id) = user_id {
         let balance = {
-            let balance = ctx.services.bet.get_balance(user_id).await;
+            let balance = ctx.services.bet.get_balance(user_id).await?;
             format!("${balance:.2}")
         };
 
-        let ready_payouts = ctx.services.bet.get_ready(user_id).await.len();
+        let ready_payouts = ctx.services.bet.get_ready(user_id).await?.len();
 
-        (balance, ready_payouts)
+        let is_admin = ctx.services.user.is_admin(user_id).await?;
+
+        (balance, ready_payouts, is_admin)
     } else {
-        ("cloud casino".to_string(), 0)
+        ("cloud casino".to_string(), 0, false)
     };
 
-    views::page(views::shell::render(
-        hero,
-        ready_payouts,
-        true,
-        html! {
-            (views::forecast::render(forecast, None, !user_id.is_some()))
-
-            (views::home::render(
-                (!user_id.is_some()).then_some(
-                    &[Provider {
-                        name: "GitHub".to_string(),
-                        icon: "github".to_string(),
-                        url: ctx
-                            .services
-                            .oauth
-                            .generate_authorization_url("github")
-                            .await
-                            .unwrap()
-                            .to_string(),
-                    }]
-                ),
-            ))
-        },
+    Ok(views::page(
+        csrf.as_ref(),
+        views::shell::render(
+            hero,
+            ready_payouts,
+            true,
+            user_id.is_some(),
+            html! {
+                (views::forecast::render(forecast, None, user_id.is_none()))
+
+                @if user_id.is_some() {
+                    // Refresh the forecast when a new one is available, keeping the selected day
+                    div hidden hx-get="/forecast" hx-trigger="sse:forecast" hx-include="#forecast"
+                        hx-target="#forecast" hx-swap="outerHTML" {}
+                }
+
+                (views::home::render(user_id.is_none().then(|| login::providers(&ctx.config)).as_deref(), is_admin))
+            },
+        ),
+    ))
+}
+
+#[derive(Deserialize)]
+struct ForecastQuery {
+    /// Day that is currently selected, which won't be a date if nothing is selected.
+    date: Option<String>,
+}
+
+async fn forecast(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    Query(query): Query<ForecastQuery>,
+) -> Result<Markup, AppError> {
+    let selected = query.date.and_then(|date| date.parse().ok());
+
+    Ok(views::forecast::render(
+        load_forecast(&ctx, Some(user_id)).await?,
+        selected,
+        false,
     ))
 }
 
@@ -107,26 +155,29 @@ pub struct DateQueryParam {
 async fn get_bet_form(
     State(ctx): State<Ctx>,
     user_id: UserId,
+    csrf: CsrfToken,
     date: Option<Query<DateQueryParam>>,
-) -> Markup {
+) -> Result<Markup, AppError> {
     let Some(date) = date.map(|date| date.0.date) else {
-        return views::home::render(None);
+        let is_admin = ctx.services.user.is_admin(user_id).await?;
+
+        return Ok(views::home::render(None, is_admin));
     };
 
-    let balance = ctx.services.bet.get_balance(user_id).await;
+    let balance = ctx.services.bet.get_balance(user_id).await?;
 
     let forecast = &ctx
         .services
         .weather
         .get_daily_forecast(date, MELBOURNE)
-        .await;
+        .await?;
 
     fn round(n: f64, points: usize) -> f64 {
         let f = 10f64.powi(points as i32);
         (n * f).round() / f
     }
 
-    let bet = ctx.services.bet.find_bet(user_id, date).await;
+    let bet = ctx.services.bet.find_bet(user_id, date).await?;
 
     let existing = bet.is_some();
     let today = Utc::now().with_timezone(&Melbourne).naive_local().date();
@@ -155,9 +206,15 @@ async fn get_bet_form(
         },
     });
 
-    let payout = Payout::max_payout(&bet, date, forecast).total();
+    let payout = Payout::max_payout(&ctx.config.game, &bet, date, forecast).total();
 
-    views::bet_form::render(Some(date), Some(bet.into()), payout, bet_form_variant)
+    Ok(views::bet_form::render(
+        &csrf,
+        Some(date),
+        Some(bet.into()),
+        payout,
+        bet_form_variant,
+    ))
 }
 
 async fn place_bet(
@@ -165,7 +222,7 @@ async fn place_bet(
     user_id: UserId,
     Path(date): Path<NaiveDate>,
     Form(bet_form): Form<BetForm>,
-) -> Result<Redirect, (HxRetarget, String)> {
+) -> Result<Redirect, Response> {
     // Construct the bet
     let bet = Bet {
         temperature: bet_form.temperature,
@@ -179,18 +236,21 @@ async fn place_bet(
         .services
         .weather
         .get_daily_forecast(date, MELBOURNE)
-        .await;
-    let payout = Payout::max_payout(&bet, date, &forecast);
+        .await
+        .map_err(|e| AppError::from(e).into_response())?;
+    let payout = Payout::max_payout(&ctx.config.game, &bet, date, &forecast);
 
     ctx.services
         .bet
         .place(user_id, date, bet, payout)
         .await
-        .map_err(|bet_error| {
-            (
+        .map_err(|bet_error| match bet_error {
+            BetError::Weather(_) | BetError::Db(_) => AppError::from(bet_error).into_response(),
+            bet_error => (
                 HxRetarget("#maximum-payout".to_string()),
                 bet_error.to_string(),
             )
+                .into_response(),
         })?;
 
     Ok(Redirect::to("/"))
@@ -200,66 +260,118 @@ async fn calculate_payout(
     State(ctx): State<Ctx>,
     Path(date): Path<NaiveDate>,
     Form(bet_form): Form<BetForm>,
-) -> Markup {
+) -> Result<Markup, AppError> {
     let forecast = ctx
         .services
         .weather
         .get_daily_forecast(date, MELBOURNE)
-        .await;
-    let payout = Payout::max_payout(&bet_form.into(), date, &forecast);
+        .await?;
+    let payout = Payout::max_payout(&ctx.config.game, &bet_form.into(), date, &forecast);
 
-    views::bet_form::render_maximum_payout(date, payout.total())
+    Ok(views::bet_form::render_maximum_payout(date, payout.total()))
 }
 
-async fn payout(State(ctx): State<Ctx>, user_id: UserId) -> Markup {
-    let balance = ctx.services.bet.get_balance(user_id).await;
-
-    let ready_payouts = ctx.services.bet.get_ready(user_id).await;
-
-    views::page(views::shell::render(
-        format!("${balance:.2}"),
-        ready_payouts.len(),
-        false,
-        views::payouts::render(
-            &ready_payouts
-                .iter()
-                .map(|(date, outcome)| {
-                    let bet = ctx.services.bet.find_bet(user_id, *date);
-                    let weather = ctx.services.weather.get_historical_weather(*date);
-
-                    async move {
-                        let weather = weather.await.unwrap();
-
-                        views::payouts::Payout {
-                            date: date.clone(),
-                            bet: bet.await.unwrap(),
-                            rain: weather.rain,
-                            rain_correct: outcome.rain,
-                            temperature: weather.temperature,
-                            temperature_correct: outcome.temperature,
-                            payout: outcome.payout,
+async fn payout(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    csrf: CsrfToken,
+) -> Result<Markup, AppError> {
+    let balance = ctx.services.bet.get_balance(user_id).await?;
+
+    let ready_payouts = ctx.services.bet.get_ready(user_id).await?;
+
+    Ok(views::page(
+        Some(&csrf),
+        views::shell::render(
+            format!("${balance:.2}"),
+            ready_payouts.len(),
+            false,
+            true,
+            views::payouts::render(
+                &ready_payouts
+                    .iter()
+                    .map(|(date, outcome)| {
+                        let bet = ctx.services.bet.find_bet(user_id, *date);
+                        let weather = ctx.services.weather.get_historical_weather(*date);
+
+                        async move {
+                            let weather = weather.await?;
+
+                            Ok::<_, AppError>(views::payouts::Payout {
+                                date: *date,
+                                bet: bet.await?.ok_or(BetError::NotFound)?,
+                                rain: weather.rain,
+                                rain_correct: outcome.rain,
+                                temperature: weather.temperature,
+                                temperature_correct: outcome.temperature,
+                                payout: outcome.payout,
+                            })
                         }
-                    }
-                })
-                .collect::<FuturesUnordered<_>>()
-                .collect::<Vec<_>>()
-                .await,
+                    })
+                    .collect::<FuturesUnordered<_>>()
+                    .try_collect::<Vec<_>>()
+                    .await?,
+            ),
         ),
     ))
 }
 
-async fn perform_payout(State(ctx): State<Ctx>, user_id: UserId) -> (HxLocation, &'static str) {
-    ctx.services.bet.payout(user_id).await;
+async fn perform_payout(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+) -> Result<(HxLocation, &'static str), AppError> {
+    ctx.services.bet.payout(user_id).await?;
+
+    Ok((HxLocation::from_str("/").unwrap(), "redirecting"))
+}
+
+async fn logout(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    session: Session,
+) -> Result<(HxLocation, &'static str), AppError> {
+    if let Some(session_id) = session.id() {
+        ctx.services
+            .session
+            .revoke(user_id, session_id.to_string())
+            .await?;
+    }
+
+    // Clear out the session, removing the user
+    session.flush().await.map_err(DbError::from)?;
 
-    (HxLocation::from_str("/").unwrap(), "redirecting")
+    Ok((HxLocation::from_str("/").unwrap(), "redirecting"))
 }
 
-pub fn init() -> Router<Ctx> {
+pub fn init(rate_limit: &RateLimitService) -> Router<Ctx> {
     Router::new()
         .route("/", get(index))
+        .route("/forecast", get(forecast))
         .route("/bet", get(get_bet_form))
-        .route("/bet/:date", post(place_bet))
-        .route("/bet/:date/payout", get(calculate_payout))
+        .route(
+            "/bet/:date",
+            post(place_bet).route_layer(middleware::from_fn_with_state(
+                (rate_limit.clone(), Action::Bet),
+                rate_limit::enforce,
+            )),
+        )
+        .route(
+            "/bet/:date/payout",
+            get(calculate_payout).route_layer(middleware::from_fn_with_state(
+                (rate_limit.clone(), Action::Payout),
+                rate_limit::enforce,
+            )),
+        )
         .route("/payout", get(payout).post(perform_payout))
-        .nest("/login", login::init())
+        .route("/logout", post(logout))
+        .route("/events", get(events::events))
+        .nest("/login", login::init(rate_limit))
+        .nest("/sessions", sessions::init())
+        .nest("/activity", activity::init())
+        .nest("/account", account::init())
+        .nest("/settings", settings::init())
+        .nest("/admin", admin::init())
+        .nest("/assets", assets::init())
+        .layer(middleware::from_fn(csrf::verify))
+        .layer(middleware::from_fn(error::render_errors))
 }
@@ -0,0 +1,40 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use tower_sessions::Session;
+
+use crate::{
+    services::rate_limit::{Action, Client, RateLimitService},
+    user::UserId,
+};
+
+use super::{client, error::AppError};
+
+/// Limit how often `action` can be performed, both by the client's IP address and by the user
+/// they're logged in as.
+pub async fn enforce(
+    State((rate_limit, action)): State<(RateLimitService, Action)>,
+    session: Session,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let mut clients = Vec::with_capacity(2);
+    if let Some(ip) = client::client_ip(
+        request.headers(),
+        request.extensions(),
+        rate_limit.trust_forwarded_for(),
+    ) {
+        clients.push(Client::Ip(ip));
+    }
+    if let Some(user_id) = UserId::from_session(&session).await? {
+        clients.push(Client::User(user_id));
+    }
+
+    rate_limit
+        .check(action, &clients)
+        .map_err(AppError::RateLimited)?;
+
+    Ok(next.run(request).await)
+}
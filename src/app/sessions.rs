@@ -0,0 +1,68 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Router,
+};
+use maud::Markup;
+use tower_sessions::Session;
+
+use crate::{user::UserId, Ctx};
+
+use super::{csrf::CsrfToken, error::AppError, views};
+
+async fn render_sessions(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    session: Session,
+    csrf: CsrfToken,
+) -> Result<Markup, AppError> {
+    let balance = ctx.services.bet.get_balance(user_id).await?;
+    let ready_payouts = ctx.services.bet.get_ready(user_id).await?;
+
+    let current = session.id().map(|id| id.to_string());
+    let sessions = ctx
+        .services
+        .session
+        .list(user_id)
+        .await?
+        .into_iter()
+        .map(|record| views::sessions::Session {
+            current: current.as_ref() == Some(&record.id),
+            id: record.id,
+            user_agent: record.user_agent,
+            created: record.created,
+            last_seen: record.last_seen,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(views::page(
+        Some(&csrf),
+        views::shell::render(
+            format!("${balance:.2}"),
+            ready_payouts.len(),
+            true,
+            true,
+            views::sessions::render(&sessions),
+        ),
+    ))
+}
+
+async fn revoke_session(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    if ctx.services.session.revoke(user_id, id).await? {
+        Ok(().into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
+
+pub fn init() -> Router<Ctx> {
+    Router::new()
+        .route("/", get(render_sessions))
+        .route("/:id", delete(revoke_session))
+}
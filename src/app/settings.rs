@@ -0,0 +1,262 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Form, Json, Router,
+};
+use maud::Markup;
+use serde::Deserialize;
+
+use crate::{
+    services::{notifications::NotificationKind, push::NewSubscription},
+    user::UserId,
+    Ctx,
+};
+
+use super::{csrf::CsrfToken, error::AppError, views};
+
+async fn render_settings(
+    ctx: &Ctx,
+    user_id: UserId,
+    csrf: &CsrfToken,
+    created_token: Option<&str>,
+    created_secret: Option<&str>,
+) -> Result<Markup, AppError> {
+    let balance = ctx.services.bet.get_balance(user_id).await?;
+    let ready_payouts = ctx.services.bet.get_ready(user_id).await?;
+
+    let tokens = ctx
+        .services
+        .token
+        .list(user_id)
+        .await?
+        .into_iter()
+        .map(|record| views::settings::Token {
+            id: record.id,
+            name: record.name,
+            prefix: record.prefix,
+            created: record.created,
+            last_used: record.last_used,
+        })
+        .collect::<Vec<_>>();
+
+    let email = ctx
+        .services
+        .user
+        .find(user_id)
+        .await?
+        .and_then(|user| user.email);
+
+    let notifications = ctx
+        .services
+        .notifications
+        .preferences(user_id)
+        .await?
+        .into_iter()
+        .map(|(kind, enabled)| views::settings::Notification {
+            name: kind.name(),
+            description: match kind {
+                NotificationKind::BetSettled => "when a bet has settled",
+                NotificationKind::ForecastDigest => "tomorrow's forecast, each evening",
+                NotificationKind::BetReminder => "a weekly reminder when you have no bets placed",
+            },
+            enabled,
+        })
+        .collect::<Vec<_>>();
+
+    let push = match ctx.services.push.public_key() {
+        Some(public_key) => Some(views::settings::Push {
+            public_key,
+            devices: ctx.services.push.list(user_id).await?.len(),
+        }),
+        None => None,
+    };
+
+    let webhooks = ctx
+        .services
+        .webhooks
+        .list(user_id)
+        .await?
+        .into_iter()
+        .map(|record| views::settings::Webhook {
+            id: record.id,
+            url: record.url,
+            created: record.created,
+        })
+        .collect::<Vec<_>>();
+
+    let deliveries = ctx
+        .services
+        .webhooks
+        .deliveries(user_id)
+        .await?
+        .into_iter()
+        .map(|record| views::settings::Delivery {
+            webhook: record.webhook,
+            event: record.event,
+            attempted: record.attempted,
+            status_code: record.status_code,
+            error: record.error,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(views::page(
+        Some(csrf),
+        views::shell::render(
+            format!("${balance:.2}"),
+            ready_payouts.len(),
+            true,
+            true,
+            views::settings::render(
+                views::settings::render_notifications(csrf, email.as_deref(), push, &notifications),
+                views::settings::render_tokens(csrf, &tokens, created_token),
+                views::settings::render_webhooks(csrf, &webhooks, &deliveries, created_secret),
+                views::settings::render_account(),
+            ),
+        ),
+    ))
+}
+
+async fn settings(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    csrf: CsrfToken,
+) -> Result<Markup, AppError> {
+    render_settings(&ctx, user_id, &csrf, None, None).await
+}
+
+#[derive(Deserialize)]
+struct CreateTokenForm {
+    name: String,
+}
+
+async fn create_token(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    csrf: CsrfToken,
+    Form(form): Form<CreateTokenForm>,
+) -> Result<Markup, AppError> {
+    let token = ctx.services.token.create(user_id, form.name.trim()).await?;
+
+    render_settings(&ctx, user_id, &csrf, Some(&token), None).await
+}
+
+#[derive(Deserialize)]
+struct NotificationsForm {
+    // Unchecked boxes aren't submitted
+    #[serde(default)]
+    bet_settled: bool,
+    #[serde(default)]
+    forecast_digest: bool,
+    #[serde(default)]
+    bet_reminder: bool,
+}
+
+async fn update_notifications(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    csrf: CsrfToken,
+    Form(form): Form<NotificationsForm>,
+) -> Result<Markup, AppError> {
+    ctx.services
+        .notifications
+        .set_preferences(
+            user_id,
+            &[
+                (NotificationKind::BetSettled, form.bet_settled),
+                (NotificationKind::ForecastDigest, form.forecast_digest),
+                (NotificationKind::BetReminder, form.bet_reminder),
+            ],
+        )
+        .await?;
+
+    render_settings(&ctx, user_id, &csrf, None, None).await
+}
+
+async fn revoke_token(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    Path(id): Path<i64>,
+) -> Result<Response, AppError> {
+    if ctx.services.token.revoke(user_id, id).await? {
+        Ok(().into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateWebhookForm {
+    url: String,
+}
+
+async fn create_webhook(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    csrf: CsrfToken,
+    Form(form): Form<CreateWebhookForm>,
+) -> Result<Markup, AppError> {
+    let secret = ctx
+        .services
+        .webhooks
+        .create(user_id, form.url.trim())
+        .await?;
+
+    render_settings(&ctx, user_id, &csrf, None, Some(&secret)).await
+}
+
+async fn remove_webhook(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    Path(id): Path<i64>,
+) -> Result<Response, AppError> {
+    if ctx.services.webhooks.remove(user_id, id).await? {
+        Ok(().into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
+
+async fn subscribe_push(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    Json(subscription): Json<NewSubscription>,
+) -> Result<(), AppError> {
+    ctx.services.push.subscribe(user_id, &subscription).await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct UnsubscribePush {
+    endpoint: String,
+}
+
+async fn unsubscribe_push(
+    State(ctx): State<Ctx>,
+    user_id: UserId,
+    Json(body): Json<UnsubscribePush>,
+) -> Result<Response, AppError> {
+    if ctx
+        .services
+        .push
+        .unsubscribe(user_id, &body.endpoint)
+        .await?
+    {
+        Ok(().into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
+
+pub fn init() -> Router<Ctx> {
+    Router::new()
+        .route("/", get(settings))
+        .route("/notifications", post(update_notifications))
+        .route("/push", post(subscribe_push).delete(unsubscribe_push))
+        .route("/tokens", post(create_token))
+        .route("/tokens/:id", delete(revoke_token))
+        .route("/webhooks", post(create_webhook))
+        .route("/webhooks/:id", delete(remove_webhook))
+}
@@ -0,0 +1,76 @@
+use chrono::NaiveDateTime;
+use maud::{html, Markup};
+
+use super::icons;
+
+pub struct Activity {
+    /// Kind of action, as recorded.
+    pub kind: String,
+
+    /// Description of what happened.
+    pub details: String,
+
+    /// Address of the client that caused the action.
+    pub ip: Option<String>,
+
+    /// User agent of the client that caused the action.
+    pub user_agent: Option<String>,
+
+    /// Whether an admin acted on the user's behalf.
+    pub by_admin: bool,
+
+    /// When the action happened.
+    pub created: NaiveDateTime,
+}
+
+fn format_time(time: &NaiveDateTime) -> String {
+    time.format("%a, %B %e %Y %H:%M").to_string().to_lowercase()
+}
+
+/// Icon for each kind of action.
+fn icon(kind: &str) -> Markup {
+    icons::icon(match kind {
+        "login" => "key-round",
+        "state_rejected" => "circle-x",
+        "bet_placed" | "bet_replaced" => "dollar-sign",
+        "payout" => "badge-dollar-sign",
+        "balance_adjusted" | "bet_voided" => "diff",
+        "session_revoked" => "monitor-smartphone",
+        "account_deleted" => "user",
+        _ => "history",
+    })
+}
+
+pub fn render(activity: &[Activity]) -> Markup {
+    html! {
+        .peek {
+            #activity {
+                @if activity.is_empty() {
+                    p .faded { "nothing has happened yet" }
+                }
+
+                @for event in activity {
+                    .pill {
+                        .details {
+                            (icon(&event.kind))
+                            span { (event.details) }
+                        }
+
+                        .created .faded {
+                            (format_time(&event.created))
+                            @if event.by_admin { " · by an admin" }
+                        }
+
+                        @if event.ip.is_some() || event.user_agent.is_some() {
+                            .client .faded {
+                                (event.ip.as_deref().unwrap_or("unknown address"))
+                                " · "
+                                (event.user_agent.as_deref().unwrap_or("unknown device"))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
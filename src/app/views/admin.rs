@@ -0,0 +1,513 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use maud::{html, Markup};
+
+use crate::app::csrf::CsrfToken;
+
+use super::icons;
+
+pub struct Job {
+    /// Name of the job.
+    pub name: &'static str,
+
+    /// When the job last started running.
+    pub last_started: Option<NaiveDateTime>,
+
+    /// When the last run finished.
+    pub last_finished: Option<NaiveDateTime>,
+
+    /// Whether the last run succeeded.
+    pub succeeded: Option<bool>,
+
+    /// Summary of the last run.
+    pub message: Option<String>,
+
+    /// When the job will next run.
+    pub next_run: Option<NaiveDateTime>,
+}
+
+pub struct User {
+    /// Unique ID of the user.
+    pub id: i64,
+
+    /// Provider the user logs in with.
+    pub provider: String,
+
+    /// Identifier of the user with the provider.
+    pub identifier: String,
+
+    pub balance: f64,
+
+    /// Whether the user has the admin role.
+    pub admin: bool,
+
+    /// Whether the user has been banned.
+    pub banned: bool,
+
+    /// Whether the user has deleted their account.
+    pub deleted: bool,
+}
+
+pub struct Bet {
+    /// Date the bet is for.
+    pub date: NaiveDate,
+
+    /// Amount wagered.
+    pub wager: f64,
+
+    /// Amount paid out, if the bet has been paid out.
+    pub payout: Option<f64>,
+}
+
+pub struct Adjustment {
+    /// Amount added to the balance.
+    pub amount: f64,
+
+    /// Why the balance was adjusted.
+    pub reason: String,
+
+    /// Admin that made the adjustment, if it wasn't made from the CLI.
+    pub admin: Option<i64>,
+
+    /// When the adjustment was made.
+    pub created: NaiveDateTime,
+}
+
+pub struct AuditEntry {
+    /// User the event concerns.
+    pub user: Option<i64>,
+
+    /// Identifier of the user with their provider.
+    pub identifier: Option<String>,
+
+    /// Admin that acted on the user's behalf.
+    pub actor: Option<i64>,
+
+    pub kind: String,
+    pub details: String,
+
+    /// Client that caused the event.
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+
+    /// When the event happened.
+    pub created: NaiveDateTime,
+}
+
+pub struct CachedForecast {
+    /// Date the forecast is for.
+    pub date: NaiveDate,
+
+    /// When the forecast was retrieved.
+    pub retrieved: NaiveDateTime,
+
+    /// Chance of rain.
+    pub rain: f64,
+
+    pub minimum_temperature: f64,
+    pub maximum_temperature: f64,
+}
+
+pub struct Observation {
+    /// Date the weather was observed.
+    pub date: NaiveDate,
+
+    /// When the weather was retrieved.
+    pub retrieved: NaiveDateTime,
+
+    pub rain: bool,
+    pub temperature: f64,
+
+    /// Where the weather came from.
+    pub source: &'static str,
+
+    /// Whether the weather may still be replaced.
+    pub provisional: bool,
+}
+
+fn format_time(time: &NaiveDateTime) -> String {
+    time.format("%a, %B %e %Y %H:%M UTC")
+        .to_string()
+        .to_lowercase()
+}
+
+fn format_date(date: &NaiveDate) -> String {
+    date.format("%a, %B %e %Y").to_string().to_lowercase()
+}
+
+pub fn render(
+    csrf: &CsrfToken,
+    jobs: &[Job],
+    forecasts: &[CachedForecast],
+    observations: &[Observation],
+    voided: Option<(NaiveDate, usize)>,
+) -> Markup {
+    html! {
+        .peek #admin {
+            h2 { "users" }
+
+            form #user-search hx-get="/admin/users" hx-target="#users" hx-trigger="input changed delay:300ms, submit" {
+                label .icon-input {
+                    p .label { "id or identifier" }
+
+                    .pill {
+                        (icons::icon("search"))
+                        input type="search" name="q" required;
+                    }
+                }
+            }
+
+            #users {}
+
+            a href="/admin/audit" hx-boost="true" { "audit log →" }
+
+            h2 { "void bets" }
+
+            p {
+                "Remove every bet for a day, taking back any payouts and refunding the wagers. Use "
+                "this when the weather for the day was wrong."
+            }
+
+            @if let Some((date, count)) = voided {
+                .pill .voided {
+                    "voided " (count) " bets for " (format_date(&date))
+                }
+            }
+
+            form #void-bets action="/admin/bets/void" method="post" hx-boost="true"
+                hx-confirm="Void every bet for this day?"
+            {
+                (csrf.input())
+
+                label .icon-input {
+                    p .label { "date" }
+
+                    .pill {
+                        (icons::icon("calendar"))
+                        input type="date" name="date" required;
+                    }
+                }
+
+                label .icon-input {
+                    p .label { "reason" }
+
+                    .pill {
+                        (icons::icon("message-square"))
+                        input type="text" name="reason" required;
+                    }
+                }
+
+                button type="submit" { "void bets" }
+            }
+
+            h2 { "jobs" }
+
+            #jobs {
+                @for job in jobs {
+                    .pill {
+                        .name {
+                            @match job.succeeded {
+                                Some(true) => (icons::icon("circle-check")),
+                                Some(false) => span .failed { (icons::icon("circle-x")) },
+                                None => (icons::icon("loader")),
+                            }
+
+                            code { (job.name) }
+                        }
+
+                        .last-run .faded {
+                            @if let Some(finished) = &job.last_finished {
+                                "last finished " (format_time(finished))
+                            } @else if let Some(started) = &job.last_started {
+                                "running since " (format_time(started))
+                            } @else {
+                                "never run"
+                            }
+                        }
+
+                        @if let Some(message) = &job.message {
+                            .message .faded { (message) }
+                        }
+
+                        .next-run .faded {
+                            @if let Some(next_run) = &job.next_run {
+                                "next run " (format_time(next_run))
+                            } @else {
+                                "not scheduled"
+                            }
+                        }
+                    }
+                }
+            }
+
+            h2 { "observed weather" }
+
+            table .cache {
+                thead {
+                    tr {
+                        th { "date" }
+                        th { "temperature" }
+                        th { "rain" }
+                        th { "source" }
+                        th { "retrieved" }
+                    }
+                }
+
+                tbody {
+                    @for observation in observations {
+                        tr {
+                            td { (observation.date) }
+                            td { (format!("{:.1}°", observation.temperature)) }
+                            td { @if observation.rain { "yes" } @else { "no" } }
+                            td {
+                                (observation.source)
+                                @if observation.provisional { " (provisional)" }
+                            }
+                            td .faded { (format_time(&observation.retrieved)) }
+                        }
+                    }
+                }
+            }
+
+            h2 { "forecasts" }
+
+            table .cache {
+                thead {
+                    tr {
+                        th { "date" }
+                        th { "temperature" }
+                        th { "rain" }
+                        th { "retrieved" }
+                    }
+                }
+
+                tbody {
+                    @for forecast in forecasts {
+                        tr {
+                            td { (forecast.date) }
+                            td {
+                                (format!(
+                                    "{:.1}° – {:.1}°",
+                                    forecast.minimum_temperature,
+                                    forecast.maximum_temperature
+                                ))
+                            }
+                            td { (format!("{:.0}%", forecast.rain * 100.0)) }
+                            td .faded { (format_time(&forecast.retrieved)) }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Results of a user search, swapped into the admin page.
+pub fn render_users(users: &[User]) -> Markup {
+    html! {
+        @if users.is_empty() {
+            p .faded { "no users found" }
+        }
+
+        @for user in users {
+            a .pill href=(format!("/admin/users/{}", user.id)) hx-boost="true" {
+                .name {
+                    (icons::icon("user"))
+                    span { (user.identifier) }
+                    code .faded { (user.provider) " #" (user.id) }
+                }
+
+                .balance { (format!("${:.2}", user.balance)) }
+
+                @if user.admin {
+                    .tag { "admin" }
+                }
+
+                @if user.banned {
+                    .tag .banned { "banned" }
+                }
+
+                @if user.deleted {
+                    .tag { "deleted" }
+                }
+            }
+        }
+    }
+}
+
+pub fn render_user(
+    csrf: &CsrfToken,
+    user: &User,
+    bets: &[Bet],
+    adjustments: &[Adjustment],
+) -> Markup {
+    html! {
+        .peek #admin {
+            a href="/admin" hx-boost="true" { "← back to admin" }
+
+            h2 { (user.identifier) }
+
+            p .faded {
+                "#" (user.id) " via " (user.provider)
+                @if user.admin { ", admin" }
+                @if user.banned { ", banned" }
+                @if user.deleted { ", deleted" }
+            }
+
+            p { "balance " strong { (format!("${:.2}", user.balance)) } }
+
+            a href=(format!("/admin/audit?user={}", user.id)) hx-boost="true" { "audit log →" }
+
+            form #adjust-balance action=(format!("/admin/users/{}/adjustments", user.id))
+                method="post" hx-boost="true"
+            {
+                (csrf.input())
+
+                label .icon-input {
+                    p .label { "amount" }
+
+                    .pill {
+                        (icons::icon("dollar-sign"))
+                        input type="number" name="amount" step="0.01" required;
+                    }
+                }
+
+                label .icon-input {
+                    p .label { "reason" }
+
+                    .pill {
+                        (icons::icon("message-square"))
+                        input type="text" name="reason" required;
+                    }
+                }
+
+                button type="submit" { "adjust balance" }
+            }
+
+            h2 { "adjustments" }
+
+            @if adjustments.is_empty() {
+                p .faded { "no adjustments" }
+            }
+
+            table .cache {
+                tbody {
+                    @for adjustment in adjustments {
+                        tr {
+                            td { (format!("{:+.2}", adjustment.amount)) }
+                            td { (adjustment.reason) }
+                            td .faded {
+                                @if let Some(admin) = adjustment.admin {
+                                    a href=(format!("/admin/users/{admin}")) { "#" (admin) }
+                                } @else {
+                                    "cli"
+                                }
+                            }
+                            td .faded { (format_time(&adjustment.created)) }
+                        }
+                    }
+                }
+            }
+
+            h2 { "bets" }
+
+            @if bets.is_empty() {
+                p .faded { "no bets" }
+            }
+
+            table .cache {
+                tbody {
+                    @for bet in bets {
+                        tr {
+                            td { (format_date(&bet.date)) }
+                            td { (format!("${:.2}", bet.wager)) }
+                            td .faded {
+                                @if let Some(payout) = bet.payout {
+                                    (format!("paid ${payout:.2}"))
+                                } @else {
+                                    "pending"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Audit log, filtered by the kind of event and the user it concerns.
+pub fn render_audit(
+    kinds: &[&str],
+    kind: Option<&str>,
+    user: Option<i64>,
+    entries: &[AuditEntry],
+) -> Markup {
+    html! {
+        .peek #admin {
+            a href="/admin" hx-boost="true" { "← back to admin" }
+
+            h2 { "audit log" }
+
+            form #audit-filter action="/admin/audit" method="get" hx-boost="true" {
+                label .icon-input {
+                    p .label { "kind" }
+
+                    .pill {
+                        (icons::icon("history"))
+                        select name="kind" {
+                            option value="" { "any" }
+
+                            @for name in kinds {
+                                option value=(name) selected[kind == Some(*name)] { (name) }
+                            }
+                        }
+                    }
+                }
+
+                label .icon-input {
+                    p .label { "user id" }
+
+                    .pill {
+                        (icons::icon("user"))
+                        input type="number" name="user" value=[user];
+                    }
+                }
+
+                button type="submit" { "filter" }
+            }
+
+            @if entries.is_empty() {
+                p .faded { "no events" }
+            }
+
+            table .cache {
+                tbody {
+                    @for entry in entries {
+                        tr {
+                            td .faded { (format_time(&entry.created)) }
+                            td { code { (entry.kind) } }
+                            td {
+                                @if let Some(user) = entry.user {
+                                    a href=(format!("/admin/users/{user}")) {
+                                        (entry.identifier.as_deref().unwrap_or("deleted user"))
+                                        " #" (user)
+                                    }
+                                }
+                            }
+                            td {
+                                (entry.details)
+                                @if let Some(actor) = entry.actor {
+                                    " (by "
+                                    a href=(format!("/admin/users/{actor}")) { "#" (actor) }
+                                    ")"
+                                }
+                            }
+                            td .faded { (entry.ip.as_deref().unwrap_or("")) }
+                            td .faded .user-agent { (entry.user_agent.as_deref().unwrap_or("")) }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -2,7 +2,9 @@ use chrono::NaiveDate;
 use maud::{html, Markup};
 use serde::Deserialize;
 
-use crate::services::bet::Bet;
+use crate::{app::csrf::CsrfToken, services::bet::Bet};
+
+use super::icons;
 
 fn input(
     name: impl AsRef<str>,
@@ -21,7 +23,7 @@ fn input(
             p .label { (label.as_ref()) }
 
             .pill {
-                i data-lucide=(icon.as_ref()) {}
+                (icons::icon(icon.as_ref()))
                 input type="text" inputmode="numeric" pattern=(pattern) name=(name.as_ref()) value=(value.as_ref()) disabled[disabled];
 
                 @if let Some(after) = after {
@@ -42,7 +44,7 @@ fn rain_button(
     html! {
         label {
             input name="rain" value=(value) type="radio" checked[checked] disabled[disabled];
-            i data-lucide=(icon.as_ref()) {}
+            (icons::icon(icon.as_ref()))
             span { (label.as_ref()) }
         }
     }
@@ -115,6 +117,7 @@ pub enum BetFormVariant {
 }
 
 pub fn render(
+    csrf: &CsrfToken,
     date: Option<NaiveDate>,
     value: Option<BetForm>,
     maximum_payout: f64,
@@ -129,11 +132,13 @@ pub fn render(
             hx-boost="true" hx-disabled-elt="#bet-form input, #bet-form button" hx-disinherit="hx-disabled-elt"
             hx-validate="true"
         {
+            (csrf.input())
+
             #rain-guess .pill {
-                @let sun_value = value.as_ref().map(|value| value.rain == false).unwrap_or(false);
+                @let sun_value = value.as_ref().map(|value| !value.rain).unwrap_or(false);
                 (rain_button("sunny", "sun", false, sun_value, disabled))
 
-                @let rain_value = value.as_ref().map(|value| value.rain == true).unwrap_or(false);
+                @let rain_value = value.as_ref().map(|value| value.rain).unwrap_or(false);
                 (rain_button("rainy", "cloud-rain", true, rain_value, disabled))
             }
 
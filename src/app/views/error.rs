@@ -0,0 +1,28 @@
+use axum::http::StatusCode;
+use maud::{html, Markup};
+
+use super::icons;
+
+pub fn render(message: &str) -> Markup {
+    html! {
+        .error-message .pill role="alert" {
+            (icons::icon("cloud-off"))
+
+            p { (message) }
+        }
+    }
+}
+
+pub fn render_page(status: StatusCode, message: &str) -> Markup {
+    html! {
+        #error-page {
+            h4 { "Cloud Casino" }
+
+            h1 { (status.as_u16()) }
+
+            (render(message))
+
+            a .button href="/" { "back home" }
+        }
+    }
+}
@@ -3,8 +3,10 @@ use maud::{html, Markup};
 
 use crate::services::weather::{Forecast, WeatherCode};
 
+use super::icons;
+
 impl WeatherCode {
-    pub fn to_lucide_icon(&self) -> &'static str {
+    pub fn to_lucide_icon(self) -> &'static str {
         match self {
             WeatherCode::Sun => "sun",
             WeatherCode::PartialSun => "cloud-sun",
@@ -39,7 +41,7 @@ pub fn render(days: Vec<ForecastDay>, selected: Option<NaiveDate>, disabled: boo
 
                 @for ForecastDay { date, forecast, user_bet } in days {
                     @let checked = selected.map(|d| d == date).unwrap_or(false);
-                    label .weather-tile {
+                    label .weather-tile .stale[forecast.stale] {
                         input type="radio" name="date" autocomplete="off"
                             value=(date) checked[checked] disabled[disabled];
 
@@ -47,23 +49,29 @@ pub fn render(days: Vec<ForecastDay>, selected: Option<NaiveDate>, disabled: boo
                             (date.format("%a").to_string())
                         }
 
-                        i data-lucide=(forecast.weather_code.to_lucide_icon()) {}
+                        (icons::icon(forecast.weather_code.to_lucide_icon()))
 
                         .line .rain {
                             p { (format!("{:.0}%", forecast.rain * 100.0)) }
-                            i data-lucide="droplets" {}
+                            (icons::icon("droplets"))
                         }
 
                         .line .temperature {
                             p { (format!("{:.0}° / {:.0}°", forecast.minimum_temperature, forecast.maximum_temperature)) }
-                            i data-lucide="thermometer" {}
+                            (icons::icon("thermometer"))
                         }
 
+                        @if forecast.stale {
+                            .line .outdated title="The latest forecast isn't available right now" {
+                                p { "outdated" }
+                                (icons::icon("history"))
+                            }
+                        }
 
                         @if let Some(bet_placed) = user_bet {
                             .line .bet-amount {
                                 p { (format!("${bet_placed:.2}"))}
-                                i data-lucide="badge-dollar-sign" {}
+                                (icons::icon("badge-dollar-sign"))
                             }
                         }
                     }
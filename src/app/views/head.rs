@@ -1,8 +1,10 @@
 use maud::{html, Markup};
 
+use super::icons;
+
 pub fn render(hero: String, payout_count: usize, show_payout: bool) -> Markup {
     html! {
-        #head {
+        #head sse-swap="head" hx-swap="outerHTML" {
             h1 #hero { (hero) }
 
             @if payout_count > 0 && show_payout {
@@ -21,7 +23,7 @@ pub fn render(hero: String, payout_count: usize, show_payout: bool) -> Markup {
                         " ready"
                     }
 
-                    i data-lucide="chevron-right" {}
+                    (icons::icon("chevron-right"))
                 }
             }
         }
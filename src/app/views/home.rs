@@ -1,8 +1,8 @@
 use maud::{html, Markup};
 
-use super::login::Provider;
+use super::{icons, login::Provider};
 
-pub fn render(providers: Option<&[Provider]>) -> Markup {
+pub fn render(providers: Option<&[Provider]>, is_admin: bool) -> Markup {
     html! {
         #home .bet-form-target {
             p { "Bet (fake) money on the weather!" }
@@ -12,7 +12,7 @@ pub fn render(providers: Option<&[Provider]>) -> Markup {
 
                 @for provider in providers {
                     a .button href=(provider.url) {
-                        i data-lucide=(provider.icon) {}
+                        (icons::icon(&provider.icon))
 
                         span { (provider.name) }
                     }
@@ -40,6 +40,30 @@ pub fn render(providers: Option<&[Provider]>) -> Markup {
                 "Payouts will be available from 10am the following day."
             }
 
+            @if providers.is_none() {
+                p .account {
+                    a href="/settings" hx-boost="true" { "settings" }
+
+                    " · "
+
+                    a href="/sessions" hx-boost="true" { "your sessions" }
+
+                    " · "
+
+                    a href="/activity" hx-boost="true" { "activity" }
+
+                    " · "
+
+                    a href="#" hx-post="/logout" { "log out" }
+
+                    @if is_admin {
+                        " · "
+
+                        a href="/admin" hx-boost="true" { "admin" }
+                    }
+                }
+            }
+
             p {
                 "made by "
                 a href="https://ando.gq" target="_blank" { "ando.gq" }
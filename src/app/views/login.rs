@@ -1,5 +1,10 @@
 use maud::{html, Markup};
 
+#[cfg(feature = "dev-login")]
+use crate::app::csrf::CsrfToken;
+
+use super::icons;
+
 pub struct Provider {
     pub icon: String,
     pub name: String,
@@ -17,7 +22,7 @@ pub fn render(providers: &[Provider]) -> Markup {
 
             @for provider in providers {
                 a .button href=(provider.url) {
-                    i data-lucide=(provider.icon){}
+                    (icons::icon(&provider.icon))
 
                     span { (provider.name) }
                 }
@@ -25,3 +30,31 @@ pub fn render(providers: &[Provider]) -> Markup {
         }
     }
 }
+
+#[cfg(feature = "dev-login")]
+pub fn render_dev(csrf: &CsrfToken) -> Markup {
+    html! {
+        #login-container {
+            h4 { "Cloud Casino" }
+
+            h1 { "Development Login" }
+
+            p { "Log in as any user, without authenticating:" }
+
+            form action="/login/dev" method="post" {
+                (csrf.input())
+
+                label .icon-input {
+                    p .label { "username" }
+
+                    .pill {
+                        (icons::icon("user"))
+                        input type="text" name="username" required;
+                    }
+                }
+
+                button type="submit" { "log in" }
+            }
+        }
+    }
+}
@@ -1,40 +1,53 @@
 use maud::{html, Markup};
 
+use super::{assets, csrf::CsrfToken};
+
+pub mod activity;
+pub mod admin;
 pub mod bet_form;
+pub mod error;
 pub mod forecast;
 pub mod head;
 pub mod home;
+pub mod icons;
 pub mod login;
 pub mod payouts;
+pub mod sessions;
+pub mod settings;
 pub mod shell;
 
-pub fn page(body: Markup) -> Markup {
+/// Render a full page. The CSRF token is included with every htmx request from the page, and should
+/// be provided whenever the page has anything that could make a change.
+pub fn page(csrf: Option<&CsrfToken>, body: Markup) -> Markup {
     html! {
         (maud::DOCTYPE)
         html lang="en" {
             head {
-                link rel="stylesheet" type="text/css" href="/app.css";
-                link rel="stylesheet" type="text/css" href="https://unpkg.com/open-props";
-                link rel="stylesheet" type="text/css" href="https://unpkg.com/open-props/normalize.light.min.css";
+                link rel="stylesheet" type="text/css" href=(assets::url("vendor/open-props.min.css"));
+                link rel="stylesheet" type="text/css" href=(assets::url("vendor/normalize.light.min.css"));
+                link rel="stylesheet" type="text/css" href=(assets::url("app.css"));
 
                 link rel="icon mask-icon" href="/favicon.svg";
                 link rel="manifest" href="/app.webmanifest";
                 title { "Cloud Casino" }
 
-                script defer src="//unpkg.com/alpinejs" {}
-                script defer src="//unpkg.com/htmx.org" {}
-                script defer src="//unpkg.com/lucide" {}
+                script defer src=(assets::url("vendor/htmx.min.js")) {}
+                script defer src=(assets::url("vendor/htmx-ext-sse.js")) {}
 
                 meta name="viewport" content="width=device-width, initial-scale=1.0";
                 meta charset="utf-8";
                 meta name="description" content="Bet (fake) money on the weather!";
                 meta name="theme-color" content="#006CCF";
+                // Indicator styles are in `app.css`, as htmx's inline styles are blocked by the CSP
+                meta name="htmx-config" content=r#"{"includeIndicatorStyles":false}"#;
 
-                script defer src="/main.js" {}
+                script defer src=(assets::url("main.js")) {}
             }
 
-            body {
+            body hx-headers=[csrf.map(CsrfToken::hx_headers)] {
                 (body)
+
+                #error {}
             }
         }
     }
@@ -3,6 +3,8 @@ use maud::{html, Markup};
 
 use crate::services::bet::Bet;
 
+use super::icons;
+
 pub struct Payout {
     /// Date this payout is for.
     pub date: NaiveDate,
@@ -28,7 +30,7 @@ pub struct Payout {
 
 fn rain_icon(rain: bool) -> Markup {
     html! {
-        i data-lucide=(if rain { "cloud-rain" } else { "sun"}) {}
+        (icons::icon(if rain { "cloud-rain" } else { "sun"}))
     }
 }
 
@@ -45,17 +47,17 @@ pub fn render(payouts: &[Payout]) -> Markup {
                         .bet-rain { (rain_icon(payout.bet.rain)) }
 
                         .bet-temperature {
-                            i data-lucide="thermometer" {}
+                            (icons::icon("thermometer"))
                             span { (payout.bet.temperature) "°" }
                         }
 
                         .bet-range {
-                            i data-lucide="diff" {}
+                            (icons::icon("diff"))
                             span { (payout.bet.range) "°" }
                         }
 
                         .arrow .faded {
-                            i data-lucide="arrow-right" {}
+                            (icons::icon("arrow-right"))
                         }
 
                         .actual-rain .correct[payout.rain_correct] .incorrect[!payout.rain_correct] {
@@ -63,7 +65,7 @@ pub fn render(payouts: &[Payout]) -> Markup {
                         }
 
                         .actual-temperature .correct[payout.temperature_correct] .incorrect[!payout.temperature_correct] {
-                            i data-lucide="thermometer" {}
+                            (icons::icon("thermometer"))
                             span { (payout.temperature) "°" }
                         }
 
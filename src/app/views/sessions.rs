@@ -0,0 +1,62 @@
+use chrono::NaiveDateTime;
+use maud::{html, Markup};
+
+use super::icons;
+
+pub struct Session {
+    /// Identifier of the session.
+    pub id: String,
+
+    /// User agent the session was last used from.
+    pub user_agent: Option<String>,
+
+    /// When the session was created.
+    pub created: NaiveDateTime,
+
+    /// When the session was last used.
+    pub last_seen: NaiveDateTime,
+
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+fn format_time(time: &NaiveDateTime) -> String {
+    time.format("%a, %B %e %Y %H:%M").to_string().to_lowercase()
+}
+
+pub fn render(sessions: &[Session]) -> Markup {
+    html! {
+        .peek {
+            #sessions {
+                @for session in sessions {
+                    .pill {
+                        .user-agent {
+                            (icons::icon("monitor-smartphone"))
+                            span { (session.user_agent.as_deref().unwrap_or("unknown device")) }
+                        }
+
+                        .last-seen .faded {
+                            "last seen " (format_time(&session.last_seen))
+                        }
+
+                        .created .faded {
+                            "signed in " (format_time(&session.created))
+                        }
+
+                        @if session.current {
+                            button hx-post="/logout" hx-trigger="click" {
+                                "log out"
+                            }
+                        } @else {
+                            button hx-delete=(format!("/sessions/{}", session.id)) hx-trigger="click"
+                                hx-target="closest .pill" hx-swap="outerHTML"
+                            {
+                                "revoke"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use chrono::NaiveDateTime;
+use maud::{html, Markup};
+
+use crate::app::csrf::CsrfToken;
+
+use super::icons;
+
+pub struct Token {
+    /// Unique ID of the token.
+    pub id: i64,
+
+    /// Name given to the token.
+    pub name: String,
+
+    /// Start of the token value.
+    pub prefix: String,
+
+    /// When the token was created.
+    pub created: NaiveDateTime,
+
+    /// Last time the token was used.
+    pub last_used: Option<NaiveDateTime>,
+}
+
+pub struct Notification {
+    /// Name of the form field for the notification.
+    pub name: &'static str,
+
+    /// What the notification is for.
+    pub description: &'static str,
+
+    /// Whether the user receives the notification.
+    pub enabled: bool,
+}
+
+pub struct Webhook {
+    /// Unique ID of the webhook.
+    pub id: i64,
+
+    /// URL that events are posted to.
+    pub url: String,
+
+    /// When the webhook was created.
+    pub created: NaiveDateTime,
+}
+
+pub struct Delivery {
+    /// ID of the webhook that the event was sent to.
+    pub webhook: i64,
+
+    /// Name of the event that was sent.
+    pub event: String,
+
+    /// When delivery was attempted.
+    pub attempted: NaiveDateTime,
+
+    /// Status code that the endpoint responded with.
+    pub status_code: Option<i64>,
+
+    /// Reason that delivery failed.
+    pub error: Option<String>,
+}
+
+fn format_time(time: &NaiveDateTime) -> String {
+    time.format("%a, %B %e %Y").to_string().to_lowercase()
+}
+
+/// Settings page, made up of the rendered sections.
+pub fn render(notifications: Markup, tokens: Markup, webhooks: Markup, account: Markup) -> Markup {
+    html! {
+        .peek #settings {
+            (notifications)
+            (tokens)
+            (webhooks)
+            (account)
+        }
+    }
+}
+
+/// Devices that push notifications can be sent to.
+pub struct Push<'a> {
+    /// Public key that the browser must subscribe with.
+    pub public_key: &'a str,
+
+    /// Number of the user's browsers that are subscribed.
+    pub devices: usize,
+}
+
+pub fn render_notifications(
+    csrf: &CsrfToken,
+    email: Option<&str>,
+    push: Option<Push>,
+    notifications: &[Notification],
+) -> Markup {
+    html! {
+        h2 { "notifications" }
+
+        @if let Some(email) = email {
+            p { "Emails are sent to " strong { (email) } ", as provided when you logged in." }
+        } @else {
+            p .faded {
+                "Your login provider didn't share an email address, so no emails will be sent. "
+                "Make your email public with the provider and log in again to receive them."
+            }
+        }
+
+        @if let Some(push) = push {
+            p {
+                "They can also be pushed to devices that have the app installed, currently "
+                (push.devices) " of yours."
+            }
+
+            button #push-toggle type="button" data-public-key=(push.public_key) hidden {
+                "push to this device"
+            }
+        }
+
+        form #notifications action="/settings/notifications" method="post" hx-boost="true" {
+            (csrf.input())
+
+            @for notification in notifications {
+                label .checkbox {
+                    input type="checkbox" name=(notification.name) value="true"
+                        checked[notification.enabled];
+                    span { (notification.description) }
+                }
+            }
+
+            button type="submit" { "save" }
+        }
+    }
+}
+
+pub fn render_tokens(csrf: &CsrfToken, tokens: &[Token], created_token: Option<&str>) -> Markup {
+    html! {
+        h2 { "api tokens" }
+
+        p {
+            "Personal access tokens allow scripts and bots to use the API at "
+            code { "/api/v1" }
+            ", by providing the token as a bearer token."
+        }
+
+        @if let Some(token) = created_token {
+            .pill .created-token {
+                p { "Copy this token now, it won't be shown again:" }
+                code { (token) }
+            }
+        }
+
+        form #create-token action="/settings/tokens" method="post" hx-boost="true" {
+            (csrf.input())
+
+            label .icon-input {
+                p .label { "token name" }
+
+                .pill {
+                    (icons::icon("key-round"))
+                    input type="text" name="name" required;
+                }
+            }
+
+            button type="submit" { "create token" }
+        }
+
+        #tokens {
+            @for token in tokens {
+                .pill {
+                    .name {
+                        (icons::icon("key-round"))
+                        span { (token.name) }
+                        code .faded { (token.prefix) "…" }
+                    }
+
+                    .created .faded {
+                        "created " (format_time(&token.created))
+                    }
+
+                    .last-used .faded {
+                        @if let Some(last_used) = &token.last_used {
+                            "last used " (format_time(last_used))
+                        } @else {
+                            "never used"
+                        }
+                    }
+
+                    button hx-delete=(format!("/settings/tokens/{}", token.id)) hx-trigger="click"
+                        hx-target="closest .pill" hx-swap="outerHTML"
+                    {
+                        "revoke"
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn render_webhooks(
+    csrf: &CsrfToken,
+    webhooks: &[Webhook],
+    deliveries: &[Delivery],
+    created_secret: Option<&str>,
+) -> Markup {
+    html! {
+        h2 { "webhooks" }
+
+        p {
+            "Webhooks receive a JSON "
+            code { "POST" }
+            " whenever a bet is placed, replaced or settled, or your balance changes. Each "
+            "request is signed in the "
+            code { "x-casino-signature" }
+            " header, with the HMAC-SHA256 of "
+            code { "<x-casino-timestamp>.<body>" }
+            " using the webhook's secret."
+        }
+
+        @if let Some(secret) = created_secret {
+            .pill .created-token {
+                p { "Copy this secret now, it won't be shown again:" }
+                code { (secret) }
+            }
+        }
+
+        form #create-webhook action="/settings/webhooks" method="post" hx-boost="true" {
+            (csrf.input())
+
+            label .icon-input {
+                p .label { "endpoint url" }
+
+                .pill {
+                    (icons::icon("webhook"))
+                    input type="url" name="url" required;
+                }
+            }
+
+            button type="submit" { "add webhook" }
+        }
+
+        #webhooks {
+            @for webhook in webhooks {
+                .pill {
+                    .url {
+                        (icons::icon("webhook"))
+                        code { (webhook.url) }
+                    }
+
+                    .created .faded {
+                        "created " (format_time(&webhook.created))
+                    }
+
+                    button hx-delete=(format!("/settings/webhooks/{}", webhook.id))
+                        hx-trigger="click" hx-target="closest .pill" hx-swap="outerHTML"
+                    {
+                        "remove"
+                    }
+                }
+            }
+        }
+
+        @if !deliveries.is_empty() {
+            h3 { "recent deliveries" }
+
+            table #deliveries {
+                @for delivery in deliveries {
+                    tr {
+                        td { code { (delivery.event) } }
+                        td .faded {
+                            @if let Some(webhook) = webhooks.iter().find(|webhook| webhook.id == delivery.webhook) {
+                                (webhook.url)
+                            }
+                        }
+                        td .faded { (delivery.attempted.format("%d/%m %H:%M:%S")) }
+                        td {
+                            @match (delivery.status_code, &delivery.error) {
+                                (Some(status_code), None) => (status_code),
+                                (Some(status_code), Some(error)) => span title=(error) { (status_code) " failed" },
+                                (None, Some(error)) => span title=(error) { "failed" },
+                                (None, None) => "pending",
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn render_account() -> Markup {
+    html! {
+        h2 { "your data" }
+
+        p {
+            "Download everything kept about you, or just the changes to your balance as a "
+            "spreadsheet."
+        }
+
+        #export {
+            a .pill href="/account/export.json" download {
+                (icons::icon("download"))
+                "everything (json)"
+            }
+
+            a .pill href="/account/export.csv" download {
+                (icons::icon("download"))
+                "balance history (csv)"
+            }
+        }
+
+        h3 { "delete account" }
+
+        p {
+            "Your login, email, tokens, webhooks and devices are removed, and you're signed out "
+            "everywhere. Your bets stay, but can no longer be linked to you."
+        }
+
+        button #delete-account hx-post="/account/delete" hx-trigger="click"
+            hx-confirm="Delete your account? This can't be undone."
+        {
+            "delete account"
+        }
+    }
+}
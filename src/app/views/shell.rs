@@ -2,18 +2,31 @@ use maud::{html, Markup};
 
 use crate::app::views;
 
+/// Render the page shell. When `live` is set, the page subscribes to server-sent events so that
+/// the head stays up to date.
 pub fn render(
     hero: String,
     payout_count: usize,
     show_payout: bool,
+    live: bool,
     draw_content: Markup,
 ) -> Markup {
     html! {
-        main {
-            (views::head::render(hero, payout_count, show_payout))
+        @if live {
+            main hx-ext="sse" sse-connect=(format!("/events?show_payout={show_payout}")) {
+                (views::head::render(hero, payout_count, show_payout))
 
-            #draw {
-                (draw_content)
+                #draw {
+                    (draw_content)
+                }
+            }
+        } @else {
+            main {
+                (views::head::render(hero, payout_count, show_payout))
+
+                #draw {
+                    (draw_content)
+                }
             }
         }
     }
@@ -0,0 +1,650 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Config,
+    db::Database,
+    services::{
+        bet::{BetError, BetHistory},
+        push::VapidKey,
+        user::{UserRecord, ROLES},
+        weather::{Weather, WeatherError, WeatherSource},
+        DbError, Services,
+    },
+    user::UserId,
+};
+
+pub const USAGE: &str = "\
+Usage: cloud-casino [command]
+
+Commands:
+    serve                                  Run the server (default)
+    migrate                                Apply any pending database migrations
+    user list                              List every user
+    user show <id>                         Show a user, along with their bets, sessions and tokens
+    user adjust-balance <id> <amount> --reason <reason>
+                                           Add to (or subtract from) a user's balance
+    user set-role <id> <role>              Give a user the `user` or `admin` role
+    user ban <id>                          Ban a user, signing them out everywhere
+    user delete <id>                       Delete a user's account, anonymising their bets
+    settle --date <date>                   Pay out every bet for a day that has finished
+    weather backfill --from <date> --to <date>
+                                           Save observed weather from the archive
+    weather import <file>                  Save observed weather from a JSON file, or `-` for stdin,
+                                           replacing provisional weather
+    export [--output <file>]               Write all users, bets and observed weather as JSON
+    push generate-key                      Generate a private key for `push.private_key`
+    help                                   Show this message
+
+Dates are formatted as YYYY-MM-DD.";
+
+/// Errors from parsing or running a command.
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("unknown command `{0}`")]
+    UnknownCommand(String),
+
+    #[error("missing argument `{0}`")]
+    MissingArgument(&'static str),
+
+    #[error("missing value for `{0}`")]
+    MissingValue(String),
+
+    #[error("unexpected argument `{0}`")]
+    UnexpectedArgument(String),
+
+    #[error("invalid value `{value}` for `{name}`")]
+    InvalidValue { name: &'static str, value: String },
+
+    #[error("no user with ID {0}")]
+    UserNotFound(UserId),
+
+    #[error("failed to access `{path}`: {source}")]
+    Io { path: PathBuf, source: io::Error },
+
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+
+    #[error(transparent)]
+    Bet(#[from] BetError),
+
+    #[error(transparent)]
+    Weather(#[from] WeatherError),
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Serve,
+    Migrate,
+    UserList,
+    UserShow {
+        user: UserId,
+    },
+    UserAdjustBalance {
+        user: UserId,
+        amount: f64,
+        reason: String,
+    },
+    UserSetRole {
+        user: UserId,
+        role: String,
+    },
+    UserBan {
+        user: UserId,
+    },
+    UserDelete {
+        user: UserId,
+    },
+    Settle {
+        date: NaiveDate,
+    },
+    WeatherBackfill {
+        from: NaiveDate,
+        to: NaiveDate,
+    },
+    WeatherImport {
+        path: PathBuf,
+    },
+    Export {
+        output: Option<PathBuf>,
+    },
+    PushGenerateKey,
+    Help,
+}
+
+impl Command {
+    /// Parse the command from the arguments, excluding the program name. No arguments will run
+    /// the server.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, CliError> {
+        let mut args = Args::new(args)?;
+
+        let command = match args.positional().as_deref() {
+            None | Some("serve") => Self::Serve,
+            Some("migrate") => Self::Migrate,
+            Some("user") => match args.positional().as_deref() {
+                Some("list") => Self::UserList,
+                Some("show") => Self::UserShow {
+                    user: args.required("id")?,
+                },
+                Some("adjust-balance") => Self::UserAdjustBalance {
+                    user: args.required("id")?,
+                    amount: args.required("amount").and_then(|amount: f64| {
+                        // Infinity and NaN parse as numbers, but would corrupt the balance
+                        if amount.is_finite() && amount != 0.0 {
+                            Ok(amount)
+                        } else {
+                            Err(CliError::InvalidValue {
+                                name: "amount",
+                                value: amount.to_string(),
+                            })
+                        }
+                    })?,
+                    reason: args.flag("reason")?,
+                },
+                Some("set-role") => Self::UserSetRole {
+                    user: args.required("id")?,
+                    role: args.required("role").and_then(|role: String| {
+                        if ROLES.contains(&role.as_str()) {
+                            Ok(role)
+                        } else {
+                            Err(CliError::InvalidValue {
+                                name: "role",
+                                value: role,
+                            })
+                        }
+                    })?,
+                },
+                Some("ban") => Self::UserBan {
+                    user: args.required("id")?,
+                },
+                Some("delete") => Self::UserDelete {
+                    user: args.required("id")?,
+                },
+                Some(command) => return Err(CliError::UnknownCommand(format!("user {command}"))),
+                None => return Err(CliError::MissingArgument("user command")),
+            },
+            Some("settle") => Self::Settle {
+                date: args.flag("date")?,
+            },
+            Some("weather") => match args.positional().as_deref() {
+                Some("backfill") => Self::WeatherBackfill {
+                    from: args.flag("from")?,
+                    to: args.flag("to")?,
+                },
+                Some("import") => Self::WeatherImport {
+                    path: args.required("file")?,
+                },
+                Some(command) => {
+                    return Err(CliError::UnknownCommand(format!("weather {command}")))
+                }
+                None => return Err(CliError::MissingArgument("weather command")),
+            },
+            Some("export") => Self::Export {
+                output: args.optional_flag("output")?,
+            },
+            Some("push") => match args.positional().as_deref() {
+                Some("generate-key") => Self::PushGenerateKey,
+                Some(command) => return Err(CliError::UnknownCommand(format!("push {command}"))),
+                None => return Err(CliError::MissingArgument("push command")),
+            },
+            Some("help" | "-h" | "--help") => Self::Help,
+            Some(command) => return Err(CliError::UnknownCommand(command.to_string())),
+        };
+
+        args.finish()?;
+
+        Ok(command)
+    }
+}
+
+/// Arguments split into positional values and `--name value` flags.
+struct Args {
+    positional: std::vec::IntoIter<String>,
+    flags: Vec<(String, String)>,
+}
+
+impl Args {
+    fn new(args: impl IntoIterator<Item = String>) -> Result<Self, CliError> {
+        let mut positional = Vec::new();
+        let mut flags = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                // Allow the help flag without a value
+                Some("help") => positional.push(arg),
+                Some(flag) => match flag.split_once('=') {
+                    Some((name, value)) => flags.push((name.to_string(), value.to_string())),
+                    None => {
+                        let value = args
+                            .next()
+                            .ok_or_else(|| CliError::MissingValue(arg.clone()))?;
+                        flags.push((flag.to_string(), value));
+                    }
+                },
+                None => positional.push(arg),
+            }
+        }
+
+        Ok(Self {
+            positional: positional.into_iter(),
+            flags,
+        })
+    }
+
+    fn positional(&mut self) -> Option<String> {
+        self.positional.next()
+    }
+
+    /// Parse the next positional argument.
+    fn required<T: std::str::FromStr>(&mut self, name: &'static str) -> Result<T, CliError> {
+        let value = self.positional().ok_or(CliError::MissingArgument(name))?;
+
+        parse_value(name, value)
+    }
+
+    /// Parse the flag, if it was provided.
+    fn optional_flag<T: std::str::FromStr>(
+        &mut self,
+        name: &'static str,
+    ) -> Result<Option<T>, CliError> {
+        let Some(i) = self.flags.iter().position(|(flag, _)| flag == name) else {
+            return Ok(None);
+        };
+
+        let (_, value) = self.flags.remove(i);
+        parse_value(name, value).map(Some)
+    }
+
+    fn flag<T: std::str::FromStr>(&mut self, name: &'static str) -> Result<T, CliError> {
+        self.optional_flag(name)?
+            .ok_or(CliError::MissingArgument(name))
+    }
+
+    /// Ensure that every argument was used.
+    fn finish(mut self) -> Result<(), CliError> {
+        if let Some(arg) = self.positional() {
+            return Err(CliError::UnexpectedArgument(arg));
+        }
+
+        if let Some((flag, _)) = self.flags.first() {
+            return Err(CliError::UnexpectedArgument(format!("--{flag}")));
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &'static str, value: String) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError::InvalidValue { name, value })
+}
+
+/// Generate a VAPID key, formatted to be pasted into the `[push]` section of the config.
+pub fn generate_push_key() -> String {
+    let private_key = VapidKey::generate();
+    let public_key = VapidKey::new(&private_key)
+        .expect("generated key is valid")
+        .public_key()
+        .to_string();
+
+    format!("private_key = \"{private_key}\"\n# public key: {public_key}")
+}
+
+/// Identify a session without printing its ID, which would let anyone who sees it use the
+/// session.
+fn session_fingerprint(id: &str) -> String {
+    let mut hash = hex::encode(Sha256::digest(id.as_bytes()));
+    hash.truncate(12);
+
+    hash
+}
+
+/// Observed weather for a day, as it is imported and exported.
+#[derive(Serialize, Deserialize)]
+struct DayWeather {
+    date: NaiveDate,
+    #[serde(flatten)]
+    weather: Weather,
+}
+
+#[derive(Serialize)]
+struct UserExport {
+    #[serde(flatten)]
+    user: UserRecord,
+    bets: Vec<BetHistory>,
+}
+
+#[derive(Serialize)]
+struct Export {
+    users: Vec<UserExport>,
+    historical_weather: Vec<DayWeather>,
+}
+
+/// Run a command against the database, other than `serve` and `help`.
+pub async fn run(command: Command, config: &Config) -> Result<(), CliError> {
+    let database = Database::connect(&config.database.url).await?;
+
+    let result = match command {
+        Command::Migrate => database.migrate().await.map_err(CliError::from),
+        command => {
+            let services = Services::new(&database.repositories(), crate::http_client(), config);
+            run_with_services(command, &services).await
+        }
+    };
+
+    database.close().await;
+
+    result
+}
+
+async fn run_with_services(command: Command, services: &Services) -> Result<(), CliError> {
+    match command {
+        Command::UserList => {
+            println!("id\tprovider\tidentifier\trole\tbalance\tbanned\tlast login");
+            for user in services.user.list().await? {
+                println!(
+                    "{}\t{}\t{}\t{}\t{:.2}\t{}\t{}",
+                    user.id,
+                    user.auth_provider,
+                    user.auth_identifier,
+                    user.role,
+                    user.balance,
+                    user.banned,
+                    user.last_login
+                );
+            }
+        }
+        Command::UserShow { user: id } => {
+            let user = services
+                .user
+                .find(id)
+                .await?
+                .ok_or(CliError::UserNotFound(id))?;
+
+            println!("id:         {}", user.id);
+            println!(
+                "login:      {} {}",
+                user.auth_provider, user.auth_identifier
+            );
+            println!("email:      {}", user.email.as_deref().unwrap_or("-"));
+            println!("role:       {}", user.role);
+            println!("balance:    {:.2}", user.balance);
+            println!("banned:     {}", user.banned);
+            if let Some(deleted) = user.deleted {
+                println!("deleted:    {deleted}");
+            }
+            println!("created:    {}", user.created);
+            println!("last login: {}", user.last_login);
+
+            println!("\nbets:");
+            for bet in services.bet.history(id).await? {
+                let outcome = match bet.outcome {
+                    Some(outcome) => format!("paid {:.2}", outcome.payout),
+                    None => "pending".to_string(),
+                };
+                println!(
+                    "    {}  ${:.2} on {}±{} and {}, {outcome}",
+                    bet.date,
+                    bet.bet.wager,
+                    bet.bet.temperature,
+                    bet.bet.range,
+                    if bet.bet.rain { "rain" } else { "no rain" }
+                );
+            }
+
+            println!("\nbalance adjustments:");
+            for adjustment in services.user.adjustments(id).await? {
+                println!(
+                    "    {}  {:+.2} by {}: {}",
+                    adjustment.created,
+                    adjustment.amount,
+                    adjustment
+                        .admin
+                        .map(|admin| format!("user {admin}"))
+                        .unwrap_or_else(|| "cli".to_string()),
+                    adjustment.reason
+                );
+            }
+
+            println!("\nsessions:");
+            for session in services.session.list(id).await? {
+                println!(
+                    "    {}  last seen {} from {}",
+                    session_fingerprint(&session.id),
+                    session.last_seen,
+                    session.user_agent.as_deref().unwrap_or("unknown")
+                );
+            }
+
+            println!("\ntokens:");
+            for token in services.token.list(id).await? {
+                println!(
+                    "    {}  {} ({}...), last used {}",
+                    token.id,
+                    token.name,
+                    token.prefix,
+                    token
+                        .last_used
+                        .map(|last_used| last_used.to_string())
+                        .unwrap_or_else(|| "never".to_string())
+                );
+            }
+        }
+        Command::UserAdjustBalance {
+            user,
+            amount,
+            reason,
+        } => {
+            let balance = services
+                .user
+                .adjust_balance(user, amount, &reason, None)
+                .await?
+                .ok_or(CliError::UserNotFound(user))?;
+
+            println!("balance of user {user} is now {balance:.2}");
+        }
+        Command::UserSetRole { user, role } => {
+            if !services.user.set_role(user, &role).await? {
+                return Err(CliError::UserNotFound(user));
+            }
+
+            println!("user {user} now has the {role} role");
+        }
+        Command::UserBan { user } => {
+            if !services.user.ban(user).await? {
+                return Err(CliError::UserNotFound(user));
+            }
+
+            println!("banned user {user}");
+        }
+        Command::UserDelete { user } => {
+            if !services.user.delete(user).await? {
+                return Err(CliError::UserNotFound(user));
+            }
+
+            println!("deleted user {user}");
+        }
+        Command::Settle { date } => {
+            let outcomes = services.bet.settle(date).await?;
+
+            for (user, outcome) in &outcomes {
+                println!("user {user}: paid {:.2}", outcome.payout);
+            }
+            println!("settled {} bets for {date}", outcomes.len());
+        }
+        Command::WeatherBackfill { from, to } => {
+            let saved = services
+                .weather
+                .backfill_historical_weather(from, to)
+                .await?;
+
+            println!("saved weather for {saved} days");
+        }
+        Command::WeatherImport { path } => {
+            let weather = read_input(&path)?;
+            let weather = serde_json::from_str::<Vec<DayWeather>>(&weather)?
+                .into_iter()
+                .map(|day| (day.date, day.weather))
+                .collect::<Vec<_>>();
+
+            services.weather.import_historical_weather(&weather).await?;
+
+            // Imported weather replaces provisional weather, such as from the forecast
+            let (finalised, resettled) = services
+                .bet
+                .finalise_weather(&weather, WeatherSource::Import)
+                .await?;
+
+            println!(
+                "imported weather for {} days, replacing provisional weather for {finalised} days \
+                    and resettling {} bets",
+                weather.len(),
+                resettled.len()
+            );
+        }
+        Command::Export { output } => {
+            let mut users = Vec::new();
+            for user in services.user.list().await? {
+                let bets = services.bet.history(UserId::from(user.id)).await?;
+                users.push(UserExport { user, bets });
+            }
+
+            let historical_weather = services
+                .weather
+                .list_historical_weather()
+                .await?
+                .into_iter()
+                .map(|(date, weather)| DayWeather { date, weather })
+                .collect();
+
+            let export = serde_json::to_string_pretty(&Export {
+                users,
+                historical_weather,
+            })?;
+
+            match output {
+                Some(path) => {
+                    fs::write(&path, export).map_err(|source| CliError::Io { path, source })?
+                }
+                None => println!("{export}"),
+            }
+        }
+        // Handled without any services
+        Command::Serve | Command::Migrate | Command::PushGenerateKey | Command::Help => {
+            unreachable!()
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a file, or stdin if the path is `-`.
+fn read_input(path: &Path) -> Result<String, CliError> {
+    let io_error = |source| CliError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    if path == Path::new("-") {
+        io::read_to_string(io::stdin()).map_err(io_error)
+    } else {
+        fs::read_to_string(path).map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Command, CliError> {
+        Command::parse(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(parse(""), Ok(Command::Serve)));
+        assert!(matches!(parse("--help"), Ok(Command::Help)));
+        assert!(matches!(
+            parse("user show 3"),
+            Ok(Command::UserShow { user }) if user == UserId::from(3)
+        ));
+        assert!(matches!(
+            parse("user adjust-balance 3 -2.5 --reason refund"),
+            Ok(Command::UserAdjustBalance { user, amount, reason })
+                if user == UserId::from(3) && amount == -2.5 && reason == "refund"
+        ));
+        assert!(matches!(
+            parse("weather backfill --from=2024-01-01 --to 2024-01-31"),
+            Ok(Command::WeatherBackfill { from, to })
+                if from == NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+                    && to == NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()
+        ));
+        assert!(matches!(
+            parse("export"),
+            Ok(Command::Export { output: None })
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(matches!(
+            parse("users"),
+            Err(CliError::UnknownCommand(command)) if command == "users"
+        ));
+        assert!(matches!(
+            parse("user show"),
+            Err(CliError::MissingArgument("id"))
+        ));
+        assert!(matches!(
+            parse("user show three"),
+            Err(CliError::InvalidValue { name: "id", .. })
+        ));
+        assert!(matches!(
+            parse("user show 3 4"),
+            Err(CliError::UnexpectedArgument(arg)) if arg == "4"
+        ));
+        assert!(matches!(
+            parse("settle --date"),
+            Err(CliError::MissingValue(_))
+        ));
+        assert!(matches!(
+            parse("settle --date 2024-01-01 --force yes"),
+            Err(CliError::UnexpectedArgument(arg)) if arg == "--force"
+        ));
+        assert!(matches!(
+            parse("user set-role 3 owner"),
+            Err(CliError::InvalidValue { name: "role", .. })
+        ));
+        assert!(matches!(
+            parse("user adjust-balance 3 10"),
+            Err(CliError::MissingArgument("reason"))
+        ));
+
+        for amount in ["inf", "-inf", "NaN", "0"] {
+            assert!(matches!(
+                parse(&format!("user adjust-balance 3 {amount} --reason test")),
+                Err(CliError::InvalidValue { name: "amount", .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn hides_session_ids() {
+        let fingerprint = session_fingerprint("secret-session-id");
+
+        assert_eq!(fingerprint.len(), 12);
+        assert!(!fingerprint.contains("secret"));
+        assert_eq!(fingerprint, session_fingerprint("secret-session-id"));
+    }
+}
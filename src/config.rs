@@ -0,0 +1,747 @@
+use std::{
+    collections::HashMap,
+    env,
+    fmt::{self, Display},
+    fs, io,
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use lettre::message::Mailbox;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+use url::Url;
+
+use crate::{db::Backend, services::push::VapidKey};
+
+mod env_vars {
+    /// Path to the config file.
+    pub static CONFIG_FILE: &str = "CONFIG_FILE";
+
+    /// Prefix for overriding any config value, with `__` separating each level. For example,
+    /// `CASINO__GAME__DEFAULT_BALANCE=50`.
+    pub static OVERRIDE_PREFIX: &str = "CASINO__";
+
+    /// Variables that were in use before the config file, and the config value they override.
+    pub static ALIASES: &[(&str, &[&str])] = &[
+        ("DATABASE_URL", &["database", "url"]),
+        ("PORT", &["server", "port"]),
+        ("STATIC_DIR", &["server", "static_dir"]),
+    ];
+
+    /// Prefixes for OAuth provider variables, followed by the name of the provider. For example,
+    /// `OAUTH_CLIENT_ID_GITHUB`.
+    pub static OAUTH_ALIASES: &[(&str, &str)] = &[
+        ("OAUTH_CLIENT_ID_", "client_id"),
+        ("OAUTH_CLIENT_SECRET_", "client_secret"),
+        ("OAUTH_AUTHORIZATION_URL_", "authorization_url"),
+        ("OAUTH_ACCESS_TOKEN_URL_", "access_token_url"),
+    ];
+}
+
+/// Config file that is used if `CONFIG_FILE` isn't set. It's fine for it to not exist.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("unable to read config file `{0}`: {1}")]
+    Read(String, io::Error),
+
+    #[error("unable to parse config: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("invalid config:\n{}", .0.iter().map(|problem| format!("  - {problem}")).collect::<Vec<_>>().join("\n"))]
+    Invalid(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub weather: WeatherConfig,
+    pub game: GameConfig,
+    pub rate_limit: RateLimitConfig,
+    pub webhooks: WebhookConfig,
+
+    /// SMTP server that notification emails are sent through. No emails are sent if it isn't set
+    pub email: Option<EmailConfig>,
+
+    /// Keys that Web Push notifications are signed with. Nothing is pushed if it isn't set
+    pub push: Option<PushConfig>,
+
+    /// Login providers, keyed by their name
+    pub oauth: HashMap<String, ProviderConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Port to listen on, if no addresses are provided in `listen`
+    pub port: u16,
+
+    /// Addresses to listen on, either a socket address (such as `[::]:3000`) or a unix socket
+    /// (such as `unix:/run/cloud-casino.sock`)
+    pub listen: Vec<BindAddress>,
+
+    /// Address to serve Prometheus metrics on, separately from the app so that it isn't exposed
+    /// publicly. Metrics aren't served if it isn't set
+    pub metrics_listen: Option<BindAddress>,
+
+    /// Directory that static files are served from
+    pub static_dir: PathBuf,
+
+    /// How long to wait for in-flight requests and jobs to finish when shutting down
+    pub shutdown_timeout_ms: u64,
+
+    /// Terminate TLS with the provided certificate, rather than serving plain HTTP
+    pub tls: Option<TlsConfig>,
+
+    /// Identify clients by the last address in `X-Forwarded-For`, which is the one appended by the
+    /// reverse proxy. Must only be enabled when behind a reverse proxy that sets it
+    pub trust_forwarded_for: bool,
+}
+
+impl ServerConfig {
+    /// All addresses that should be listened on.
+    pub fn addresses(&self) -> Vec<BindAddress> {
+        if self.listen.is_empty() {
+            vec![BindAddress::Tcp(SocketAddr::from((
+                Ipv4Addr::UNSPECIFIED,
+                self.port,
+            )))]
+        } else {
+            self.listen.clone()
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 3000,
+            listen: Vec::new(),
+            metrics_listen: None,
+            static_dir: PathBuf::from("static"),
+            shutdown_timeout_ms: 30_000,
+            tls: None,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+/// Address that the server can listen on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum BindAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for BindAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+
+        s.parse()
+            .map(Self::Tcp)
+            .map_err(|_| format!("`{s}` must be a socket address or `unix:` followed by a path"))
+    }
+}
+
+impl TryFrom<String> for BindAddress {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindAddress::Tcp(address) => write!(f, "{address}"),
+            BindAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl From<BindAddress> for String {
+    fn from(address: BindAddress) -> Self {
+        address.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file containing the certificate chain
+    pub certificate: PathBuf,
+
+    /// PEM file containing the private key
+    pub private_key: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Connection string for the database
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeatherConfig {
+    /// Maximum time a single request to the weather API may take
+    pub timeout_ms: u64,
+
+    /// Number of times a failed request will be retried
+    pub max_retries: u32,
+
+    /// Delay before the first retry, doubling for each subsequent retry
+    pub backoff_ms: u64,
+
+    /// Number of consecutive failures before requests stop being sent
+    pub breaker_threshold: u32,
+
+    /// How long requests stop being sent for once the breaker has tripped
+    pub breaker_cooldown_ms: u64,
+
+    /// Precipitation (in mm) above which a day is considered to have rained. This gives a small
+    /// buffer incase the rain isn't perceptable.
+    pub rain_threshold: f64,
+
+    /// Open-Meteo forecast and historical weather APIs, which can be pointed at a self-hosted
+    /// instance
+    pub forecast_url: Url,
+    pub archive_url: Url,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 5000,
+            max_retries: 2,
+            backoff_ms: 250,
+            breaker_threshold: 5,
+            breaker_cooldown_ms: 60_000,
+            rain_threshold: 0.05,
+            forecast_url: Url::parse("https://api.open-meteo.com/v1/forecast")
+                .expect("forecast URL is valid"),
+            archive_url: Url::parse("https://archive-api.open-meteo.com/v1/archive")
+                .expect("archive URL is valid"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    /// Balance that new users start with
+    pub default_balance: f64,
+
+    /// Added to the payout multipliers for each day into the future a bet is placed
+    pub day_multiplier: f64,
+
+    /// Base multiplier for a correct rain guess
+    pub rain_multiplier: f64,
+
+    /// Multiplier for a correct temperature guess with the narrowest possible range
+    pub max_temperature_multiplier: f64,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            default_balance: 100.0,
+            day_multiplier: 0.2,
+            rain_multiplier: 0.75,
+            max_temperature_multiplier: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Attempts to log in, including each redirect to a login provider
+    pub login: RateLimit,
+
+    /// Bets placed or replaced
+    pub bet: RateLimit,
+
+    /// Maximum payout calculations, which are made as the bet form is filled in
+    pub payout: RateLimit,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            login: RateLimit {
+                requests: 10,
+                period_ms: 60_000,
+            },
+            bet: RateLimit {
+                requests: 30,
+                period_ms: 60_000,
+            },
+            payout: RateLimit {
+                requests: 120,
+                period_ms: 60_000,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Allow webhooks over plain HTTP and to loopback or private addresses, which must only be
+    /// enabled for local development
+    pub allow_insecure: bool,
+}
+
+/// Limit applied separately to each IP address and each user.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// Requests allowed in a burst, which are gradually allowed again over `period_ms`
+    pub requests: u32,
+
+    /// How long it takes for all of the requests to be allowed again
+    pub period_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    /// Host name of the SMTP server
+    pub host: String,
+
+    /// Port of the SMTP server, if it isn't the standard port for `tls`
+    pub port: Option<u16>,
+
+    /// How the connection to the SMTP server is secured
+    #[serde(default)]
+    pub tls: SmtpTls,
+
+    /// Credentials for the SMTP server, if it requires them
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    /// Address that emails are sent from, such as `Cloud Casino <casino@example.com>`
+    pub from: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Connect with TLS from the start
+    #[default]
+    Tls,
+
+    /// Upgrade a plain connection with `STARTTLS`
+    StartTls,
+
+    /// Send everything in plain text, which should only be used with a local server
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PushConfig {
+    /// VAPID private key, as a base64url encoded P-256 scalar. The public key is derived from it
+    pub private_key: String,
+
+    /// Contact for the push services, either a `mailto:` or `https:` URL
+    pub subject: String,
+
+    /// Allow push endpoints over plain HTTP and on loopback or private addresses, which must only
+    /// be enabled for local development, such as with a stub push service
+    #[serde(default)]
+    pub allow_insecure: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorization_url: Url,
+    pub access_token_url: Url,
+}
+
+impl Config {
+    /// Load the config, starting with the defaults, then applying the config file, and finally any
+    /// environment variable overrides.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut table = Table::try_from(Config::default()).expect("default config is serialisable");
+
+        // Apply the config file
+        let (path, required) = match env::var(env_vars::CONFIG_FILE) {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_FILE.to_string(), false),
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => merge(&mut table, contents.parse::<Table>()?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {}
+            Err(e) => return Err(ConfigError::Read(path, e)),
+        }
+
+        apply_overrides(&mut table, env::vars());
+
+        let mut config = Config::deserialize(table)?;
+
+        // Providers without any credentials are left unconfigured
+        config.oauth.retain(|_, provider| {
+            !provider.client_id.is_empty() || !provider.client_secret.is_empty()
+        });
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Ensure all values are usable, collecting all of the problems.
+    fn validate(&self) -> Result<(), ConfigError> {
+        fn check(problems: &mut Vec<String>, valid: bool, problem: impl Display) {
+            if !valid {
+                problems.push(problem.to_string());
+            }
+        }
+
+        let mut problems = Vec::new();
+
+        check(
+            &mut problems,
+            !self.database.url.is_empty(),
+            "`database.url` must be set (or `DATABASE_URL`)",
+        );
+        check(
+            &mut problems,
+            self.database.url.is_empty() || Backend::from_url(&self.database.url).is_some(),
+            "`database.url` must start with `sqlite:` or `postgres:`",
+        );
+        check(
+            &mut problems,
+            self.server.port != 0,
+            "`server.port` must be between 1 and 65535",
+        );
+        check(
+            &mut problems,
+            self.server.static_dir.is_dir(),
+            format_args!(
+                "`server.static_dir` must be a directory, but `{}` isn't",
+                self.server.static_dir.display()
+            ),
+        );
+
+        if let Some(tls) = &self.server.tls {
+            for (name, path) in [
+                ("certificate", &tls.certificate),
+                ("private_key", &tls.private_key),
+            ] {
+                check(
+                    &mut problems,
+                    path.is_file(),
+                    format_args!(
+                        "`server.tls.{name}` must be a file, but `{}` isn't",
+                        path.display()
+                    ),
+                );
+            }
+        }
+
+        check(
+            &mut problems,
+            self.weather.timeout_ms > 0,
+            "`weather.timeout_ms` must be greater than 0",
+        );
+        check(
+            &mut problems,
+            self.weather.breaker_threshold > 0,
+            "`weather.breaker_threshold` must be greater than 0",
+        );
+        check(
+            &mut problems,
+            self.weather.rain_threshold >= 0.0,
+            "`weather.rain_threshold` must not be negative",
+        );
+
+        for (name, value) in [
+            ("default_balance", self.game.default_balance),
+            ("day_multiplier", self.game.day_multiplier),
+            ("rain_multiplier", self.game.rain_multiplier),
+            (
+                "max_temperature_multiplier",
+                self.game.max_temperature_multiplier,
+            ),
+        ] {
+            check(
+                &mut problems,
+                value.is_finite() && value >= 0.0,
+                format_args!("`game.{name}` must be a positive number"),
+            );
+        }
+
+        for (name, limit) in [
+            ("login", &self.rate_limit.login),
+            ("bet", &self.rate_limit.bet),
+            ("payout", &self.rate_limit.payout),
+        ] {
+            check(
+                &mut problems,
+                limit.requests > 0 && limit.period_ms > 0,
+                format_args!(
+                    "`rate_limit.{name}.requests` and `rate_limit.{name}.period_ms` must be greater than 0"
+                ),
+            );
+        }
+
+        if let Some(email) = &self.email {
+            check(
+                &mut problems,
+                !email.host.is_empty(),
+                "`email.host` must be set",
+            );
+            check(
+                &mut problems,
+                email.from.parse::<Mailbox>().is_ok(),
+                format_args!(
+                    "`email.from` must be an email address, but is `{}`",
+                    email.from
+                ),
+            );
+            check(
+                &mut problems,
+                email.username.is_some() == email.password.is_some(),
+                "`email.username` and `email.password` must be set together",
+            );
+        }
+
+        if let Some(push) = &self.push {
+            check(
+                &mut problems,
+                VapidKey::new(&push.private_key).is_ok(),
+                "`push.private_key` must be a base64url encoded P-256 private key",
+            );
+            check(
+                &mut problems,
+                push.subject.starts_with("mailto:") || push.subject.starts_with("https:"),
+                format_args!(
+                    "`push.subject` must be a `mailto:` or `https:` URL, but is `{}`",
+                    push.subject
+                ),
+            );
+        }
+
+        for (name, provider) in &self.oauth {
+            check(
+                &mut problems,
+                !provider.client_id.is_empty() && !provider.client_secret.is_empty(),
+                format_args!("`oauth.{name}` must have a client ID and secret"),
+            );
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+/// Apply environment variable overrides. Aliases are applied before `CASINO__` variables, so that
+/// the more specific variable wins if both are set, and each group is applied in order of name.
+fn apply_overrides(table: &mut Table, vars: impl IntoIterator<Item = (String, String)>) {
+    let mut overrides = vars
+        .into_iter()
+        .filter_map(|(name, value)| Some((override_path(&name)?, name, value)))
+        .collect::<Vec<_>>();
+    overrides
+        .sort_by_key(|(_, name, _)| (name.starts_with(env_vars::OVERRIDE_PREFIX), name.clone()));
+
+    for (path, _, value) in overrides {
+        set(table, &path, &value);
+    }
+}
+
+/// Determine which config value an environment variable overrides, if any.
+fn override_path(name: &str) -> Option<Vec<String>> {
+    if let Some(path) = name.strip_prefix(env_vars::OVERRIDE_PREFIX) {
+        return Some(path.split("__").map(str::to_lowercase).collect());
+    }
+
+    if let Some((_, path)) = env_vars::ALIASES.iter().find(|(alias, _)| *alias == name) {
+        return Some(path.iter().map(|key| key.to_string()).collect());
+    }
+
+    env_vars::OAUTH_ALIASES
+        .iter()
+        .find_map(|(prefix, key)| Some((name.strip_prefix(prefix)?, key)))
+        .map(|(provider, key)| {
+            vec![
+                "oauth".to_string(),
+                provider.to_lowercase(),
+                key.to_string(),
+            ]
+        })
+}
+
+/// Recursively merge `other` into `table`.
+fn merge(table: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(value)) => merge(existing, value),
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Set the value at `path`, creating any tables along the way. The value is parsed as TOML unless
+/// it is replacing a string, so that values such as ports can be provided. Values that aren't
+/// otherwise set, such as in optional sections, are parsed as a TOML number or boolean if they can
+/// be, so a string that looks like one must be quoted.
+fn set(table: &mut Table, path: &[String], raw: &str) {
+    let Some((key, parents)) = path.split_last() else {
+        return;
+    };
+
+    let mut table = table;
+    for parent in parents {
+        let entry = table
+            .entry(parent.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+
+        table = entry.as_table_mut().expect("entry was just made a table");
+    }
+
+    let parsed = format!("value = {raw}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut parsed| parsed.remove("value"));
+
+    let value = match (table.get(key), parsed) {
+        (Some(Value::String(_)), _) => Value::String(raw.to_string()),
+        (Some(_), Some(value)) => value,
+        (None, Some(value)) if !value.is_table() && !value.is_array() => value,
+        _ => Value::String(raw.to_string()),
+    };
+
+    table.insert(key.clone(), value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(keys: &[&str]) -> Option<Vec<String>> {
+        Some(keys.iter().map(|key| key.to_string()).collect())
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn default_table() -> Table {
+        Table::try_from(Config::default()).unwrap()
+    }
+
+    #[test]
+    fn maps_variables_to_paths() {
+        assert_eq!(
+            override_path("CASINO__GAME__DEFAULT_BALANCE"),
+            path(&["game", "default_balance"])
+        );
+        assert_eq!(override_path("DATABASE_URL"), path(&["database", "url"]));
+        assert_eq!(
+            override_path("OAUTH_CLIENT_SECRET_GITHUB"),
+            path(&["oauth", "github", "client_secret"])
+        );
+        assert_eq!(override_path("HOME"), None);
+    }
+
+    #[test]
+    fn merges_nested_tables() {
+        let mut table = default_table();
+        merge(
+            &mut table,
+            "[server]\nport = 8080\n[database]\nurl = \"sqlite://casino.sqlite\""
+                .parse()
+                .unwrap(),
+        );
+
+        let config = Config::deserialize(table).unwrap();
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.server.static_dir, ServerConfig::default().static_dir);
+        assert_eq!(config.database.url, "sqlite://casino.sqlite");
+    }
+
+    #[test]
+    fn sets_values_by_type() {
+        let mut table = default_table();
+        for (keys, raw) in [
+            (&["server", "port"][..], "8080"),
+            (&["database", "url"], "8080"),
+            (&["email", "port"], "587"),
+            (&["email", "host"], "smtp.example.com"),
+            (&["email", "password"], "\"123456\""),
+            (&["email", "username"], "[user]"),
+        ] {
+            set(&mut table, path(keys).as_deref().unwrap(), raw);
+        }
+
+        assert_eq!(table["server"]["port"], Value::Integer(8080));
+        assert_eq!(table["database"]["url"], Value::from("8080"));
+        assert_eq!(table["email"]["port"], Value::Integer(587));
+        assert_eq!(table["email"]["host"], Value::from("smtp.example.com"));
+        assert_eq!(table["email"]["password"], Value::from("123456"));
+        assert_eq!(table["email"]["username"], Value::from("[user]"));
+    }
+
+    #[test]
+    fn applies_prefixed_overrides_after_aliases() {
+        let mut table = default_table();
+        apply_overrides(
+            &mut table,
+            vars(&[
+                ("CASINO__DATABASE__URL", "postgres://prefixed"),
+                ("DATABASE_URL", "sqlite://alias"),
+                ("PORT", "8080"),
+            ]),
+        );
+
+        assert_eq!(table["database"]["url"], Value::from("postgres://prefixed"));
+        assert_eq!(table["server"]["port"], Value::Integer(8080));
+    }
+
+    #[test]
+    fn collects_every_problem() {
+        let mut config = Config::default();
+        config.database.url = "sqlite://casino.sqlite".to_string();
+        assert!(config.validate().is_ok());
+
+        config.database.url = "mysql://casino".to_string();
+        config.server.port = 0;
+        config.game.rain_multiplier = f64::NAN;
+        config.rate_limit.bet.requests = 0;
+
+        let Err(ConfigError::Invalid(problems)) = config.validate() else {
+            panic!("config should be invalid");
+        };
+        assert_eq!(problems.len(), 4, "{problems:?}");
+        assert!(problems[0].contains("`database.url`"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Connections to the supported databases, and the repositories that the services persist their
+//! data with. Which database is used is determined by the scheme of `DATABASE_URL`.
+
+mod postgres;
+mod sqlite;
+#[cfg(test)]
+mod tests;
+
+use std::{str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use sqlx::{sqlite::SqliteConnectOptions, PgPool, SqlitePool};
+use tower_sessions::{
+    session::{Id, Record},
+    session_store, ExpiredDeletion, SessionStore,
+};
+use tower_sessions_sqlx_store::{PostgresStore, SqliteStore};
+use url::Url;
+
+use crate::services::{
+    audit::AuditRepository, bet::BetRepository, jobs::JobRepository,
+    notifications::NotificationRepository, push::PushRepository, session::SessionRepository,
+    state::StateRepository, token::TokenRepository, user::UserRepository,
+    weather::WeatherRepository, webhooks::WebhookRepository, DbError,
+};
+
+/// Kind of database, as determined by the scheme of its URL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+impl Backend {
+    pub fn from_url(url: &str) -> Option<Self> {
+        match url.split_once(':')?.0 {
+            "sqlite" => Some(Self::Sqlite),
+            "postgres" | "postgresql" => Some(Self::Postgres),
+            _ => None,
+        }
+    }
+}
+
+/// `url` with any password replaced, so that it can be logged.
+pub fn redact_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some("redacted"));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Database {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
+}
+
+impl Database {
+    /// Connect to the database at `url`, creating it if it's a SQLite database that doesn't exist.
+    pub async fn connect(url: &str) -> Result<Self, DbError> {
+        match Backend::from_url(url) {
+            Some(Backend::Sqlite) => Ok(Self::Sqlite(
+                SqlitePool::connect_with(
+                    SqliteConnectOptions::from_str(url)?.create_if_missing(true),
+                )
+                .await?,
+            )),
+            Some(Backend::Postgres) => Ok(Self::Postgres(PgPool::connect(url).await?)),
+            None => Err(DbError::UnsupportedDatabase(
+                url.split_once(':')
+                    .map(|(scheme, _)| scheme)
+                    .unwrap_or(url)
+                    .to_string(),
+            )),
+        }
+    }
+
+    /// Run any migrations that haven't been applied yet, including those for the session store.
+    pub async fn migrate(&self) -> Result<(), DbError> {
+        match self {
+            Database::Sqlite(pool) => {
+                sqlx::migrate!("migrations/sqlite").run(pool).await?;
+                SqliteStore::new(pool.clone()).migrate().await?;
+            }
+            Database::Postgres(pool) => {
+                sqlx::migrate!("migrations/postgres").run(pool).await?;
+                PostgresStore::new(pool.clone()).migrate().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the database can be reached.
+    pub async fn ping(&self) -> Result<(), DbError> {
+        match self {
+            Database::Sqlite(pool) => {
+                sqlx::query("SELECT 1;").execute(pool).await?;
+            }
+            Database::Postgres(pool) => {
+                sqlx::query("SELECT 1;").execute(pool).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn close(&self) {
+        match self {
+            Database::Sqlite(pool) => pool.close().await,
+            Database::Postgres(pool) => pool.close().await,
+        }
+    }
+
+    /// Build the repositories for this database.
+    pub fn repositories(&self) -> Repositories {
+        match self {
+            Database::Sqlite(pool) => sqlite::repositories(pool),
+            Database::Postgres(pool) => postgres::repositories(pool),
+        }
+    }
+}
+
+/// Repositories for every service, all backed by the same database.
+#[derive(Clone)]
+pub struct Repositories {
+    pub bets: Arc<dyn BetRepository>,
+    pub weather: Arc<dyn WeatherRepository>,
+    pub states: Arc<dyn StateRepository>,
+    pub sessions: Arc<dyn SessionRepository>,
+    pub session_store: AnySessionStore,
+    pub tokens: Arc<dyn TokenRepository>,
+    pub users: Arc<dyn UserRepository>,
+    pub jobs: Arc<dyn JobRepository>,
+    pub notifications: Arc<dyn NotificationRepository>,
+    pub webhooks: Arc<dyn WebhookRepository>,
+    pub push: Arc<dyn PushRepository>,
+    pub audit: Arc<dyn AuditRepository>,
+}
+
+/// Session store for whichever database is in use.
+#[derive(Clone, Debug)]
+pub enum AnySessionStore {
+    Sqlite(SqliteStore),
+    Postgres(PostgresStore),
+}
+
+#[async_trait]
+impl SessionStore for AnySessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        match self {
+            AnySessionStore::Sqlite(store) => store.create(record).await,
+            AnySessionStore::Postgres(store) => store.create(record).await,
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        match self {
+            AnySessionStore::Sqlite(store) => store.save(record).await,
+            AnySessionStore::Postgres(store) => store.save(record).await,
+        }
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        match self {
+            AnySessionStore::Sqlite(store) => store.load(session_id).await,
+            AnySessionStore::Postgres(store) => store.load(session_id).await,
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        match self {
+            AnySessionStore::Sqlite(store) => store.delete(session_id).await,
+            AnySessionStore::Postgres(store) => store.delete(session_id).await,
+        }
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for AnySessionStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        match self {
+            AnySessionStore::Sqlite(store) => store.delete_expired().await,
+            AnySessionStore::Postgres(store) => store.delete_expired().await,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::services::{
+    audit::{AuditEvent, AuditFilter, AuditRecord, AuditRepository},
+    DbError,
+};
+
+#[derive(Clone)]
+pub struct PostgresAuditRepository {
+    pool: PgPool,
+}
+
+impl PostgresAuditRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditRepository for PostgresAuditRepository {
+    async fn insert(&self, event: &AuditEvent) -> Result<(), DbError> {
+        sqlx::query(
+            r#"INSERT INTO audit_events ("user", actor, kind, details, ip, user_agent, created)
+                VALUES ($1, $2, $3, $4, $5, $6, NOW() AT TIME ZONE 'UTC');"#,
+        )
+        .bind(event.user)
+        .bind(event.actor)
+        .bind(event.kind.name())
+        .bind(&event.details)
+        .bind(event.ip.map(|ip| ip.to_string()))
+        .bind(&event.user_agent)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, filter: &AuditFilter, limit: i64) -> Result<Vec<AuditRecord>, DbError> {
+        Ok(sqlx::query_as::<_, AuditRecord>(
+            r#"SELECT audit_events.id, "user", auth_identifier AS identifier, actor, kind, details,
+                    ip, user_agent, audit_events.created
+                FROM audit_events
+                LEFT JOIN users ON users.id = audit_events."user"
+                WHERE ($1::BIGINT IS NULL OR "user" = $1) AND ($2::TEXT IS NULL OR kind = $2)
+                ORDER BY audit_events.created DESC, audit_events.id DESC
+                LIMIT $3;"#,
+        )
+        .bind(filter.user)
+        .bind(filter.kind.map(|kind| kind.name()))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
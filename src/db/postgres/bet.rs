@@ -0,0 +1,489 @@
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use sqlx::PgPool;
+
+use crate::{
+    services::{
+        bet::{BetOutcome, BetRecord, BetRepository},
+        weather::{Weather, WeatherSource},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct PostgresBetRepository {
+    pool: PgPool,
+}
+
+impl PostgresBetRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl BetRepository for PostgresBetRepository {
+    async fn upsert_bet(&self, user: UserId, bet: &BetRecord) -> Result<bool, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Lock the user, so that another replica can't change the balance or bet part way through
+        sqlx::query("SELECT id FROM users WHERE id = $1 FOR UPDATE;")
+            .bind(user)
+            .execute(tx.as_mut())
+            .await?;
+
+        // Get the current wager
+        let previous_wager = sqlx::query_scalar::<_, f64>(
+            r#"SELECT wager
+                FROM bets
+                WHERE "user" = $1 AND date = $2;"#,
+        )
+        .bind(user)
+        .bind(bet.date)
+        .fetch_optional(tx.as_mut())
+        .await?
+        .unwrap_or_default();
+
+        // Insert the new bet
+        sqlx::query(
+            r#"INSERT INTO bets ("user", date, temperature, range, rain, wager, rain_payout, temperature_payout)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT ("user", date) DO UPDATE
+                    SET temperature = $3, range = $4, rain = $5, wager = $6, rain_payout = $7,
+                        temperature_payout = $8;"#,
+        )
+        .bind(user)
+        .bind(bet.date)
+        .bind(bet.temperature)
+        .bind(bet.range)
+        .bind(bet.rain)
+        .bind(bet.wager)
+        .bind(bet.rain_payout)
+        .bind(bet.temperature_payout)
+        .execute(tx.as_mut())
+        .await?;
+
+        // Update the user's balance
+        let balance = sqlx::query_scalar::<_, f64>(
+            "UPDATE users SET balance = balance + $1 WHERE id = $2 RETURNING balance;",
+        )
+        .bind(previous_wager - bet.wager)
+        .bind(user)
+        .fetch_one(tx.as_mut())
+        .await?;
+
+        if balance >= 0.0 {
+            tx.commit().await?;
+
+            Ok(true)
+        } else {
+            tx.rollback().await?;
+
+            Ok(false)
+        }
+    }
+
+    async fn delete_bet(
+        &self,
+        user: UserId,
+        date: NaiveDate,
+    ) -> Result<Option<BetRecord>, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Remove the bet, as long as it hasn't been paid out
+        let bet = sqlx::query_as::<_, BetRecord>(
+            r#"DELETE FROM bets
+                WHERE "user" = $1
+                    AND date = $2
+                    AND NOT EXISTS (
+                        SELECT 1
+                            FROM payouts
+                            WHERE payouts.date = bets.date
+                                AND payouts."user" = bets."user"
+                    )
+                RETURNING date, temperature, range, rain, wager, rain_payout, temperature_payout;"#,
+        )
+        .bind(user)
+        .bind(date)
+        .fetch_optional(tx.as_mut())
+        .await?;
+
+        let Some(bet) = bet else {
+            return Ok(None);
+        };
+
+        // Refund the wager
+        sqlx::query("UPDATE users SET balance = balance + $1 WHERE id = $2;")
+            .bind(bet.wager)
+            .bind(user)
+            .execute(tx.as_mut())
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(bet))
+    }
+
+    async fn find_bet(&self, user: UserId, date: NaiveDate) -> Result<Option<BetRecord>, DbError> {
+        Ok(sqlx::query_as::<_, BetRecord>(
+            r#"SELECT date, temperature, range, rain, wager, rain_payout, temperature_payout
+                FROM bets
+                WHERE "user" = $1 AND date = $2;"#,
+        )
+        .bind(user)
+        .bind(date)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn record_payout(
+        &self,
+        user: UserId,
+        date: NaiveDate,
+        outcome: &BetOutcome,
+    ) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Record the payout, which fails if another replica has already paid it out
+        sqlx::query(
+            r#"INSERT INTO payouts ("user", date, rain_correct, temperature_correct)
+                VALUES ($1, $2, $3, $4);"#,
+        )
+        .bind(user)
+        .bind(date)
+        .bind(outcome.rain)
+        .bind(outcome.temperature)
+        .execute(tx.as_mut())
+        .await?;
+
+        // Add the payout for each correct part of the bet
+        sqlx::query(
+            r#"UPDATE users
+                SET balance = balance + COALESCE((
+                    SELECT CASE WHEN $1 THEN temperature_payout ELSE 0 END
+                            + CASE WHEN $2 THEN rain_payout ELSE 0 END
+                        FROM bets
+                        WHERE "user" = $3 AND date = $4
+                ), 0)
+                WHERE id = $3;"#,
+        )
+        .bind(outcome.temperature)
+        .bind(outcome.rain)
+        .bind(user)
+        .bind(date)
+        .execute(tx.as_mut())
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn ready_bets(&self, user: UserId) -> Result<Vec<BetRecord>, DbError> {
+        // WARN: This will mix UTC dates with user locale dates
+        let now = Utc::now().date_naive();
+
+        Ok(sqlx::query_as::<_, BetRecord>(
+            r#"SELECT date, temperature, range, rain, wager, rain_payout, temperature_payout
+                FROM bets
+                WHERE "user" = $1
+                    AND date < $2
+                    AND NOT EXISTS (
+                        SELECT 1
+                            FROM payouts
+                            WHERE payouts.date = bets.date
+                                AND payouts."user" = bets."user"
+                    );"#,
+        )
+        .bind(user)
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn history(
+        &self,
+        user: UserId,
+    ) -> Result<Vec<(BetRecord, Option<(bool, bool)>)>, DbError> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            #[sqlx(flatten)]
+            bet: BetRecord,
+            rain_correct: Option<bool>,
+            temperature_correct: Option<bool>,
+        }
+
+        Ok(sqlx::query_as::<_, Row>(
+            r#"SELECT bets.date, temperature, range, rain, wager, rain_payout, temperature_payout,
+                    payouts.rain_correct, payouts.temperature_correct
+                FROM bets
+                LEFT JOIN payouts
+                    ON payouts.date = bets.date AND payouts."user" = bets."user"
+                WHERE bets."user" = $1
+                ORDER BY bets.date DESC;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.bet, row.rain_correct.zip(row.temperature_correct)))
+        .collect())
+    }
+
+    async fn void_bets(
+        &self,
+        date: NaiveDate,
+        reason: &str,
+        admin: Option<UserId>,
+    ) -> Result<Vec<(UserId, f64)>, DbError> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            user: UserId,
+            #[sqlx(flatten)]
+            bet: BetRecord,
+            rain_correct: Option<bool>,
+            temperature_correct: Option<bool>,
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        // Lock the bets so they can't be paid out whilst being voided
+        let bets = sqlx::query_as::<_, Row>(
+            r#"SELECT bets."user", bets.date, temperature, range, rain, wager, rain_payout,
+                    temperature_payout, payouts.rain_correct, payouts.temperature_correct
+                FROM bets
+                LEFT JOIN payouts
+                    ON payouts.date = bets.date AND payouts."user" = bets."user"
+                WHERE bets.date = $1
+                ORDER BY bets."user"
+                FOR UPDATE OF bets;"#,
+        )
+        .bind(date)
+        .fetch_all(tx.as_mut())
+        .await?;
+
+        let mut refunds = Vec::with_capacity(bets.len());
+        for row in bets {
+            // Take back anything that was paid out, and return the wager
+            let payout = row
+                .rain_correct
+                .zip(row.temperature_correct)
+                .map(|(rain, temperature)| row.bet.settled_outcome(rain, temperature).payout)
+                .unwrap_or_default();
+            let refund = row.bet.wager - payout;
+
+            sqlx::query(r#"DELETE FROM payouts WHERE "user" = $1 AND date = $2;"#)
+                .bind(row.user)
+                .bind(date)
+                .execute(tx.as_mut())
+                .await?;
+
+            sqlx::query(r#"DELETE FROM bets WHERE "user" = $1 AND date = $2;"#)
+                .bind(row.user)
+                .bind(date)
+                .execute(tx.as_mut())
+                .await?;
+
+            sqlx::query("UPDATE users SET balance = balance + $1 WHERE id = $2;")
+                .bind(refund)
+                .bind(row.user)
+                .execute(tx.as_mut())
+                .await?;
+
+            sqlx::query(
+                r#"INSERT INTO balance_adjustments ("user", amount, reason, admin, created)
+                    VALUES ($1, $2, $3, $4, NOW() AT TIME ZONE 'UTC');"#,
+            )
+            .bind(row.user)
+            .bind(refund)
+            .bind(reason)
+            .bind(admin)
+            .execute(tx.as_mut())
+            .await?;
+
+            refunds.push((row.user, refund));
+        }
+
+        tx.commit().await?;
+
+        Ok(refunds)
+    }
+
+    async fn resettle(
+        &self,
+        date: NaiveDate,
+        weather: &Weather,
+        source: WeatherSource,
+    ) -> Result<Option<Vec<(UserId, f64)>>, DbError> {
+        #[derive(sqlx::FromRow)]
+        struct Previous {
+            temperature: f64,
+            rain: bool,
+            source: WeatherSource,
+        }
+
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            user: UserId,
+            #[sqlx(flatten)]
+            bet: BetRecord,
+            rain_correct: bool,
+            temperature_correct: bool,
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        // Lock the weather so the day is only resettled once
+        let Some(previous) = sqlx::query_as::<_, Previous>(
+            "SELECT temperature, rain, source
+                FROM historical_weather
+                WHERE date = $1 AND provisional
+                FOR UPDATE;",
+        )
+        .bind(date)
+        .fetch_optional(tx.as_mut())
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            "UPDATE historical_weather
+                SET temperature = $1, rain = $2, source = $3, provisional = $4,
+                    date_retrieved = NOW() AT TIME ZONE 'UTC'
+                WHERE date = $5;",
+        )
+        .bind(weather.temperature)
+        .bind(weather.rain)
+        .bind(source)
+        .bind(source.is_provisional())
+        .bind(date)
+        .execute(tx.as_mut())
+        .await?;
+
+        if previous.temperature == weather.temperature && previous.rain == weather.rain {
+            tx.commit().await?;
+
+            return Ok(Some(Vec::new()));
+        }
+
+        // Find the bets that were paid out with the previous weather
+        let bets = sqlx::query_as::<_, Row>(
+            r#"SELECT bets."user", bets.date, temperature, range, rain, wager, rain_payout,
+                    temperature_payout, payouts.rain_correct, payouts.temperature_correct
+                FROM bets
+                INNER JOIN payouts
+                    ON payouts.date = bets.date AND payouts."user" = bets."user"
+                WHERE bets.date = $1
+                ORDER BY bets."user"
+                FOR UPDATE OF payouts;"#,
+        )
+        .bind(date)
+        .fetch_all(tx.as_mut())
+        .await?;
+
+        let reason = format!("resettled bet for {date} after the observed weather was corrected");
+
+        let mut resettled = Vec::new();
+        for row in bets {
+            let previous_outcome = row
+                .bet
+                .settled_outcome(row.rain_correct, row.temperature_correct);
+            let outcome = row.bet.outcome(weather);
+            if (outcome.rain, outcome.temperature) == (row.rain_correct, row.temperature_correct) {
+                continue;
+            }
+
+            sqlx::query(
+                r#"UPDATE payouts
+                    SET rain_correct = $1, temperature_correct = $2
+                    WHERE "user" = $3 AND date = $4;"#,
+            )
+            .bind(outcome.rain)
+            .bind(outcome.temperature)
+            .bind(row.user)
+            .bind(date)
+            .execute(tx.as_mut())
+            .await?;
+
+            let difference = outcome.payout - previous_outcome.payout;
+            if difference != 0.0 {
+                sqlx::query("UPDATE users SET balance = balance + $1 WHERE id = $2;")
+                    .bind(difference)
+                    .bind(row.user)
+                    .execute(tx.as_mut())
+                    .await?;
+
+                sqlx::query(
+                    r#"INSERT INTO balance_adjustments ("user", amount, reason, created)
+                        VALUES ($1, $2, $3, NOW() AT TIME ZONE 'UTC');"#,
+                )
+                .bind(row.user)
+                .bind(difference)
+                .bind(&reason)
+                .execute(tx.as_mut())
+                .await?;
+            }
+
+            resettled.push((row.user, difference));
+        }
+
+        sqlx::query(
+            "INSERT INTO weather_corrections (date, previous_temperature, previous_rain,
+                    previous_source, temperature, rain, source, bets_resettled, created)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW() AT TIME ZONE 'UTC');",
+        )
+        .bind(date)
+        .bind(previous.temperature)
+        .bind(previous.rain)
+        .bind(previous.source)
+        .bind(weather.temperature)
+        .bind(weather.rain)
+        .bind(source)
+        .bind(resettled.len() as i64)
+        .execute(tx.as_mut())
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(resettled))
+    }
+
+    async fn get_balance(&self, user: UserId) -> Result<f64, DbError> {
+        Ok(
+            sqlx::query_scalar::<_, f64>("SELECT balance FROM users WHERE id = $1;")
+                .bind(user)
+                .fetch_one(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn unsettled_bets(&self, date: NaiveDate) -> Result<Vec<(UserId, BetRecord)>, DbError> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            user: UserId,
+            #[sqlx(flatten)]
+            bet: BetRecord,
+        }
+
+        Ok(sqlx::query_as::<_, Row>(
+            r#"SELECT "user", date, temperature, range, rain, wager, rain_payout, temperature_payout
+                FROM bets
+                WHERE date = $1
+                    AND NOT EXISTS (
+                        SELECT 1
+                            FROM payouts
+                            WHERE payouts.date = bets.date
+                                AND payouts."user" = bets."user"
+                    )
+                ORDER BY "user";"#,
+        )
+        .bind(date)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.user, row.bet))
+        .collect())
+    }
+}
@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate};
+use sqlx::PgPool;
+
+use crate::services::{
+    jobs::{JobRepository, JobRun},
+    DbError,
+};
+
+#[derive(Clone)]
+pub struct PostgresJobRepository {
+    pool: PgPool,
+}
+
+impl PostgresJobRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl JobRepository for PostgresJobRepository {
+    async fn lock(&self, job: &str, lease: Duration) -> Result<bool, DbError> {
+        Ok(sqlx::query_scalar::<_, String>(
+            "INSERT INTO job_locks (job, locked_until)
+                VALUES ($1, NOW() AT TIME ZONE 'UTC' + MAKE_INTERVAL(secs => $2))
+                ON CONFLICT (job) DO UPDATE SET locked_until = excluded.locked_until
+                    WHERE job_locks.locked_until <= NOW() AT TIME ZONE 'UTC'
+                RETURNING job;",
+        )
+        .bind(job)
+        .bind(lease.num_seconds() as f64)
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some())
+    }
+
+    async fn start_run(&self, job: &str) -> Result<i64, DbError> {
+        Ok(sqlx::query_scalar::<_, i64>(
+            "INSERT INTO job_runs (job, started)
+                VALUES ($1, NOW() AT TIME ZONE 'UTC')
+                RETURNING id;",
+        )
+        .bind(job)
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    async fn finish_run(&self, id: i64, succeeded: bool, message: &str) -> Result<(), DbError> {
+        sqlx::query(
+            "UPDATE job_runs
+                SET finished = NOW() AT TIME ZONE 'UTC', succeeded = $1, message = $2
+                WHERE id = $3;",
+        )
+        .bind(succeeded)
+        .bind(message)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn last_runs(&self) -> Result<Vec<JobRun>, DbError> {
+        Ok(sqlx::query_as::<_, JobRun>(
+            "SELECT job, started, finished, succeeded, message
+                FROM job_runs
+                WHERE id IN (SELECT MAX(id) FROM job_runs GROUP BY job);",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn dates_missing_weather(&self, before: NaiveDate) -> Result<Vec<NaiveDate>, DbError> {
+        Ok(sqlx::query_scalar::<_, NaiveDate>(
+            "SELECT DISTINCT date
+                FROM bets
+                WHERE date < $1 AND date NOT IN (SELECT date FROM historical_weather)
+                ORDER BY date;",
+        )
+        .bind(before)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
@@ -0,0 +1,50 @@
+//! Repositories backed by Postgres, allowing multiple replicas to share one database. The query
+//! macros can only check against a single database, so these queries are checked at runtime
+//! instead, against the schema in `migrations/postgres`.
+//!
+//! Timestamps are stored in UTC without a time zone (`NOW() AT TIME ZONE 'UTC'`), so they match
+//! the values SQLite produces with `DATETIME()`.
+
+mod audit;
+mod bet;
+mod jobs;
+mod notifications;
+mod push;
+mod session;
+mod state;
+mod token;
+mod user;
+mod weather;
+mod webhooks;
+
+use std::sync::Arc;
+
+use sqlx::PgPool;
+use tower_sessions_sqlx_store::PostgresStore;
+
+use self::{
+    audit::PostgresAuditRepository, bet::PostgresBetRepository, jobs::PostgresJobRepository,
+    notifications::PostgresNotificationRepository, push::PostgresPushRepository,
+    session::PostgresSessionRepository, state::PostgresStateRepository,
+    token::PostgresTokenRepository, user::PostgresUserRepository,
+    weather::PostgresWeatherRepository, webhooks::PostgresWebhookRepository,
+};
+
+use super::{AnySessionStore, Repositories};
+
+pub fn repositories(pool: &PgPool) -> Repositories {
+    Repositories {
+        bets: Arc::new(PostgresBetRepository::new(pool.clone())),
+        weather: Arc::new(PostgresWeatherRepository::new(pool.clone())),
+        states: Arc::new(PostgresStateRepository::new(pool.clone())),
+        sessions: Arc::new(PostgresSessionRepository::new(pool.clone())),
+        session_store: AnySessionStore::Postgres(PostgresStore::new(pool.clone())),
+        tokens: Arc::new(PostgresTokenRepository::new(pool.clone())),
+        users: Arc::new(PostgresUserRepository::new(pool.clone())),
+        jobs: Arc::new(PostgresJobRepository::new(pool.clone())),
+        notifications: Arc::new(PostgresNotificationRepository::new(pool.clone())),
+        webhooks: Arc::new(PostgresWebhookRepository::new(pool.clone())),
+        push: Arc::new(PostgresPushRepository::new(pool.clone())),
+        audit: Arc::new(PostgresAuditRepository::new(pool.clone())),
+    }
+}
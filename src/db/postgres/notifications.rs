@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use sqlx::PgPool;
+
+use crate::{
+    services::{
+        notifications::{NotificationRepository, Recipient},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct PostgresNotificationRepository {
+    pool: PgPool,
+}
+
+impl PostgresNotificationRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for PostgresNotificationRepository {
+    async fn preferences(&self, user: UserId) -> Result<Vec<(String, bool)>, DbError> {
+        Ok(sqlx::query_as::<_, (String, bool)>(
+            r#"SELECT kind, enabled FROM notification_preferences WHERE "user" = $1;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn set_preference(&self, user: UserId, kind: &str, enabled: bool) -> Result<(), DbError> {
+        sqlx::query(
+            r#"INSERT INTO notification_preferences ("user", kind, enabled)
+                VALUES ($1, $2, $3)
+                ON CONFLICT ("user", kind) DO UPDATE SET enabled = excluded.enabled;"#,
+        )
+        .bind(user)
+        .bind(kind)
+        .bind(enabled)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn recipients(
+        &self,
+        kind: &str,
+        default: bool,
+        date: NaiveDate,
+    ) -> Result<Vec<Recipient>, DbError> {
+        Ok(sqlx::query_as::<_, Recipient>(
+            r#"SELECT users.id AS "user", users.email AS email
+                FROM users
+                LEFT JOIN notification_preferences
+                    ON notification_preferences."user" = users.id
+                        AND notification_preferences.kind = $1
+                WHERE (
+                    users.email IS NOT NULL
+                        OR EXISTS (
+                            SELECT 1 FROM push_subscriptions
+                                WHERE push_subscriptions."user" = users.id
+                        )
+                )
+                    AND NOT users.banned
+                    AND COALESCE(notification_preferences.enabled, $2)
+                    AND NOT EXISTS (
+                        SELECT 1 FROM sent_notifications
+                            WHERE sent_notifications."user" = users.id
+                                AND sent_notifications.kind = $1
+                                AND sent_notifications.date = $3
+                    )
+                ORDER BY users.id;"#,
+        )
+        .bind(kind)
+        .bind(default)
+        .bind(date)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn record_sent(&self, user: UserId, kind: &str, date: NaiveDate) -> Result<(), DbError> {
+        sqlx::query(
+            r#"INSERT INTO sent_notifications ("user", kind, date, sent)
+                VALUES ($1, $2, $3, NOW() AT TIME ZONE 'UTC')
+                ON CONFLICT DO NOTHING;"#,
+        )
+        .bind(user)
+        .bind(kind)
+        .bind(date)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::{
+    services::{
+        push::{PushRepository, SubscriptionRecord},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct PostgresPushRepository {
+    pool: PgPool,
+}
+
+impl PostgresPushRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PushRepository for PostgresPushRepository {
+    async fn subscribe(
+        &self,
+        user: UserId,
+        endpoint: &str,
+        p256dh: &str,
+        auth: &str,
+    ) -> Result<(), DbError> {
+        sqlx::query(
+            r#"INSERT INTO push_subscriptions ("user", endpoint, p256dh, auth, created)
+                VALUES ($1, $2, $3, $4, NOW() AT TIME ZONE 'UTC')
+                ON CONFLICT (endpoint) DO UPDATE
+                    SET "user" = excluded."user",
+                        p256dh = excluded.p256dh,
+                        auth = excluded.auth,
+                        created = excluded.created;"#,
+        )
+        .bind(user)
+        .bind(endpoint)
+        .bind(p256dh)
+        .bind(auth)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, user: UserId, endpoint: &str) -> Result<bool, DbError> {
+        Ok(
+            sqlx::query(r#"DELETE FROM push_subscriptions WHERE endpoint = $1 AND "user" = $2;"#)
+                .bind(endpoint)
+                .bind(user)
+                .execute(&self.pool)
+                .await?
+                .rows_affected()
+                > 0,
+        )
+    }
+
+    async fn list(&self, user: UserId) -> Result<Vec<SubscriptionRecord>, DbError> {
+        Ok(sqlx::query_as::<_, SubscriptionRecord>(
+            r#"SELECT id, endpoint, p256dh, auth, created
+                FROM push_subscriptions
+                WHERE "user" = $1
+                ORDER BY created DESC, id DESC;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn remove(&self, id: i64) -> Result<(), DbError> {
+        sqlx::query("DELETE FROM push_subscriptions WHERE id = $1;")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+
+use crate::{
+    services::{
+        session::{SessionRecord, SessionRepository},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct PostgresSessionRepository {
+    pool: PgPool,
+}
+
+impl PostgresSessionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for PostgresSessionRepository {
+    async fn insert(
+        &self,
+        id: &str,
+        user: UserId,
+        user_agent: Option<&str>,
+    ) -> Result<(), DbError> {
+        sqlx::query(
+            r#"INSERT INTO user_sessions (id, "user", user_agent, created, last_seen)
+                VALUES ($1, $2, $3, NOW() AT TIME ZONE 'UTC', NOW() AT TIME ZONE 'UTC')
+                ON CONFLICT (id) DO UPDATE
+                    SET user_agent = $3, last_seen = NOW() AT TIME ZONE 'UTC';"#,
+        )
+        .bind(id)
+        .bind(user)
+        .bind(user_agent)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn touch(
+        &self,
+        id: &str,
+        user: UserId,
+        user_agent: Option<&str>,
+    ) -> Result<bool, DbError> {
+        Ok(sqlx::query(
+            r#"UPDATE user_sessions SET user_agent = $1, last_seen = NOW() AT TIME ZONE 'UTC'
+                WHERE id = $2 AND "user" = $3;"#,
+        )
+        .bind(user_agent)
+        .bind(id)
+        .bind(user)
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
+    async fn exists(&self, id: &str, user: UserId) -> Result<bool, DbError> {
+        Ok(sqlx::query_scalar::<_, String>(
+            r#"SELECT id FROM user_sessions WHERE id = $1 AND "user" = $2;"#,
+        )
+        .bind(id)
+        .bind(user)
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some())
+    }
+
+    async fn delete_inactive(&self, user: UserId, cutoff: NaiveDateTime) -> Result<(), DbError> {
+        sqlx::query(r#"DELETE FROM user_sessions WHERE "user" = $1 AND last_seen < $2;"#)
+            .bind(user)
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, user: UserId) -> Result<Vec<SessionRecord>, DbError> {
+        Ok(sqlx::query_as::<_, SessionRecord>(
+            r#"SELECT id, user_agent, created, last_seen
+                FROM user_sessions
+                WHERE "user" = $1
+                ORDER BY last_seen DESC;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn remove(&self, user: UserId, id: &str) -> Result<bool, DbError> {
+        Ok(
+            sqlx::query(r#"DELETE FROM user_sessions WHERE "user" = $1 AND id = $2;"#)
+                .bind(user)
+                .bind(id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected()
+                > 0,
+        )
+    }
+
+    async fn remove_all(&self, user: UserId) -> Result<Vec<String>, DbError> {
+        Ok(sqlx::query_scalar::<_, String>(
+            r#"DELETE FROM user_sessions
+                WHERE "user" = $1
+                RETURNING id;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
@@ -0,0 +1,71 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::services::{state::StateRepository, DbError};
+
+#[derive(Clone)]
+pub struct PostgresStateRepository {
+    pool: PgPool,
+}
+
+impl PostgresStateRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl StateRepository for PostgresStateRepository {
+    async fn insert(
+        &self,
+        namespace: &str,
+        value: &str,
+        generated: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        sqlx::query(
+            "INSERT INTO states (namespace, value, generated)
+                VALUES ($1, $2, $3);",
+        )
+        .bind(namespace)
+        .bind(value)
+        .bind(generated)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn redeem(
+        &self,
+        namespace: &str,
+        value: &str,
+        redeemed: DateTime<Utc>,
+        cutoff: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        Ok(sqlx::query(
+            "UPDATE states
+                SET redeemed = $1
+                WHERE namespace = $2 AND value = $3 AND redeemed IS NULL AND generated > $4;",
+        )
+        .bind(redeemed)
+        .bind(namespace)
+        .bind(value)
+        .bind(cutoff)
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
+    async fn delete_expired(&self, cutoff: DateTime<Utc>) -> Result<u64, DbError> {
+        Ok(sqlx::query(
+            "DELETE FROM states
+                WHERE redeemed IS NOT NULL OR generated <= $1;",
+        )
+        .bind(cutoff)
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+}
@@ -0,0 +1,89 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::{
+    services::{
+        token::{TokenRecord, TokenRepository},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct PostgresTokenRepository {
+    pool: PgPool,
+}
+
+impl PostgresTokenRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl TokenRepository for PostgresTokenRepository {
+    async fn insert(
+        &self,
+        user: UserId,
+        name: &str,
+        prefix: &str,
+        token_hash: &str,
+    ) -> Result<(), DbError> {
+        sqlx::query(
+            r#"INSERT INTO api_tokens ("user", name, prefix, token_hash, created)
+                VALUES ($1, $2, $3, $4, NOW() AT TIME ZONE 'UTC');"#,
+        )
+        .bind(user)
+        .bind(name)
+        .bind(prefix)
+        .bind(token_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, user: UserId) -> Result<Vec<TokenRecord>, DbError> {
+        Ok(sqlx::query_as::<_, TokenRecord>(
+            r#"SELECT id, name, prefix, created, last_used
+                FROM api_tokens
+                WHERE "user" = $1
+                ORDER BY created DESC;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn remove(&self, user: UserId, id: i64) -> Result<bool, DbError> {
+        Ok(
+            sqlx::query(r#"DELETE FROM api_tokens WHERE "user" = $1 AND id = $2;"#)
+                .bind(user)
+                .bind(id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected()
+                > 0,
+        )
+    }
+
+    async fn authenticate(&self, token_hash: &str) -> Result<Option<UserId>, DbError> {
+        Ok(sqlx::query_scalar::<_, UserId>(
+            r#"UPDATE api_tokens
+                SET last_used = NOW() AT TIME ZONE 'UTC'
+                WHERE token_hash = $1
+                RETURNING "user";"#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn remove_all(&self, user: UserId) -> Result<u64, DbError> {
+        Ok(sqlx::query(r#"DELETE FROM api_tokens WHERE "user" = $1;"#)
+            .bind(user)
+            .execute(&self.pool)
+            .await?
+            .rows_affected())
+    }
+}
@@ -0,0 +1,214 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::{
+    services::{
+        user::{AdjustmentRecord, UserRecord, UserRepository},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct PostgresUserRepository {
+    pool: PgPool,
+}
+
+impl PostgresUserRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for PostgresUserRepository {
+    async fn upsert(&self, provider: &str, identifier: &str, balance: f64) -> Result<i64, DbError> {
+        Ok(sqlx::query_scalar::<_, i64>(
+            "INSERT INTO users (balance, last_login, created, auth_provider, auth_identifier)
+                VALUES ($1, NOW() AT TIME ZONE 'UTC', NOW() AT TIME ZONE 'UTC', $2, $3)
+                ON CONFLICT (auth_provider, auth_identifier)
+                    DO UPDATE SET last_login = NOW() AT TIME ZONE 'UTC'
+                RETURNING id;",
+        )
+        .bind(balance)
+        .bind(provider)
+        .bind(identifier)
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    async fn set_email(&self, user: UserId, email: &str) -> Result<(), DbError> {
+        sqlx::query("UPDATE users SET email = $1 WHERE id = $2;")
+            .bind(email)
+            .bind(user)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn role(&self, user: UserId) -> Result<Option<String>, DbError> {
+        Ok(
+            sqlx::query_scalar::<_, String>("SELECT role FROM users WHERE id = $1;")
+                .bind(user)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn list(&self) -> Result<Vec<UserRecord>, DbError> {
+        Ok(sqlx::query_as::<_, UserRecord>(
+            "SELECT id, auth_provider, auth_identifier, email, balance, role, banned, created, last_login, deleted
+                FROM users
+                ORDER BY id;",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn find(&self, user: UserId) -> Result<Option<UserRecord>, DbError> {
+        Ok(sqlx::query_as::<_, UserRecord>(
+            "SELECT id, auth_provider, auth_identifier, email, balance, role, banned, created, last_login, deleted
+                FROM users
+                WHERE id = $1;",
+        )
+        .bind(user)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<UserRecord>, DbError> {
+        Ok(sqlx::query_as::<_, UserRecord>(
+            "SELECT id, auth_provider, auth_identifier, email, balance, role, banned, created, last_login, deleted
+                FROM users
+                WHERE CAST(id AS TEXT) = $1 OR auth_identifier ILIKE '%' || $1 || '%'
+                ORDER BY id
+                LIMIT 50;",
+        )
+        .bind(query)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn adjust_balance(
+        &self,
+        user: UserId,
+        amount: f64,
+        reason: &str,
+        admin: Option<UserId>,
+    ) -> Result<Option<f64>, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        let balance = sqlx::query_scalar::<_, f64>(
+            "UPDATE users SET balance = balance + $1 WHERE id = $2 RETURNING balance;",
+        )
+        .bind(amount)
+        .bind(user)
+        .fetch_optional(tx.as_mut())
+        .await?;
+
+        if balance.is_some() {
+            sqlx::query(
+                r#"INSERT INTO balance_adjustments ("user", amount, reason, admin, created)
+                    VALUES ($1, $2, $3, $4, NOW() AT TIME ZONE 'UTC');"#,
+            )
+            .bind(user)
+            .bind(amount)
+            .bind(reason)
+            .bind(admin)
+            .execute(tx.as_mut())
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(balance)
+    }
+
+    async fn adjustments(&self, user: UserId) -> Result<Vec<AdjustmentRecord>, DbError> {
+        Ok(sqlx::query_as::<_, AdjustmentRecord>(
+            r#"SELECT id, amount, reason, admin, created
+                FROM balance_adjustments
+                WHERE "user" = $1
+                ORDER BY created DESC, id DESC;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn set_role(&self, user: UserId, role: &str) -> Result<bool, DbError> {
+        Ok(
+            sqlx::query("UPDATE users SET role = $1 WHERE id = $2 AND deleted IS NULL;")
+                .bind(role)
+                .bind(user)
+                .execute(&self.pool)
+                .await?
+                .rows_affected()
+                > 0,
+        )
+    }
+
+    async fn ban(&self, user: UserId) -> Result<bool, DbError> {
+        Ok(sqlx::query("UPDATE users SET banned = TRUE WHERE id = $1;")
+            .bind(user)
+            .execute(&self.pool)
+            .await?
+            .rows_affected()
+            > 0)
+    }
+
+    async fn delete(&self, user: UserId) -> Result<bool, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        // The identifier only needs to be unique, so the ID is used in place of it
+        let deleted = sqlx::query(
+            "UPDATE users
+                SET auth_provider = 'deleted', auth_identifier = CAST(id AS TEXT), email = NULL,
+                    role = 'user', deleted = NOW() AT TIME ZONE 'UTC'
+                WHERE id = $1 AND deleted IS NULL;",
+        )
+        .bind(user)
+        .execute(tx.as_mut())
+        .await?
+        .rows_affected()
+            > 0;
+
+        if !deleted {
+            return Ok(false);
+        }
+
+        sqlx::query(r#"DELETE FROM api_tokens WHERE "user" = $1;"#)
+            .bind(user)
+            .execute(tx.as_mut())
+            .await?;
+        sqlx::query(r#"DELETE FROM webhooks WHERE "user" = $1;"#)
+            .bind(user)
+            .execute(tx.as_mut())
+            .await?;
+        sqlx::query(r#"DELETE FROM push_subscriptions WHERE "user" = $1;"#)
+            .bind(user)
+            .execute(tx.as_mut())
+            .await?;
+        sqlx::query(r#"DELETE FROM notification_preferences WHERE "user" = $1;"#)
+            .bind(user)
+            .execute(tx.as_mut())
+            .await?;
+        sqlx::query(r#"DELETE FROM sent_notifications WHERE "user" = $1;"#)
+            .bind(user)
+            .execute(tx.as_mut())
+            .await?;
+
+        sqlx::query(
+            r#"UPDATE audit_events SET ip = NULL, user_agent = NULL
+                WHERE "user" = $1 AND (ip IS NOT NULL OR user_agent IS NOT NULL);"#,
+        )
+        .bind(user)
+        .execute(tx.as_mut())
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,197 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use sqlx::PgPool;
+
+use crate::services::{
+    weather::{CachedForecast, CachedWeather, Forecast, Weather, WeatherRepository, WeatherSource},
+    DbError,
+};
+
+#[derive(Clone)]
+pub struct PostgresWeatherRepository {
+    pool: PgPool,
+}
+
+impl PostgresWeatherRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WeatherRepository for PostgresWeatherRepository {
+    async fn get_day_forecast(
+        &self,
+        date: NaiveDate,
+        retrieval_date: NaiveDate,
+    ) -> Result<Option<Forecast>, DbError> {
+        Ok(sqlx::query_as::<_, Forecast>(
+            "SELECT rain, minimum_temperature, maximum_temperature, weather_code, FALSE AS stale
+                FROM forecasts
+                WHERE date = $1 AND CAST(date_retrieved AS DATE) = $2;",
+        )
+        .bind(date)
+        .bind(retrieval_date)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn get_forecast_range(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        retrieval_date: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, Forecast)>, DbError> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            date: NaiveDate,
+            #[sqlx(flatten)]
+            forecast: Forecast,
+        }
+
+        Ok(sqlx::query_as::<_, Row>(
+            "SELECT date, rain, minimum_temperature, maximum_temperature, weather_code,
+                    FALSE AS stale
+                FROM forecasts
+                WHERE date >= $1 AND date <= $2 AND CAST(date_retrieved AS DATE) = $3
+                ORDER BY date;",
+        )
+        .bind(start_date)
+        .bind(end_date)
+        .bind(retrieval_date)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.date, row.forecast))
+        .collect())
+    }
+
+    async fn get_latest_forecast(&self, date: NaiveDate) -> Result<Option<Forecast>, DbError> {
+        Ok(sqlx::query_as::<_, Forecast>(
+            "SELECT rain, minimum_temperature, maximum_temperature, weather_code, TRUE AS stale
+                FROM forecasts
+                WHERE date = $1
+                ORDER BY date_retrieved DESC
+                LIMIT 1;",
+        )
+        .bind(date)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn save_forecast(&self, date: NaiveDate, forecast: &Forecast) -> Result<(), DbError> {
+        sqlx::query(
+            "INSERT INTO forecasts (date, rain, minimum_temperature, maximum_temperature, weather_code)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT DO NOTHING;",
+        )
+        .bind(date)
+        .bind(forecast.rain)
+        .bind(forecast.minimum_temperature)
+        .bind(forecast.maximum_temperature)
+        .bind(forecast.weather_code)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_cached_forecasts(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<CachedForecast>, DbError> {
+        Ok(sqlx::query_as::<_, CachedForecast>(
+            "SELECT date, date_retrieved, rain, minimum_temperature, maximum_temperature,
+                    weather_code, FALSE AS stale
+                FROM forecasts
+                WHERE date >= $1 AND date <= $2
+                ORDER BY date, date_retrieved;",
+        )
+        .bind(start_date)
+        .bind(end_date)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn list_cached_weather(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<CachedWeather>, DbError> {
+        Ok(sqlx::query_as::<_, CachedWeather>(
+            "SELECT date, date_retrieved, rain, temperature, source, provisional
+                FROM historical_weather
+                WHERE date >= $1 AND date <= $2
+                ORDER BY date;",
+        )
+        .bind(start_date)
+        .bind(end_date)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn get_historical_weather(&self, date: NaiveDate) -> Result<Option<Weather>, DbError> {
+        Ok(sqlx::query_as::<_, Weather>(
+            "SELECT rain, temperature
+                FROM historical_weather
+                WHERE date = $1;",
+        )
+        .bind(date)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn list_historical_weather(&self) -> Result<Vec<(NaiveDate, Weather)>, DbError> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            date: NaiveDate,
+            #[sqlx(flatten)]
+            weather: Weather,
+        }
+
+        Ok(sqlx::query_as::<_, Row>(
+            "SELECT date, rain, temperature
+                FROM historical_weather
+                ORDER BY date;",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.date, row.weather))
+        .collect())
+    }
+
+    async fn save_historical_weather(
+        &self,
+        date: NaiveDate,
+        weather: &Weather,
+        source: WeatherSource,
+    ) -> Result<(), DbError> {
+        sqlx::query(
+            "INSERT INTO historical_weather (date, temperature, rain, source, provisional)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT DO NOTHING;",
+        )
+        .bind(date)
+        .bind(weather.temperature)
+        .bind(weather.rain)
+        .bind(source)
+        .bind(source.is_provisional())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn provisional_dates(&self) -> Result<Vec<NaiveDate>, DbError> {
+        Ok(sqlx::query_scalar::<_, NaiveDate>(
+            "SELECT date
+                FROM historical_weather
+                WHERE provisional
+                ORDER BY date;",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
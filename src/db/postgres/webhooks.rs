@@ -0,0 +1,171 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::{
+    services::{
+        webhooks::{DeliveryRecord, OutboxMessage, WebhookRecord, WebhookRepository},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct PostgresWebhookRepository {
+    pool: PgPool,
+}
+
+impl PostgresWebhookRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for PostgresWebhookRepository {
+    async fn insert(&self, user: UserId, url: &str, secret: &str) -> Result<(), DbError> {
+        sqlx::query(
+            r#"INSERT INTO webhooks ("user", url, secret, created)
+                VALUES ($1, $2, $3, NOW() AT TIME ZONE 'UTC');"#,
+        )
+        .bind(user)
+        .bind(url)
+        .bind(secret)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, user: UserId) -> Result<Vec<WebhookRecord>, DbError> {
+        Ok(sqlx::query_as::<_, WebhookRecord>(
+            r#"SELECT id, url, created
+                FROM webhooks
+                WHERE "user" = $1
+                ORDER BY created DESC, id DESC;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn remove(&self, user: UserId, id: i64) -> Result<bool, DbError> {
+        Ok(
+            sqlx::query(r#"DELETE FROM webhooks WHERE id = $1 AND "user" = $2;"#)
+                .bind(id)
+                .bind(user)
+                .execute(&self.pool)
+                .await?
+                .rows_affected()
+                > 0,
+        )
+    }
+
+    async fn enqueue(&self, user: UserId, event: &str, payload: &str) -> Result<u64, DbError> {
+        Ok(sqlx::query(
+            r#"INSERT INTO webhook_outbox (webhook, event, payload, next_attempt, created)
+                SELECT id, $1, $2, NOW() AT TIME ZONE 'UTC', NOW() AT TIME ZONE 'UTC'
+                    FROM webhooks
+                    WHERE "user" = $3;"#,
+        )
+        .bind(event)
+        .bind(payload)
+        .bind(user)
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+
+    async fn claim_due(&self, limit: i64, lease_secs: i64) -> Result<Vec<OutboxMessage>, DbError> {
+        // Other replicas skip the rows that are being claimed, rather than waiting to claim them
+        // as well
+        Ok(sqlx::query_as::<_, OutboxMessage>(
+            "WITH claimed AS (
+                UPDATE webhook_outbox
+                    SET next_attempt = NOW() AT TIME ZONE 'UTC' + make_interval(secs => $2)
+                    WHERE id IN (
+                        SELECT id
+                            FROM webhook_outbox
+                            WHERE completed IS NULL AND next_attempt <= NOW() AT TIME ZONE 'UTC'
+                            ORDER BY next_attempt
+                            LIMIT $1
+                            FOR UPDATE SKIP LOCKED
+                    )
+                    RETURNING id, webhook, event, payload, attempts
+            )
+            SELECT claimed.id, webhooks.url, webhooks.secret, claimed.event, claimed.payload,
+                    claimed.attempts
+                FROM claimed
+                INNER JOIN webhooks ON webhooks.id = claimed.webhook;",
+        )
+        .bind(limit)
+        .bind(lease_secs as f64)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn record_attempt(
+        &self,
+        message: i64,
+        status_code: Option<i64>,
+        error: Option<&str>,
+        retry_secs: Option<i64>,
+    ) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "INSERT INTO webhook_deliveries (message, attempted, status_code, error)
+                VALUES ($1, NOW() AT TIME ZONE 'UTC', $2, $3);",
+        )
+        .bind(message)
+        .bind(status_code)
+        .bind(error)
+        .execute(tx.as_mut())
+        .await?;
+
+        if let Some(retry_secs) = retry_secs {
+            sqlx::query(
+                "UPDATE webhook_outbox
+                    SET attempts = attempts + 1,
+                        next_attempt = NOW() AT TIME ZONE 'UTC' + make_interval(secs => $1)
+                    WHERE id = $2;",
+            )
+            .bind(retry_secs as f64)
+            .bind(message)
+            .execute(tx.as_mut())
+            .await?;
+        } else {
+            sqlx::query(
+                "UPDATE webhook_outbox
+                    SET attempts = attempts + 1,
+                        completed = NOW() AT TIME ZONE 'UTC',
+                        delivered = $1
+                    WHERE id = $2;",
+            )
+            .bind(error.is_none())
+            .bind(message)
+            .execute(tx.as_mut())
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn deliveries(&self, user: UserId, limit: i64) -> Result<Vec<DeliveryRecord>, DbError> {
+        Ok(sqlx::query_as::<_, DeliveryRecord>(
+            r#"SELECT webhook_outbox.webhook, webhook_outbox.event, webhook_deliveries.attempted,
+                    webhook_deliveries.status_code, webhook_deliveries.error
+                FROM webhook_deliveries
+                INNER JOIN webhook_outbox ON webhook_outbox.id = webhook_deliveries.message
+                INNER JOIN webhooks ON webhooks.id = webhook_outbox.webhook
+                WHERE webhooks."user" = $1
+                ORDER BY webhook_deliveries.attempted DESC, webhook_deliveries.id DESC
+                LIMIT $2;"#,
+        )
+        .bind(user)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+
+use crate::services::{
+    audit::{AuditEvent, AuditFilter, AuditRecord, AuditRepository},
+    DbError,
+};
+
+#[derive(Clone)]
+pub struct SqliteAuditRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteAuditRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditRepository for SqliteAuditRepository {
+    async fn insert(&self, event: &AuditEvent) -> Result<(), DbError> {
+        let kind = event.kind.name();
+        let ip = event.ip.map(|ip| ip.to_string());
+
+        sqlx::query!(
+            "INSERT INTO audit_events (user, actor, kind, details, ip, user_agent, created)
+                VALUES (?, ?, ?, ?, ?, ?, DATETIME());",
+            event.user,
+            event.actor,
+            kind,
+            event.details,
+            ip,
+            event.user_agent
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, filter: &AuditFilter, limit: i64) -> Result<Vec<AuditRecord>, DbError> {
+        let kind = filter.kind.map(|kind| kind.name());
+
+        Ok(sqlx::query_as!(
+            AuditRecord,
+            r#"SELECT audit_events.id, user, auth_identifier AS "identifier?", actor, kind, details,
+                    ip, user_agent, audit_events.created
+                FROM audit_events
+                LEFT JOIN users ON users.id = audit_events.user
+                WHERE (? IS NULL OR user = ?) AND (? IS NULL OR kind = ?)
+                ORDER BY audit_events.created DESC, audit_events.id DESC
+                LIMIT ?;"#,
+            filter.user,
+            filter.user,
+            kind,
+            kind,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
@@ -0,0 +1,532 @@
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use sqlx::SqlitePool;
+
+use crate::{
+    services::{
+        bet::{BetOutcome, BetRecord, BetRepository},
+        weather::{Weather, WeatherSource},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct SqliteBetRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteBetRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl BetRepository for SqliteBetRepository {
+    async fn upsert_bet(&self, user: UserId, bet: &BetRecord) -> Result<bool, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Get the current wager
+        let previous_wager = sqlx::query_scalar!(
+            "SELECT wager
+                FROM bets
+                WHERE user = ? AND date = ?;",
+            user,
+            bet.date
+        )
+        .fetch_optional(tx.as_mut())
+        .await?
+        .unwrap_or_default();
+
+        // Insert the new bet
+        sqlx::query!(
+            "INSERT INTO bets (user, date, temperature, range, rain, wager, rain_payout, temperature_payout)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (user, date) DO UPDATE
+                    SET temperature = ?, range = ?, rain = ?, wager = ?, rain_payout = ?, temperature_payout = ?;",
+            // Insert values
+            user,
+            bet.date,
+            bet.temperature,
+            bet.range,
+            bet.rain,
+            bet.wager,
+            bet.rain_payout,
+            bet.temperature_payout,
+            // Update values
+            bet.temperature,
+            bet.range,
+            bet.rain,
+            bet.wager,
+            bet.rain_payout,
+            bet.temperature_payout,
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        // Update the user's balance
+        let d_balance = previous_wager - bet.wager;
+        let balance = sqlx::query_scalar!(
+            "UPDATE users SET balance = balance + ? WHERE id = ? RETURNING balance;",
+            d_balance,
+            user
+        )
+        .fetch_one(tx.as_mut())
+        .await?;
+
+        if balance >= 0.0 {
+            // Finalise the transaction
+            tx.commit().await?;
+
+            Ok(true)
+        } else {
+            tx.rollback().await?;
+
+            Ok(false)
+        }
+    }
+
+    async fn delete_bet(
+        &self,
+        user: UserId,
+        date: NaiveDate,
+    ) -> Result<Option<BetRecord>, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Remove the bet, as long as it hasn't been paid out
+        let bet = sqlx::query_as!(
+            BetRecord,
+            "DELETE FROM bets
+                WHERE user = ?
+                    AND date = ?
+                    AND (
+                        SELECT COUNT(*)
+                            FROM payouts
+                            WHERE payouts.date = bets.date
+                                AND payouts.user = bets.user
+                    ) = 0
+                RETURNING date, temperature, range, rain, wager, rain_payout, temperature_payout;",
+            user,
+            date
+        )
+        .fetch_optional(tx.as_mut())
+        .await?;
+
+        let Some(bet) = bet else {
+            return Ok(None);
+        };
+
+        // Refund the wager
+        sqlx::query!(
+            "UPDATE users SET balance = balance + ? WHERE id = ?;",
+            bet.wager,
+            user
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(bet))
+    }
+
+    async fn find_bet(&self, user: UserId, date: NaiveDate) -> Result<Option<BetRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            BetRecord,
+            "SELECT date, temperature, range, rain, wager, rain_payout, temperature_payout
+                FROM bets
+                WHERE user = ? and date = ?;",
+            user,
+            date,
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn record_payout(
+        &self,
+        user: UserId,
+        date: NaiveDate,
+        outcome: &BetOutcome,
+    ) -> Result<(), DbError> {
+        // Begin a new transaction
+        let mut tx = self.pool.begin().await?;
+
+        // Record the payout
+        sqlx::query!(
+            "INSERT INTO payouts (user, date, rain_correct, temperature_correct)
+                VALUES (?, ?, ?, ?);",
+            user,
+            date,
+            outcome.rain,
+            outcome.temperature
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        sqlx::query!(
+            "UPDATE users
+                -- Perform the actual balance update
+                SET balance = users.balance + d.balance
+                FROM (
+                    -- Build the balance change amount
+                    SELECT (
+                        IFNULL(
+                            -- Select the temperature payout from the bet
+                            (SELECT temperature_payout
+                                FROM bets
+                                -- Only include the temperature payout if it's correct
+                                WHERE ?
+                                    AND date = ?
+                                    AND user = ?),
+                            0
+                        ) + IFNULL(
+                            -- Do the same for the rain payout
+                            (SELECT rain_payout
+                                FROM bets
+                                WHERE ?
+                                    AND date = ?
+                                    AND user = ?),
+                            0
+                        )
+                    ) AS balance
+                ) AS d
+                WHERE id = ?;",
+            // Temperature payout information
+            outcome.temperature,
+            date,
+            user,
+            // Rain payout information
+            outcome.rain,
+            date,
+            user,
+            // User to update
+            user
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn ready_bets(&self, user: UserId) -> Result<Vec<BetRecord>, DbError> {
+        // WARN: This will mix UTC dates with user locale dates
+        let now = Utc::now().date_naive();
+
+        // Select all bets that don't have a corresponding payout
+        Ok(sqlx::query_as!(
+            BetRecord,
+            "SELECT date, temperature, range, rain, wager, rain_payout, temperature_payout
+                FROM bets
+                WHERE user = ?
+                    AND date < ?
+                    AND (
+                        SELECT COUNT(*)
+                            FROM payouts
+                            WHERE payouts.date = bets.date
+                                AND payouts.user = bets.user
+                    ) = 0;",
+            user,
+            now
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn history(
+        &self,
+        user: UserId,
+    ) -> Result<Vec<(BetRecord, Option<(bool, bool)>)>, DbError> {
+        Ok(sqlx::query!(
+            r#"SELECT bets.date, temperature, range, rain, wager, rain_payout, temperature_payout,
+                    payouts.rain_correct AS "rain_correct?",
+                    payouts.temperature_correct AS "temperature_correct?"
+                FROM bets
+                LEFT JOIN payouts
+                    ON payouts.date = bets.date AND payouts.user = bets.user
+                WHERE bets.user = ?
+                ORDER BY bets.date DESC;"#,
+            user
+        )
+        .map(|row| {
+            (
+                BetRecord {
+                    date: row.date,
+                    temperature: row.temperature,
+                    range: row.range,
+                    rain: row.rain,
+                    wager: row.wager,
+                    rain_payout: row.rain_payout,
+                    temperature_payout: row.temperature_payout,
+                },
+                row.rain_correct.zip(row.temperature_correct),
+            )
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn void_bets(
+        &self,
+        date: NaiveDate,
+        reason: &str,
+        admin: Option<UserId>,
+    ) -> Result<Vec<(UserId, f64)>, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        let bets = sqlx::query!(
+            r#"SELECT bets.user AS "user: UserId", bets.date, temperature, range, rain, wager,
+                    rain_payout, temperature_payout,
+                    payouts.rain_correct AS "rain_correct?",
+                    payouts.temperature_correct AS "temperature_correct?"
+                FROM bets
+                LEFT JOIN payouts
+                    ON payouts.date = bets.date AND payouts.user = bets.user
+                WHERE bets.date = ?
+                ORDER BY bets.user;"#,
+            date
+        )
+        .fetch_all(tx.as_mut())
+        .await?;
+
+        let mut refunds = Vec::with_capacity(bets.len());
+        for row in bets {
+            let bet = BetRecord {
+                date: row.date,
+                temperature: row.temperature,
+                range: row.range,
+                rain: row.rain,
+                wager: row.wager,
+                rain_payout: row.rain_payout,
+                temperature_payout: row.temperature_payout,
+            };
+
+            // Take back anything that was paid out, and return the wager
+            let payout = row
+                .rain_correct
+                .zip(row.temperature_correct)
+                .map(|(rain, temperature)| bet.settled_outcome(rain, temperature).payout)
+                .unwrap_or_default();
+            let refund = bet.wager - payout;
+
+            sqlx::query!(
+                "DELETE FROM payouts WHERE user = ? AND date = ?;",
+                row.user,
+                date
+            )
+            .execute(tx.as_mut())
+            .await?;
+
+            sqlx::query!(
+                "DELETE FROM bets WHERE user = ? AND date = ?;",
+                row.user,
+                date
+            )
+            .execute(tx.as_mut())
+            .await?;
+
+            sqlx::query!(
+                "UPDATE users SET balance = balance + ? WHERE id = ?;",
+                refund,
+                row.user
+            )
+            .execute(tx.as_mut())
+            .await?;
+
+            sqlx::query!(
+                "INSERT INTO balance_adjustments (user, amount, reason, admin, created)
+                    VALUES (?, ?, ?, ?, DATETIME());",
+                row.user,
+                refund,
+                reason,
+                admin
+            )
+            .execute(tx.as_mut())
+            .await?;
+
+            refunds.push((row.user, refund));
+        }
+
+        tx.commit().await?;
+
+        Ok(refunds)
+    }
+
+    async fn resettle(
+        &self,
+        date: NaiveDate,
+        weather: &Weather,
+        source: WeatherSource,
+    ) -> Result<Option<Vec<(UserId, f64)>>, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        let Some(previous) = sqlx::query!(
+            r#"SELECT temperature, rain, source AS "source: WeatherSource"
+                FROM historical_weather
+                WHERE date = ? AND provisional;"#,
+            date
+        )
+        .fetch_optional(tx.as_mut())
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let provisional = source.is_provisional();
+        sqlx::query!(
+            "UPDATE historical_weather
+                SET temperature = ?, rain = ?, source = ?, provisional = ?,
+                    date_retrieved = DATETIME()
+                WHERE date = ?;",
+            weather.temperature,
+            weather.rain,
+            source,
+            provisional,
+            date
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        if previous.temperature == weather.temperature && previous.rain == weather.rain {
+            tx.commit().await?;
+
+            return Ok(Some(Vec::new()));
+        }
+
+        // Find the bets that were paid out with the previous weather
+        let bets = sqlx::query!(
+            r#"SELECT bets.user AS "user: UserId", bets.date, temperature, range, rain, wager,
+                    rain_payout, temperature_payout, payouts.rain_correct,
+                    payouts.temperature_correct
+                FROM bets
+                INNER JOIN payouts
+                    ON payouts.date = bets.date AND payouts.user = bets.user
+                WHERE bets.date = ?
+                ORDER BY bets.user;"#,
+            date
+        )
+        .fetch_all(tx.as_mut())
+        .await?;
+
+        let reason = format!("resettled bet for {date} after the observed weather was corrected");
+
+        let mut resettled = Vec::new();
+        for row in bets {
+            let bet = BetRecord {
+                date: row.date,
+                temperature: row.temperature,
+                range: row.range,
+                rain: row.rain,
+                wager: row.wager,
+                rain_payout: row.rain_payout,
+                temperature_payout: row.temperature_payout,
+            };
+
+            let previous_outcome = bet.settled_outcome(row.rain_correct, row.temperature_correct);
+            let outcome = bet.outcome(weather);
+            if (outcome.rain, outcome.temperature) == (row.rain_correct, row.temperature_correct) {
+                continue;
+            }
+
+            sqlx::query!(
+                "UPDATE payouts
+                    SET rain_correct = ?, temperature_correct = ?
+                    WHERE user = ? AND date = ?;",
+                outcome.rain,
+                outcome.temperature,
+                row.user,
+                date
+            )
+            .execute(tx.as_mut())
+            .await?;
+
+            let difference = outcome.payout - previous_outcome.payout;
+            if difference != 0.0 {
+                sqlx::query!(
+                    "UPDATE users SET balance = balance + ? WHERE id = ?;",
+                    difference,
+                    row.user
+                )
+                .execute(tx.as_mut())
+                .await?;
+
+                sqlx::query!(
+                    "INSERT INTO balance_adjustments (user, amount, reason, created)
+                        VALUES (?, ?, ?, DATETIME());",
+                    row.user,
+                    difference,
+                    reason
+                )
+                .execute(tx.as_mut())
+                .await?;
+            }
+
+            resettled.push((row.user, difference));
+        }
+
+        let bets_resettled = resettled.len() as i64;
+        sqlx::query!(
+            "INSERT INTO weather_corrections (date, previous_temperature, previous_rain,
+                    previous_source, temperature, rain, source, bets_resettled, created)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, DATETIME());",
+            date,
+            previous.temperature,
+            previous.rain,
+            previous.source,
+            weather.temperature,
+            weather.rain,
+            source,
+            bets_resettled
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(resettled))
+    }
+
+    async fn get_balance(&self, user: UserId) -> Result<f64, DbError> {
+        Ok(
+            sqlx::query_scalar!("SELECT balance FROM users WHERE id = ?;", user)
+                .fetch_one(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn unsettled_bets(&self, date: NaiveDate) -> Result<Vec<(UserId, BetRecord)>, DbError> {
+        Ok(sqlx::query!(
+            r#"SELECT user AS "user: UserId", date, temperature, range, rain, wager, rain_payout,
+                    temperature_payout
+                FROM bets
+                WHERE date = ?
+                    AND (
+                        SELECT COUNT(*)
+                            FROM payouts
+                            WHERE payouts.date = bets.date
+                                AND payouts.user = bets.user
+                    ) = 0
+                ORDER BY user;"#,
+            date
+        )
+        .map(|row| {
+            (
+                row.user,
+                BetRecord {
+                    date: row.date,
+                    temperature: row.temperature,
+                    range: row.range,
+                    rain: row.rain,
+                    wager: row.wager,
+                    rain_payout: row.rain_payout,
+                    temperature_payout: row.temperature_payout,
+                },
+            )
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
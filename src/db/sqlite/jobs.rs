@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate};
+use sqlx::SqlitePool;
+
+use crate::services::{
+    jobs::{JobRepository, JobRun},
+    DbError,
+};
+
+#[derive(Clone)]
+pub struct SqliteJobRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteJobRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl JobRepository for SqliteJobRepository {
+    async fn lock(&self, job: &str, lease: Duration) -> Result<bool, DbError> {
+        let lease = format!("+{} seconds", lease.num_seconds());
+
+        Ok(sqlx::query_scalar!(
+            "INSERT INTO job_locks (job, locked_until) VALUES (?, DATETIME('now', ?))
+                ON CONFLICT (job) DO UPDATE SET locked_until = excluded.locked_until
+                    WHERE job_locks.locked_until <= DATETIME()
+                RETURNING job;",
+            job,
+            lease
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some())
+    }
+
+    async fn start_run(&self, job: &str) -> Result<i64, DbError> {
+        Ok(sqlx::query_scalar!(
+            "INSERT INTO job_runs (job, started) VALUES (?, DATETIME()) RETURNING id;",
+            job
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    async fn finish_run(&self, id: i64, succeeded: bool, message: &str) -> Result<(), DbError> {
+        sqlx::query!(
+            "UPDATE job_runs
+                SET finished = DATETIME(), succeeded = ?, message = ?
+                WHERE id = ?;",
+            succeeded,
+            message,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn last_runs(&self) -> Result<Vec<JobRun>, DbError> {
+        Ok(sqlx::query_as!(
+            JobRun,
+            "SELECT job, started, finished, succeeded, message
+                FROM job_runs
+                WHERE id IN (SELECT MAX(id) FROM job_runs GROUP BY job);"
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn dates_missing_weather(&self, before: NaiveDate) -> Result<Vec<NaiveDate>, DbError> {
+        Ok(sqlx::query_scalar!(
+            r#"SELECT DISTINCT date AS "date: NaiveDate"
+                FROM bets
+                WHERE date < ? AND date NOT IN (SELECT date FROM historical_weather)
+                ORDER BY date;"#,
+            before
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
@@ -0,0 +1,46 @@
+//! Repositories backed by SQLite. Queries are checked at compile time against the schema in
+//! `migrations/sqlite`.
+
+mod audit;
+mod bet;
+mod jobs;
+mod notifications;
+mod push;
+mod session;
+mod state;
+mod token;
+mod user;
+mod weather;
+mod webhooks;
+
+use std::sync::Arc;
+
+use sqlx::SqlitePool;
+use tower_sessions_sqlx_store::SqliteStore;
+
+use self::{
+    audit::SqliteAuditRepository, bet::SqliteBetRepository, jobs::SqliteJobRepository,
+    notifications::SqliteNotificationRepository, push::SqlitePushRepository,
+    session::SqliteSessionRepository, state::SqliteStateRepository, token::SqliteTokenRepository,
+    user::SqliteUserRepository, weather::SqliteWeatherRepository,
+    webhooks::SqliteWebhookRepository,
+};
+
+use super::{AnySessionStore, Repositories};
+
+pub fn repositories(pool: &SqlitePool) -> Repositories {
+    Repositories {
+        bets: Arc::new(SqliteBetRepository::new(pool.clone())),
+        weather: Arc::new(SqliteWeatherRepository::new(pool.clone())),
+        states: Arc::new(SqliteStateRepository::new(pool.clone())),
+        sessions: Arc::new(SqliteSessionRepository::new(pool.clone())),
+        session_store: AnySessionStore::Sqlite(SqliteStore::new(pool.clone())),
+        tokens: Arc::new(SqliteTokenRepository::new(pool.clone())),
+        users: Arc::new(SqliteUserRepository::new(pool.clone())),
+        jobs: Arc::new(SqliteJobRepository::new(pool.clone())),
+        notifications: Arc::new(SqliteNotificationRepository::new(pool.clone())),
+        webhooks: Arc::new(SqliteWebhookRepository::new(pool.clone())),
+        push: Arc::new(SqlitePushRepository::new(pool.clone())),
+        audit: Arc::new(SqliteAuditRepository::new(pool.clone())),
+    }
+}
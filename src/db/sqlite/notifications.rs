@@ -0,0 +1,105 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use sqlx::SqlitePool;
+
+use crate::{
+    services::{
+        notifications::{NotificationRepository, Recipient},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct SqliteNotificationRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteNotificationRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for SqliteNotificationRepository {
+    async fn preferences(&self, user: UserId) -> Result<Vec<(String, bool)>, DbError> {
+        Ok(sqlx::query!(
+            "SELECT kind, enabled FROM notification_preferences WHERE user = ?;",
+            user
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.kind, row.enabled))
+        .collect())
+    }
+
+    async fn set_preference(&self, user: UserId, kind: &str, enabled: bool) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO notification_preferences (user, kind, enabled)
+                VALUES (?, ?, ?)
+                ON CONFLICT (user, kind) DO UPDATE SET enabled = excluded.enabled;",
+            user,
+            kind,
+            enabled
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn recipients(
+        &self,
+        kind: &str,
+        default: bool,
+        date: NaiveDate,
+    ) -> Result<Vec<Recipient>, DbError> {
+        Ok(sqlx::query_as!(
+            Recipient,
+            r#"SELECT users.id AS "user: UserId", users.email
+                FROM users
+                LEFT JOIN notification_preferences
+                    ON notification_preferences.user = users.id
+                        AND notification_preferences.kind = ?
+                WHERE (
+                    users.email IS NOT NULL
+                        OR EXISTS (
+                            SELECT 1 FROM push_subscriptions
+                                WHERE push_subscriptions.user = users.id
+                        )
+                )
+                    AND NOT users.banned
+                    AND COALESCE(notification_preferences.enabled, ?)
+                    AND NOT EXISTS (
+                        SELECT 1 FROM sent_notifications
+                            WHERE sent_notifications.user = users.id
+                                AND sent_notifications.kind = ?
+                                AND sent_notifications.date = ?
+                    )
+                ORDER BY users.id;"#,
+            kind,
+            default,
+            kind,
+            date
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn record_sent(&self, user: UserId, kind: &str, date: NaiveDate) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO sent_notifications (user, kind, date, sent)
+                VALUES (?, ?, ?, DATETIME())
+                ON CONFLICT DO NOTHING;",
+            user,
+            kind,
+            date
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
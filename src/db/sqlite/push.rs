@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+
+use crate::{
+    services::{
+        push::{PushRepository, SubscriptionRecord},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct SqlitePushRepository {
+    pool: SqlitePool,
+}
+
+impl SqlitePushRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PushRepository for SqlitePushRepository {
+    async fn subscribe(
+        &self,
+        user: UserId,
+        endpoint: &str,
+        p256dh: &str,
+        auth: &str,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO push_subscriptions (user, endpoint, p256dh, auth, created)
+                VALUES (?, ?, ?, ?, DATETIME())
+                ON CONFLICT (endpoint) DO UPDATE
+                    SET user = excluded.user,
+                        p256dh = excluded.p256dh,
+                        auth = excluded.auth,
+                        created = excluded.created;",
+            user,
+            endpoint,
+            p256dh,
+            auth
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, user: UserId, endpoint: &str) -> Result<bool, DbError> {
+        Ok(sqlx::query!(
+            "DELETE FROM push_subscriptions WHERE endpoint = ? AND user = ?;",
+            endpoint,
+            user
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
+    async fn list(&self, user: UserId) -> Result<Vec<SubscriptionRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            SubscriptionRecord,
+            "SELECT id, endpoint, p256dh, auth, created
+                FROM push_subscriptions
+                WHERE user = ?
+                ORDER BY created DESC, id DESC;",
+            user
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn remove(&self, id: i64) -> Result<(), DbError> {
+        sqlx::query!("DELETE FROM push_subscriptions WHERE id = ?;", id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
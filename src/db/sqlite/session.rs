@@ -0,0 +1,128 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::SqlitePool;
+
+use crate::{
+    services::{
+        session::{SessionRecord, SessionRepository},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct SqliteSessionRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteSessionRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for SqliteSessionRepository {
+    async fn insert(
+        &self,
+        id: &str,
+        user: UserId,
+        user_agent: Option<&str>,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO user_sessions (id, user, user_agent, created, last_seen)
+                VALUES (?, ?, ?, DATETIME(), DATETIME())
+                ON CONFLICT (id) DO UPDATE
+                    SET user_agent = ?, last_seen = DATETIME();",
+            // Insert values
+            id,
+            user,
+            user_agent,
+            // Update values
+            user_agent
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn touch(
+        &self,
+        id: &str,
+        user: UserId,
+        user_agent: Option<&str>,
+    ) -> Result<bool, DbError> {
+        Ok(sqlx::query!(
+            "UPDATE user_sessions SET user_agent = ?, last_seen = DATETIME()
+                WHERE id = ? AND user = ?;",
+            user_agent,
+            id,
+            user
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
+    async fn exists(&self, id: &str, user: UserId) -> Result<bool, DbError> {
+        Ok(sqlx::query_scalar!(
+            "SELECT id FROM user_sessions WHERE id = ? AND user = ?;",
+            id,
+            user
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some())
+    }
+
+    async fn delete_inactive(&self, user: UserId, cutoff: NaiveDateTime) -> Result<(), DbError> {
+        sqlx::query!(
+            "DELETE FROM user_sessions WHERE user = ? AND last_seen < ?;",
+            user,
+            cutoff
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, user: UserId) -> Result<Vec<SessionRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            SessionRecord,
+            "SELECT id, user_agent, created, last_seen
+                FROM user_sessions
+                WHERE user = ?
+                ORDER BY last_seen DESC;",
+            user
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn remove(&self, user: UserId, id: &str) -> Result<bool, DbError> {
+        Ok(sqlx::query_scalar!(
+            "DELETE FROM user_sessions
+                WHERE user = ? AND id = ?
+                RETURNING id;",
+            user,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some())
+    }
+
+    async fn remove_all(&self, user: UserId) -> Result<Vec<String>, DbError> {
+        Ok(sqlx::query_scalar!(
+            "DELETE FROM user_sessions
+                WHERE user = ?
+                RETURNING id;",
+            user
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
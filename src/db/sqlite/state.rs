@@ -0,0 +1,71 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+
+use crate::services::{state::StateRepository, DbError};
+
+#[derive(Clone)]
+pub struct SqliteStateRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteStateRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl StateRepository for SqliteStateRepository {
+    async fn insert(
+        &self,
+        namespace: &str,
+        value: &str,
+        generated: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO states (namespace, value, generated)
+                VALUES (?, ?, ?);",
+            namespace,
+            value,
+            generated
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn redeem(
+        &self,
+        namespace: &str,
+        value: &str,
+        redeemed: DateTime<Utc>,
+        cutoff: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        Ok(sqlx::query!(
+            "UPDATE states
+                SET redeemed = ?
+                WHERE namespace = ? AND value = ? AND redeemed IS NULL AND generated > ?
+                RETURNING value;",
+            redeemed,
+            namespace,
+            value,
+            cutoff
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some())
+    }
+
+    async fn delete_expired(&self, cutoff: DateTime<Utc>) -> Result<u64, DbError> {
+        Ok(sqlx::query!(
+            "DELETE FROM states
+                WHERE redeemed IS NOT NULL OR generated <= ?;",
+            cutoff
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+}
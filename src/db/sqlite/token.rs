@@ -0,0 +1,89 @@
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+
+use crate::{
+    services::{
+        token::{TokenRecord, TokenRepository},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct SqliteTokenRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteTokenRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl TokenRepository for SqliteTokenRepository {
+    async fn insert(
+        &self,
+        user: UserId,
+        name: &str,
+        prefix: &str,
+        token_hash: &str,
+    ) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO api_tokens (user, name, prefix, token_hash, created)
+                VALUES (?, ?, ?, ?, DATETIME());",
+            user,
+            name,
+            prefix,
+            token_hash
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, user: UserId) -> Result<Vec<TokenRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            TokenRecord,
+            "SELECT id, name, prefix, created, last_used
+                FROM api_tokens
+                WHERE user = ?
+                ORDER BY created DESC;",
+            user
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn remove(&self, user: UserId, id: i64) -> Result<bool, DbError> {
+        Ok(sqlx::query!(
+            "DELETE FROM api_tokens WHERE user = ? AND id = ?;",
+            user,
+            id
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
+    async fn authenticate(&self, token_hash: &str) -> Result<Option<UserId>, DbError> {
+        Ok(sqlx::query_scalar!(
+            r#"UPDATE api_tokens
+                SET last_used = DATETIME()
+                WHERE token_hash = ?
+                RETURNING user AS "user: UserId";"#,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn remove_all(&self, user: UserId) -> Result<u64, DbError> {
+        Ok(sqlx::query!("DELETE FROM api_tokens WHERE user = ?;", user)
+            .execute(&self.pool)
+            .await?
+            .rows_affected())
+    }
+}
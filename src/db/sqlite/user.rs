@@ -0,0 +1,211 @@
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+
+use crate::{
+    services::{
+        user::{AdjustmentRecord, UserRecord, UserRepository},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct SqliteUserRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteUserRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqliteUserRepository {
+    async fn upsert(&self, provider: &str, identifier: &str, balance: f64) -> Result<i64, DbError> {
+        Ok(sqlx::query_scalar!(
+            "INSERT INTO users (balance, last_login, created, auth_provider, auth_identifier)
+                VALUES (?, DATETIME(), DATETIME(), ?, ?)
+                ON CONFLICT(auth_provider, auth_identifier)
+                    DO UPDATE SET last_login = DATETIME()
+                RETURNING id;",
+            balance,
+            provider,
+            identifier
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    async fn set_email(&self, user: UserId, email: &str) -> Result<(), DbError> {
+        sqlx::query!("UPDATE users SET email = ? WHERE id = ?;", email, user)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn role(&self, user: UserId) -> Result<Option<String>, DbError> {
+        Ok(
+            sqlx::query_scalar!("SELECT role FROM users WHERE id = ?;", user)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn list(&self) -> Result<Vec<UserRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            UserRecord,
+            "SELECT id, auth_provider, auth_identifier, email, balance, role, banned, created, last_login, deleted
+                FROM users
+                ORDER BY id;"
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn find(&self, user: UserId) -> Result<Option<UserRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            UserRecord,
+            "SELECT id, auth_provider, auth_identifier, email, balance, role, banned, created, last_login, deleted
+                FROM users
+                WHERE id = ?;",
+            user
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<UserRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            UserRecord,
+            "SELECT id, auth_provider, auth_identifier, email, balance, role, banned, created, last_login, deleted
+                FROM users
+                WHERE CAST(id AS TEXT) = ? OR auth_identifier LIKE '%' || ? || '%'
+                ORDER BY id
+                LIMIT 50;",
+            query,
+            query
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn adjust_balance(
+        &self,
+        user: UserId,
+        amount: f64,
+        reason: &str,
+        admin: Option<UserId>,
+    ) -> Result<Option<f64>, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        let balance = sqlx::query_scalar!(
+            "UPDATE users SET balance = balance + ? WHERE id = ? RETURNING balance;",
+            amount,
+            user
+        )
+        .fetch_optional(tx.as_mut())
+        .await?;
+
+        if balance.is_some() {
+            sqlx::query!(
+                "INSERT INTO balance_adjustments (user, amount, reason, admin, created)
+                    VALUES (?, ?, ?, ?, DATETIME());",
+                user,
+                amount,
+                reason,
+                admin
+            )
+            .execute(tx.as_mut())
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(balance)
+    }
+
+    async fn adjustments(&self, user: UserId) -> Result<Vec<AdjustmentRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            AdjustmentRecord,
+            "SELECT id, amount, reason, admin, created
+                FROM balance_adjustments
+                WHERE user = ?
+                ORDER BY created DESC, id DESC;",
+            user
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn set_role(&self, user: UserId, role: &str) -> Result<bool, DbError> {
+        Ok(sqlx::query!(
+            "UPDATE users SET role = ? WHERE id = ? AND deleted IS NULL;",
+            role,
+            user
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
+    async fn ban(&self, user: UserId) -> Result<bool, DbError> {
+        Ok(
+            sqlx::query!("UPDATE users SET banned = TRUE WHERE id = ?;", user)
+                .execute(&self.pool)
+                .await?
+                .rows_affected()
+                > 0,
+        )
+    }
+
+    async fn delete(&self, user: UserId) -> Result<bool, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        // The identifier only needs to be unique, so the ID is used in place of it
+        let deleted = sqlx::query!(
+            "UPDATE users
+                SET auth_provider = 'deleted', auth_identifier = CAST(id AS TEXT), email = NULL,
+                    role = 'user', deleted = DATETIME()
+                WHERE id = ? AND deleted IS NULL;",
+            user
+        )
+        .execute(tx.as_mut())
+        .await?
+        .rows_affected()
+            > 0;
+
+        if !deleted {
+            return Ok(false);
+        }
+
+        sqlx::query!("DELETE FROM api_tokens WHERE user = ?;", user)
+            .execute(tx.as_mut())
+            .await?;
+        sqlx::query!("DELETE FROM webhooks WHERE user = ?;", user)
+            .execute(tx.as_mut())
+            .await?;
+        sqlx::query!("DELETE FROM push_subscriptions WHERE user = ?;", user)
+            .execute(tx.as_mut())
+            .await?;
+        sqlx::query!("DELETE FROM notification_preferences WHERE user = ?;", user)
+            .execute(tx.as_mut())
+            .await?;
+        sqlx::query!("DELETE FROM sent_notifications WHERE user = ?;", user)
+            .execute(tx.as_mut())
+            .await?;
+        sqlx::query!(
+            "UPDATE audit_events SET ip = NULL, user_agent = NULL
+                WHERE user = ? AND (ip IS NOT NULL OR user_agent IS NOT NULL);",
+            user
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,254 @@
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlx::{Error, SqlitePool};
+
+use crate::services::{
+    weather::{
+        CachedForecast, CachedWeather, Forecast, Weather, WeatherCode, WeatherRepository,
+        WeatherSource,
+    },
+    DbError,
+};
+
+#[derive(Clone)]
+pub struct SqliteWeatherRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteWeatherRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WeatherRepository for SqliteWeatherRepository {
+    async fn get_day_forecast(
+        &self,
+        date: NaiveDate,
+        retrieval_date: NaiveDate,
+    ) -> Result<Option<Forecast>, DbError> {
+        Ok(sqlx::query_as!(
+            Forecast,
+            r#"SELECT rain, minimum_temperature, maximum_temperature, weather_code,
+                    FALSE AS "stale!: bool"
+                FROM forecasts
+                WHERE date = ? AND DATE(date_retrieved) = ?;"#,
+            date,
+            retrieval_date
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn get_forecast_range(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        retrieval_date: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, Forecast)>, DbError> {
+        struct Row {
+            date: NaiveDate,
+            rain: f64,
+            minimum_temperature: f64,
+            maximum_temperature: f64,
+            weather_code: WeatherCode,
+        }
+
+        // Try pull the forecasts for the day that was generated today
+        Ok(sqlx::query_as!(
+            Row,
+            "SELECT date, rain, minimum_temperature, maximum_temperature, weather_code
+                FROM forecasts
+                WHERE date >= ? AND date <= ? AND DATE(date_retrieved) = ?
+                ORDER BY date;",
+            start_date,
+            end_date,
+            retrieval_date
+        )
+        .map(|result| {
+            (
+                result.date,
+                Forecast {
+                    rain: result.rain,
+                    minimum_temperature: result.minimum_temperature,
+                    maximum_temperature: result.maximum_temperature,
+                    weather_code: result.weather_code,
+                    stale: false,
+                },
+            )
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn get_latest_forecast(&self, date: NaiveDate) -> Result<Option<Forecast>, DbError> {
+        Ok(sqlx::query_as!(
+            Forecast,
+            r#"SELECT rain, minimum_temperature, maximum_temperature, weather_code,
+                    TRUE AS "stale!: bool"
+                FROM forecasts
+                WHERE date = ?
+                ORDER BY date_retrieved DESC
+                LIMIT 1;"#,
+            date
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn save_forecast(&self, date: NaiveDate, forecast: &Forecast) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO forecasts (date, rain, minimum_temperature, maximum_temperature, weather_code)
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT DO NOTHING;",
+            date,
+            forecast.rain,
+            forecast.minimum_temperature,
+            forecast.maximum_temperature,
+            forecast.weather_code,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_cached_forecasts(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<CachedForecast>, DbError> {
+        struct Row {
+            date: NaiveDate,
+            date_retrieved: NaiveDateTime,
+            rain: f64,
+            minimum_temperature: f64,
+            maximum_temperature: f64,
+            weather_code: WeatherCode,
+        }
+
+        Ok(sqlx::query_as!(
+            Row,
+            "SELECT date, date_retrieved, rain, minimum_temperature, maximum_temperature,
+                    weather_code
+                FROM forecasts
+                WHERE date >= ? AND date <= ?
+                ORDER BY date, date_retrieved;",
+            start_date,
+            end_date
+        )
+        .map(|row| CachedForecast {
+            date: row.date,
+            date_retrieved: row.date_retrieved,
+            forecast: Forecast {
+                rain: row.rain,
+                minimum_temperature: row.minimum_temperature,
+                maximum_temperature: row.maximum_temperature,
+                weather_code: row.weather_code,
+                stale: false,
+            },
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn list_cached_weather(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<CachedWeather>, DbError> {
+        Ok(sqlx::query!(
+            r#"SELECT date, date_retrieved, rain, temperature,
+                    source AS "source: WeatherSource", provisional
+                FROM historical_weather
+                WHERE date >= ? AND date <= ?
+                ORDER BY date;"#,
+            start_date,
+            end_date
+        )
+        .map(|row| CachedWeather {
+            date: row.date,
+            date_retrieved: row.date_retrieved,
+            weather: Weather {
+                rain: row.rain,
+                temperature: row.temperature,
+            },
+            source: row.source,
+            provisional: row.provisional,
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn get_historical_weather(&self, date: NaiveDate) -> Result<Option<Weather>, DbError> {
+        Ok(sqlx::query_as!(
+            Weather,
+            "SELECT rain, temperature
+                FROM historical_weather
+                WHERE date = ?",
+            date
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn list_historical_weather(&self) -> Result<Vec<(NaiveDate, Weather)>, DbError> {
+        Ok(sqlx::query!(
+            "SELECT date, rain, temperature
+                FROM historical_weather
+                ORDER BY date;"
+        )
+        .map(|row| {
+            (
+                row.date,
+                Weather {
+                    rain: row.rain,
+                    temperature: row.temperature,
+                },
+            )
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn save_historical_weather(
+        &self,
+        date: NaiveDate,
+        weather: &Weather,
+        source: WeatherSource,
+    ) -> Result<(), DbError> {
+        let provisional = source.is_provisional();
+
+        match sqlx::query!(
+            "INSERT INTO historical_weather (date, temperature, rain, source, provisional)
+                VALUES (?, ?, ?, ?, ?)",
+            date,
+            weather.temperature,
+            weather.rain,
+            source,
+            provisional
+        )
+        .execute(&self.pool)
+        .await
+        {
+            // Weather has already been saved for this day
+            Err(Error::Database(e)) if e.is_unique_violation() => Ok(()),
+            result => {
+                result?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn provisional_dates(&self) -> Result<Vec<NaiveDate>, DbError> {
+        Ok(sqlx::query_scalar!(
+            "SELECT date
+                FROM historical_weather
+                WHERE provisional
+                ORDER BY date;"
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
@@ -0,0 +1,175 @@
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+
+use crate::{
+    services::{
+        webhooks::{DeliveryRecord, OutboxMessage, WebhookRecord, WebhookRepository},
+        DbError,
+    },
+    user::UserId,
+};
+
+#[derive(Clone)]
+pub struct SqliteWebhookRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteWebhookRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for SqliteWebhookRepository {
+    async fn insert(&self, user: UserId, url: &str, secret: &str) -> Result<(), DbError> {
+        sqlx::query!(
+            "INSERT INTO webhooks (user, url, secret, created) VALUES (?, ?, ?, DATETIME());",
+            user,
+            url,
+            secret
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, user: UserId) -> Result<Vec<WebhookRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            WebhookRecord,
+            "SELECT id, url, created
+                FROM webhooks
+                WHERE user = ?
+                ORDER BY created DESC, id DESC;",
+            user
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn remove(&self, user: UserId, id: i64) -> Result<bool, DbError> {
+        Ok(
+            sqlx::query!("DELETE FROM webhooks WHERE id = ? AND user = ?;", id, user)
+                .execute(&self.pool)
+                .await?
+                .rows_affected()
+                > 0,
+        )
+    }
+
+    async fn enqueue(&self, user: UserId, event: &str, payload: &str) -> Result<u64, DbError> {
+        Ok(sqlx::query!(
+            "INSERT INTO webhook_outbox (webhook, event, payload, next_attempt, created)
+                SELECT id, ?, ?, DATETIME(), DATETIME()
+                    FROM webhooks
+                    WHERE user = ?;",
+            event,
+            payload,
+            user
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+
+    async fn claim_due(&self, limit: i64, lease_secs: i64) -> Result<Vec<OutboxMessage>, DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        let messages = sqlx::query_as!(
+            OutboxMessage,
+            "SELECT webhook_outbox.id, webhooks.url, webhooks.secret, webhook_outbox.event,
+                    webhook_outbox.payload, webhook_outbox.attempts
+                FROM webhook_outbox
+                INNER JOIN webhooks ON webhooks.id = webhook_outbox.webhook
+                WHERE webhook_outbox.completed IS NULL
+                    AND webhook_outbox.next_attempt <= DATETIME()
+                ORDER BY webhook_outbox.next_attempt
+                LIMIT ?;",
+            limit
+        )
+        .fetch_all(tx.as_mut())
+        .await?;
+
+        let lease = format!("+{lease_secs} seconds");
+        for message in &messages {
+            sqlx::query!(
+                "UPDATE webhook_outbox SET next_attempt = DATETIME('now', ?) WHERE id = ?;",
+                lease,
+                message.id
+            )
+            .execute(tx.as_mut())
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(messages)
+    }
+
+    async fn record_attempt(
+        &self,
+        message: i64,
+        status_code: Option<i64>,
+        error: Option<&str>,
+        retry_secs: Option<i64>,
+    ) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            "INSERT INTO webhook_deliveries (message, attempted, status_code, error)
+                VALUES (?, DATETIME(), ?, ?);",
+            message,
+            status_code,
+            error
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        if let Some(retry_secs) = retry_secs {
+            let retry = format!("+{retry_secs} seconds");
+            sqlx::query!(
+                "UPDATE webhook_outbox
+                    SET attempts = attempts + 1, next_attempt = DATETIME('now', ?)
+                    WHERE id = ?;",
+                retry,
+                message
+            )
+            .execute(tx.as_mut())
+            .await?;
+        } else {
+            let delivered = error.is_none();
+            sqlx::query!(
+                "UPDATE webhook_outbox
+                    SET attempts = attempts + 1, completed = DATETIME(), delivered = ?
+                    WHERE id = ?;",
+                delivered,
+                message
+            )
+            .execute(tx.as_mut())
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn deliveries(&self, user: UserId, limit: i64) -> Result<Vec<DeliveryRecord>, DbError> {
+        Ok(sqlx::query_as!(
+            DeliveryRecord,
+            "SELECT webhook_outbox.webhook, webhook_outbox.event, webhook_deliveries.attempted,
+                    webhook_deliveries.status_code, webhook_deliveries.error
+                FROM webhook_deliveries
+                INNER JOIN webhook_outbox ON webhook_outbox.id = webhook_deliveries.message
+                INNER JOIN webhooks ON webhooks.id = webhook_outbox.webhook
+                WHERE webhooks.user = ?
+                ORDER BY webhook_deliveries.attempted DESC, webhook_deliveries.id DESC
+                LIMIT ?;",
+            user,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
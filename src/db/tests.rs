@@ -0,0 +1,410 @@
+//! Every repository method, run against a real database. The Postgres queries aren't checked at
+//! compile time, so this is what catches mistakes in them. SQLite is always tested against a
+//! temporary database, and Postgres is tested when `TEST_POSTGRES_URL` points at an empty one.
+
+use std::{env, net::Ipv4Addr, process};
+
+use chrono::{Duration, NaiveDate, Utc};
+
+use crate::{
+    services::{
+        audit::{AuditEvent, AuditFilter, AuditKind},
+        bet::{Bet, BetOutcome, BetRecord, Payout},
+        weather::{Forecast, Weather, WeatherCode, WeatherSource},
+    },
+    user::UserId,
+};
+
+use super::{Database, Repositories};
+
+#[tokio::test]
+async fn sqlite_repositories() {
+    let path = env::temp_dir().join(format!("cloud-casino-test-{}.sqlite", process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let database = Database::connect(&format!("sqlite://{}", path.display()))
+        .await
+        .unwrap();
+    database.migrate().await.unwrap();
+
+    exercise(&database.repositories()).await;
+    audit_append_only(&database).await;
+
+    database.close().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn postgres_repositories() {
+    let Ok(url) = env::var("TEST_POSTGRES_URL") else {
+        eprintln!("skipping, as `TEST_POSTGRES_URL` isn't set");
+        return;
+    };
+
+    let database = Database::connect(&url).await.unwrap();
+    database.migrate().await.unwrap();
+
+    exercise(&database.repositories()).await;
+    audit_append_only(&database).await;
+
+    database.close().await;
+}
+
+async fn exercise(repositories: &Repositories) {
+    let user = users(repositories).await;
+
+    weather(repositories).await;
+    bets(repositories, user).await;
+    jobs(repositories).await;
+    notifications(repositories, user).await;
+    push(repositories, user).await;
+    sessions(repositories, user).await;
+    states(repositories).await;
+    tokens(repositories, user).await;
+    webhooks(repositories, user).await;
+    audit(repositories, user).await;
+
+    // Removes the user's data, so must be last
+    delete_user(repositories, user).await;
+}
+
+fn bet(date: NaiveDate) -> BetRecord {
+    BetRecord::new(
+        date,
+        Bet {
+            temperature: 20.0,
+            range: 2.0,
+            rain: false,
+            wager: 10.0,
+        },
+        Payout {
+            rain: 5.0,
+            temperature: 15.0,
+        },
+    )
+}
+
+async fn users(repositories: &Repositories) -> UserId {
+    let db = &repositories.users;
+
+    let id = db.upsert("test", "alice", 100.0).await.unwrap();
+    assert_eq!(db.upsert("test", "alice", 100.0).await.unwrap(), id);
+    let user = UserId::from(id);
+
+    db.set_email(user, "alice@example.com").await.unwrap();
+    assert_eq!(db.role(user).await.unwrap().as_deref(), Some("user"));
+    assert!(db
+        .list()
+        .await
+        .unwrap()
+        .iter()
+        .any(|record| record.id == id));
+    assert_eq!(
+        db.find(user).await.unwrap().unwrap().email.as_deref(),
+        Some("alice@example.com")
+    );
+    assert!(db
+        .search("alic")
+        .await
+        .unwrap()
+        .iter()
+        .any(|record| record.id == id));
+
+    assert_eq!(
+        db.adjust_balance(user, 5.0, "test", None).await.unwrap(),
+        Some(105.0)
+    );
+    assert_eq!(db.adjustments(user).await.unwrap().len(), 1);
+
+    assert!(db.set_role(user, "admin").await.unwrap());
+    assert!(db.set_role(user, "user").await.unwrap());
+
+    user
+}
+
+async fn weather(repositories: &Repositories) {
+    let db = &repositories.weather;
+    let today = Utc::now().date_naive();
+    let date = today + Duration::days(2);
+
+    db.save_forecast(
+        date,
+        &Forecast {
+            rain: 0.2,
+            minimum_temperature: 12.0,
+            maximum_temperature: 24.0,
+            weather_code: WeatherCode::Cloud,
+            stale: false,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(db.get_day_forecast(date, today).await.unwrap().is_some());
+    assert_eq!(
+        db.get_forecast_range(today, date, today)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(db.get_latest_forecast(date).await.unwrap().unwrap().stale);
+    assert_eq!(
+        db.list_cached_forecasts(today, date).await.unwrap().len(),
+        1
+    );
+
+    let past = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+    db.save_historical_weather(
+        past,
+        &Weather {
+            rain: false,
+            temperature: 20.0,
+        },
+        WeatherSource::Forecast,
+    )
+    .await
+    .unwrap();
+    assert!(db.get_historical_weather(past).await.unwrap().is_some());
+    assert_eq!(db.list_historical_weather().await.unwrap().len(), 1);
+    assert!(db.list_cached_weather(past, past).await.unwrap()[0].provisional);
+    assert_eq!(db.provisional_dates().await.unwrap(), vec![past]);
+}
+
+async fn bets(repositories: &Repositories, user: UserId) {
+    let db = &repositories.bets;
+    let future = Utc::now().date_naive() + Duration::days(2);
+    let past = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+
+    let balance = db.get_balance(user).await.unwrap();
+    assert!(db.upsert_bet(user, &bet(future)).await.unwrap());
+    assert_eq!(db.get_balance(user).await.unwrap(), balance - 10.0);
+    assert!(db.find_bet(user, future).await.unwrap().is_some());
+    assert!(db.delete_bet(user, future).await.unwrap().is_some());
+    assert_eq!(db.get_balance(user).await.unwrap(), balance);
+
+    assert!(db.upsert_bet(user, &bet(past)).await.unwrap());
+    assert_eq!(db.ready_bets(user).await.unwrap().len(), 1);
+    assert_eq!(db.unsettled_bets(past).await.unwrap()[0].0, user);
+
+    db.record_payout(
+        user,
+        past,
+        &BetOutcome {
+            rain: true,
+            temperature: true,
+            payout: 20.0,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(db.ready_bets(user).await.unwrap().is_empty());
+    assert!(db.history(user).await.unwrap()[0].1.is_some());
+
+    // The temperature was outside of the bet's range after all
+    let resettled = db
+        .resettle(
+            past,
+            &Weather {
+                rain: false,
+                temperature: 30.0,
+            },
+            WeatherSource::Archive,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(resettled, vec![(user, -15.0)]);
+
+    assert_eq!(db.void_bets(past, "test", None).await.unwrap().len(), 1);
+    assert!(db.history(user).await.unwrap().is_empty());
+    assert_eq!(db.get_balance(user).await.unwrap(), balance);
+}
+
+async fn jobs(repositories: &Repositories) {
+    let db = &repositories.jobs;
+
+    assert!(db.lock("test", Duration::minutes(5)).await.unwrap());
+    assert!(!db.lock("test", Duration::minutes(5)).await.unwrap());
+    assert!(db.lock("other", Duration::zero()).await.unwrap());
+    assert!(db.lock("other", Duration::zero()).await.unwrap());
+
+    let id = db.start_run("test").await.unwrap();
+    db.finish_run(id, true, "done").await.unwrap();
+    let run = db
+        .last_runs()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|run| run.job == "test")
+        .unwrap();
+    assert_eq!(run.succeeded, Some(true));
+
+    assert!(db
+        .dates_missing_weather(NaiveDate::from_ymd_opt(2000, 1, 1).unwrap())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+async fn notifications(repositories: &Repositories, user: UserId) {
+    let db = &repositories.notifications;
+    let date = Utc::now().date_naive();
+
+    db.set_preference(user, "test", true).await.unwrap();
+    assert_eq!(
+        db.preferences(user).await.unwrap(),
+        vec![("test".to_string(), true)]
+    );
+
+    assert_eq!(
+        db.recipients("test", false, date).await.unwrap()[0].user,
+        user
+    );
+    db.record_sent(user, "test", date).await.unwrap();
+    assert!(db.recipients("test", false, date).await.unwrap().is_empty());
+}
+
+async fn push(repositories: &Repositories, user: UserId) {
+    let db = &repositories.push;
+    let endpoint = "https://push.example.com/test";
+
+    db.subscribe(user, endpoint, "key", "auth").await.unwrap();
+    db.subscribe(user, endpoint, "key", "auth").await.unwrap();
+    assert_eq!(db.list(user).await.unwrap().len(), 1);
+    assert!(db.unsubscribe(user, endpoint).await.unwrap());
+
+    db.subscribe(user, endpoint, "key", "auth").await.unwrap();
+    db.remove(db.list(user).await.unwrap()[0].id).await.unwrap();
+    assert!(db.list(user).await.unwrap().is_empty());
+}
+
+async fn sessions(repositories: &Repositories, user: UserId) {
+    let db = &repositories.sessions;
+
+    assert!(!db.touch("first", user, Some("test")).await.unwrap());
+    assert!(!db.exists("first", user).await.unwrap());
+    db.insert("first", user, Some("test")).await.unwrap();
+    assert!(db.exists("first", user).await.unwrap());
+    assert!(db.touch("first", user, Some("test")).await.unwrap());
+    db.delete_inactive(user, Utc::now().naive_utc() - Duration::days(1))
+        .await
+        .unwrap();
+    assert_eq!(db.list(user).await.unwrap().len(), 1);
+    assert!(db.remove(user, "first").await.unwrap());
+    assert!(!db.exists("first", user).await.unwrap());
+
+    db.insert("second", user, None).await.unwrap();
+    assert_eq!(
+        db.remove_all(user).await.unwrap(),
+        vec!["second".to_string()]
+    );
+}
+
+async fn states(repositories: &Repositories) {
+    let db = &repositories.states;
+    let now = Utc::now();
+
+    db.insert("test", "value", now).await.unwrap();
+    assert!(db
+        .redeem("test", "value", now, now - Duration::hours(1))
+        .await
+        .unwrap());
+    assert!(!db
+        .redeem("test", "value", now, now - Duration::hours(1))
+        .await
+        .unwrap());
+    assert_eq!(db.delete_expired(now).await.unwrap(), 1);
+}
+
+async fn tokens(repositories: &Repositories, user: UserId) {
+    let db = &repositories.tokens;
+
+    db.insert(user, "test", "cc_test", "first").await.unwrap();
+    assert_eq!(db.authenticate("first").await.unwrap(), Some(user));
+    let token = db.list(user).await.unwrap().remove(0);
+    assert!(token.last_used.is_some());
+    assert!(db.remove(user, token.id).await.unwrap());
+
+    db.insert(user, "test", "cc_test", "second").await.unwrap();
+    assert_eq!(db.remove_all(user).await.unwrap(), 1);
+    assert_eq!(db.authenticate("second").await.unwrap(), None);
+}
+
+async fn webhooks(repositories: &Repositories, user: UserId) {
+    let db = &repositories.webhooks;
+
+    db.insert(user, "https://example.com/hook", "secret")
+        .await
+        .unwrap();
+    let webhook = db.list(user).await.unwrap().remove(0);
+
+    assert_eq!(db.enqueue(user, "test", "{}").await.unwrap(), 1);
+    let message = db.claim_due(10, 60).await.unwrap().remove(0);
+    assert!(db.claim_due(10, 60).await.unwrap().is_empty());
+
+    db.record_attempt(message.id, None, Some("timed out"), Some(0))
+        .await
+        .unwrap();
+    db.record_attempt(message.id, Some(200), None, None)
+        .await
+        .unwrap();
+    assert_eq!(db.deliveries(user, 10).await.unwrap().len(), 2);
+
+    assert!(db.remove(user, webhook.id).await.unwrap());
+}
+
+async fn audit(repositories: &Repositories, user: UserId) {
+    let db = &repositories.audit;
+
+    db.insert(&AuditEvent {
+        ip: Some(Ipv4Addr::LOCALHOST.into()),
+        user_agent: Some("test".to_string()),
+        ..AuditEvent::new(AuditKind::Login, Some(user), "test")
+    })
+    .await
+    .unwrap();
+
+    let events = db
+        .list(
+            &AuditFilter {
+                user: Some(user),
+                kind: Some(AuditKind::Login),
+            },
+            10,
+        )
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].ip.as_deref(), Some("127.0.0.1"));
+}
+
+async fn delete_user(repositories: &Repositories, user: UserId) {
+    let db = &repositories.users;
+
+    assert!(db.ban(user).await.unwrap());
+    assert!(db.delete(user).await.unwrap());
+    assert!(!db.delete(user).await.unwrap());
+    assert!(db.find(user).await.unwrap().unwrap().deleted.is_some());
+}
+
+/// Audit events can only be added, other than clearing the client details of deleted users, even
+/// when bypassing the repository.
+async fn audit_append_only(database: &Database) {
+    let changes = [
+        ("UPDATE audit_events SET details = 'changed';", false),
+        ("DELETE FROM audit_events;", false),
+        (
+            "UPDATE audit_events SET ip = NULL, user_agent = NULL;",
+            true,
+        ),
+    ];
+
+    for (query, allowed) in changes {
+        let result = match database {
+            Database::Sqlite(pool) => sqlx::query(query).execute(pool).await.map(|_| ()),
+            Database::Postgres(pool) => sqlx::query(query).execute(pool).await.map(|_| ()),
+        };
+
+        assert_eq!(result.is_ok(), allowed, "{query}");
+    }
+}
@@ -0,0 +1,26 @@
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+
+use crate::Ctx;
+
+/// The server is running and able to handle requests.
+async fn live() -> &'static str {
+    "ok"
+}
+
+/// The server is able to reach the database, and so is ready to receive traffic.
+async fn ready(State(ctx): State<Ctx>) -> Result<&'static str, (StatusCode, &'static str)> {
+    match ctx.db.ping().await {
+        Ok(_) => Ok("ok"),
+        Err(e) => {
+            tracing::warn!("readiness check failed: {e}");
+            Err((StatusCode::SERVICE_UNAVAILABLE, "database unavailable"))
+        }
+    }
+}
+
+pub fn init() -> Router<Ctx> {
+    Router::new()
+        .route("/", get(ready))
+        .route("/ready", get(ready))
+        .route("/live", get(live))
+}
@@ -1,67 +1,85 @@
+mod api;
 mod app;
+mod cli;
+mod config;
+mod db;
+mod health;
+mod security;
+mod server;
 mod services;
+mod telemetry;
 mod user;
 
-use std::{env, net::Ipv4Addr, str::FromStr};
+use std::{process, sync::Arc, time::Duration};
 
-use axum::{http::HeaderValue, routing::get, Router};
+use axum::{http::HeaderValue, middleware, routing::get, Router};
+use cli::Command;
+use config::Config;
+use db::Database;
 use reqwest::header::USER_AGENT;
-use services::Services;
-use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
-use tower_http::services::ServeDir;
-use tower_sessions::{
-    cookie::{time, SameSite},
-    Expiry, SessionManagerLayer,
+use server::Server;
+use services::{jobs::Job, session::SESSION_INACTIVITY, Services};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tower::ServiceBuilder;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    trace::{DefaultOnResponse, TraceLayer},
 };
-use tower_sessions_sqlx_store::SqliteStore;
+use tower_sessions::{cookie::SameSite, ExpiredDeletion, Expiry, SessionManagerLayer};
 
 const MELBOURNE: (f64, f64) = (-37.814, 144.9633);
 
 #[derive(Clone)]
 pub struct Ctx {
-    pub db: SqlitePool,
+    pub db: Database,
     pub services: Services,
+    pub config: Arc<Config>,
 }
 
 #[tokio::main]
 async fn main() {
-    let connection_string = env::var("DATABASE_URL")
-        .expect("`DATABASE_URL` environment variable must contain a connection string");
-    let port = env::var("PORT")
-        .expect("`PORT` environment variable must contain a valid port")
-        .parse::<u16>()
-        .expect("provided port must be between 1 and 65535");
-    let static_dir = env::var("STATIC_DIR")
-        .expect("`STATIC_DIR` environment variable must be path to static directory");
+    let command = match Command::parse(std::env::args().skip(1)) {
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::PushGenerateKey) => {
+            // Doesn't need any config, so that it can be run before the config is written
+            println!("{}", cli::generate_push_key());
+            return;
+        }
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{}", cli::USAGE);
+            process::exit(2);
+        }
+    };
 
-    println!("starting server on port {port}, serving files from {static_dir}, db at {connection_string}");
+    telemetry::init_tracing();
 
-    // DB
-    let pool = SqlitePool::connect_with(
-        SqliteConnectOptions::from_str(&connection_string)
-            .unwrap()
-            .create_if_missing(true),
-    )
-    .await
-    .unwrap();
-
-    // Run migrations
-    sqlx::migrate!().run(&pool).await.unwrap();
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            tracing::error!("{e}");
+            process::exit(1);
+        }
+    };
 
-    // Set up sessions
-    let session_store = SqliteStore::new(pool.clone());
-    session_store.migrate().await.unwrap();
-
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_secure(true)
-        .with_same_site(SameSite::Lax)
-        .with_http_only(true)
-        .with_expiry(Expiry::AtDateTime(time::OffsetDateTime::new_utc(
-            time::Date::from_calendar_date(2099, time::Month::December, 31).unwrap(),
-            time::Time::MIDNIGHT,
-        )));
+    match command {
+        Command::Serve => serve(config).await,
+        command => {
+            if let Err(e) = cli::run(command, &config).await {
+                tracing::error!("{e}");
+                process::exit(1);
+            }
+        }
+    }
+}
 
-    let reqwest_client = reqwest::Client::builder()
+/// Client used for all outgoing requests, identifying the application.
+pub fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
         .default_headers(
             [(
                 USER_AGENT,
@@ -76,20 +94,167 @@ async fn main() {
             .collect(),
         )
         .build()
-        .unwrap();
+        .unwrap()
+}
+
+async fn serve(config: Arc<Config>) {
+    let metrics = telemetry::init_metrics();
+
+    let static_dir = &config.server.static_dir;
+    let connection_string = &config.database.url;
+
+    tracing::info!(
+        "starting server, serving files from {}, db at {}",
+        static_dir.display(),
+        db::redact_url(connection_string)
+    );
+
+    if let Err(e) = app::assets::load(static_dir) {
+        tracing::error!("{e}");
+        process::exit(1);
+    }
+
+    let (server, metrics_server) = match tokio::try_join!(
+        Server::bind(&config.server),
+        Server::bind_metrics(&config.server)
+    ) {
+        Ok(servers) => servers,
+        Err(e) => {
+            tracing::error!("{e}");
+            process::exit(1);
+        }
+    };
+
+    let shutdown = CancellationToken::new();
+    tokio::task::spawn(server::shutdown_signal(shutdown.clone()));
+
+    // Requests and jobs that must be allowed to finish before exiting
+    let tasks = TaskTracker::new();
+
+    // DB
+    let database = match Database::connect(connection_string).await {
+        Ok(database) => database,
+        Err(e) => {
+            tracing::error!("unable to connect to the database: {e}");
+            process::exit(1);
+        }
+    };
+
+    // Run migrations
+    if let Err(e) = database.migrate().await {
+        tracing::error!("unable to run migrations: {e}");
+        process::exit(1);
+    }
+
+    let repositories = database.repositories();
+
+    // Set up sessions
+    let session_store = repositories.session_store.clone();
+
+    // Periodically clear out expired sessions
+    tokio::task::spawn(
+        session_store
+            .clone()
+            .continuously_delete_expired(tokio::time::Duration::from_secs(60 * 60)),
+    );
+
+    let session_layer = SessionManagerLayer::new(session_store)
+        .with_secure(true)
+        .with_same_site(SameSite::Lax)
+        .with_http_only(true)
+        .with_expiry(Expiry::OnInactivity(SESSION_INACTIVITY));
+
+    let services = Services::new(&repositories, http_client(), &config);
+
+    // Periodically clear out used and expired state values
+    tokio::task::spawn(
+        services
+            .state
+            .clone()
+            .continuously_delete_expired(tokio::time::Duration::from_secs(60 * 10)),
+    );
+
+    // Periodically forget clients that are no longer rate limited
+    tokio::task::spawn(
+        services
+            .rate_limit
+            .clone()
+            .continuously_delete_expired(tokio::time::Duration::from_secs(60 * 10)),
+    );
+
+    // Deliver webhooks in the background
+    tasks.spawn(
+        services
+            .webhooks
+            .clone()
+            .continuously_deliver(shutdown.clone()),
+    );
+
+    // Run scheduled jobs in the background
+    for job in Job::ALL {
+        tasks.spawn(
+            services
+                .jobs
+                .clone()
+                .continuously_run(job, shutdown.clone()),
+        );
+    }
+
+    let events = services.events.clone();
 
     let app = Router::new()
-        .merge(app::init())
-        .route("/health", get(|| async { "ok" }))
-        .fallback_service(ServeDir::new(&static_dir))
+        .merge(app::init(&services.rate_limit))
+        .nest("/api", api::init(&services))
+        .nest("/health", health::init())
+        .route_layer(middleware::from_fn(telemetry::track_requests))
+        .fallback_service(ServeDir::new(static_dir))
         .layer(session_layer)
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::new(
+                    telemetry::REQUEST_ID_HEADER,
+                    MakeRequestUuid,
+                ))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(telemetry::make_span)
+                        .on_response(DefaultOnResponse::new().level(tracing::Level::INFO)),
+                )
+                .layer(PropagateRequestIdLayer::new(telemetry::REQUEST_ID_HEADER))
+                .layer(middleware::from_fn(security::headers)),
+        )
         .with_state(Ctx {
-            db: pool.clone(),
-            services: Services::new(pool, reqwest_client),
+            db: database.clone(),
+            services,
+            config: config.clone(),
         });
 
-    let listener = tokio::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))
+    // Metrics are served separately, so that they aren't reachable through the app
+    let metrics_app = Router::new().route("/metrics", get(move || async move { metrics.render() }));
+    let serve_metrics = async {
+        if let Some(metrics_server) = metrics_server {
+            metrics_server
+                .serve(metrics_app, shutdown.clone(), &tasks)
+                .await;
+        }
+    };
+
+    tokio::join!(server.serve(app, shutdown.clone(), &tasks), serve_metrics);
+
+    // Event streams never finish on their own, so end them to let their connections close
+    events.close();
+
+    tasks.close();
+    let shutdown_timeout = Duration::from_millis(config.server.shutdown_timeout_ms);
+    if tokio::time::timeout(shutdown_timeout, tasks.wait())
         .await
-        .unwrap();
-    axum::serve(listener, app).await.unwrap();
+        .is_err()
+    {
+        tracing::warn!(
+            "{} requests and jobs still running after {shutdown_timeout:?}, exiting anyway",
+            tasks.len()
+        );
+    }
+
+    database.close().await;
 }
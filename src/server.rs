@@ -0,0 +1,295 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    net::SocketAddr,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::{extract::ConnectInfo, http::Request, Router};
+use hyper::{body::Incoming, server::conn::http1};
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, UnixListener},
+    signal::{self, unix::SignalKind},
+    task::JoinSet,
+};
+use tokio_rustls::{rustls, TlsAcceptor};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tower::ServiceExt;
+
+use crate::config::{BindAddress, ServerConfig, TlsConfig};
+
+/// Delay before accepting again after a failed accept, so that running out of file descriptors
+/// doesn't turn into a busy loop.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    #[error("unable to listen on {address}: {source}")]
+    Bind {
+        address: BindAddress,
+        source: io::Error,
+    },
+
+    #[error("unable to read TLS file {}: {source}", path.display())]
+    ReadTls { path: PathBuf, source: io::Error },
+
+    #[error("no private key found in {}", .0.display())]
+    MissingPrivateKey(PathBuf),
+
+    #[error("invalid TLS configuration: {0}")]
+    InvalidTls(#[from] rustls::Error),
+}
+
+/// Anything a connection can be served over.
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T> Io for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    async fn bind(address: &BindAddress) -> io::Result<Self> {
+        match address {
+            BindAddress::Tcp(address) => Ok(Self::Tcp(TcpListener::bind(address).await?)),
+            BindAddress::Unix(path) => {
+                // A socket left behind by a previous run would otherwise prevent binding, but
+                // anything else at the path is left alone
+                match std::fs::symlink_metadata(path) {
+                    Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+                    Ok(_) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            "path exists and isn't a socket",
+                        ))
+                    }
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    Err(_) => {}
+                }
+
+                Ok(Self::Unix(UnixListener::bind(path)?, path.clone()))
+            }
+        }
+    }
+
+    /// Accept a connection, along with the address of the peer if it has one.
+    async fn accept(&self) -> io::Result<(Box<dyn Io>, Option<SocketAddr>)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                stream.set_nodelay(true)?;
+
+                Ok((Box::new(stream), Some(peer)))
+            }
+            Listener::Unix(listener, _) => {
+                let (stream, _) = listener.accept().await?;
+
+                Ok((Box::new(stream), None))
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Server listening on every configured address, ready to serve the app.
+pub struct Server {
+    listeners: Vec<(BindAddress, Listener)>,
+    tls: Option<TlsAcceptor>,
+}
+
+impl Server {
+    /// Listen on every configured address, failing if any of them can't be bound.
+    pub async fn bind(config: &ServerConfig) -> Result<Self, ServerError> {
+        let tls = config.tls.as_ref().map(load_tls).transpose()?;
+
+        Self::bind_addresses(config.addresses(), tls).await
+    }
+
+    /// Listen on the metrics address, if there is one. Metrics are always served over plain HTTP.
+    pub async fn bind_metrics(config: &ServerConfig) -> Result<Option<Self>, ServerError> {
+        match &config.metrics_listen {
+            Some(address) => Ok(Some(
+                Self::bind_addresses(vec![address.clone()], None).await?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn bind_addresses(
+        addresses: Vec<BindAddress>,
+        tls: Option<TlsAcceptor>,
+    ) -> Result<Self, ServerError> {
+        let mut listeners = Vec::new();
+        for address in addresses {
+            let listener = Listener::bind(&address)
+                .await
+                .map_err(|source| ServerError::Bind {
+                    address: address.clone(),
+                    source,
+                })?;
+
+            listeners.push((address, listener));
+        }
+
+        Ok(Self { listeners, tls })
+    }
+
+    /// Serve the app until `shutdown` is cancelled. Each connection is spawned on `tasks`, and
+    /// will be gracefully closed once its in-flight request completes.
+    pub async fn serve(self, app: Router, shutdown: CancellationToken, tasks: &TaskTracker) {
+        let mut accept_loops = JoinSet::new();
+
+        for (address, listener) in self.listeners {
+            let scheme = if self.tls.is_some() { "https" } else { "http" };
+            tracing::info!("listening on {address} ({scheme})");
+
+            accept_loops.spawn(accept_loop(
+                listener,
+                self.tls.clone(),
+                app.clone(),
+                shutdown.clone(),
+                tasks.clone(),
+            ));
+        }
+
+        while accept_loops.join_next().await.is_some() {}
+    }
+}
+
+async fn accept_loop(
+    listener: Listener,
+    tls: Option<TlsAcceptor>,
+    app: Router,
+    shutdown: CancellationToken,
+    tasks: TaskTracker,
+) {
+    loop {
+        let (io, peer) = tokio::select! {
+            result = listener.accept() => match result {
+                Ok(connection) => connection,
+                Err(e) => {
+                    tracing::warn!("unable to accept connection: {e}");
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            },
+            _ = shutdown.cancelled() => break,
+        };
+
+        tasks.spawn(serve_connection(
+            io,
+            peer,
+            tls.clone(),
+            app.clone(),
+            shutdown.clone(),
+        ));
+    }
+}
+
+async fn serve_connection(
+    io: Box<dyn Io>,
+    peer: Option<SocketAddr>,
+    tls: Option<TlsAcceptor>,
+    app: Router,
+    shutdown: CancellationToken,
+) {
+    let io: Box<dyn Io> = match tls {
+        Some(tls) => {
+            let handshake = tokio::select! {
+                result = tls.accept(io) => result,
+                _ = shutdown.cancelled() => return,
+            };
+
+            match handshake {
+                Ok(stream) => Box::new(stream),
+                Err(e) => {
+                    tracing::debug!("TLS handshake failed: {e}");
+                    return;
+                }
+            }
+        }
+        None => io,
+    };
+
+    // Make the peer address available to handlers, as `axum::serve` would
+    let service = app.map_request(move |mut request: Request<Incoming>| {
+        if let Some(peer) = peer {
+            request.extensions_mut().insert(ConnectInfo(peer));
+        }
+
+        request
+    });
+
+    let connection = http1::Builder::new()
+        .serve_connection(TokioIo::new(io), TowerToHyperService::new(service))
+        .with_upgrades();
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = shutdown.cancelled() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+
+    if let Err(e) = result {
+        tracing::debug!("connection closed with error: {e}");
+    }
+}
+
+fn load_tls(config: &TlsConfig) -> Result<TlsAcceptor, ServerError> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|source| ServerError::ReadTls {
+                path: path.to_path_buf(),
+                source,
+            })
+    };
+    let read_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| ServerError::ReadTls { path, source }
+    };
+
+    let certificates = rustls_pemfile::certs(&mut open(&config.certificate)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_error(&config.certificate))?;
+    let private_key = rustls_pemfile::private_key(&mut open(&config.private_key)?)
+        .map_err(read_error(&config.private_key))?
+        .ok_or_else(|| ServerError::MissingPrivateKey(config.private_key.clone()))?;
+
+    let mut tls = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certificates, private_key)?;
+    tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(tls)))
+}
+
+/// Wait for the process to be asked to stop, then cancel `shutdown`.
+pub async fn shutdown_signal(shutdown: CancellationToken) {
+    let mut terminate =
+        signal::unix::signal(SignalKind::terminate()).expect("SIGTERM handler can be installed");
+
+    tokio::select! {
+        _ = signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+
+    tracing::info!("shutting down, waiting for in-flight requests and jobs");
+    shutdown.cancel();
+}
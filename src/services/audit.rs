@@ -0,0 +1,183 @@
+use std::{net::IpAddr, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::user::UserId;
+
+use super::DbError;
+
+/// Most events that are listed at once.
+const LIST_LIMIT: i64 = 200;
+
+/// Kinds of security- and money-relevant actions that are recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditKind {
+    /// User logged in with a provider
+    Login,
+
+    /// State value (such as from an OAuth callback) was invalid, expired or already used
+    StateRejected,
+
+    /// Bet was placed for a day without one
+    BetPlaced,
+
+    /// Existing bet for a day was replaced
+    BetReplaced,
+
+    /// Bet was settled and paid out
+    Payout,
+
+    /// Balance was adjusted by an admin
+    BalanceAdjusted,
+
+    /// Bet was voided by an admin, refunding the wager
+    BetVoided,
+
+    /// Session was signed out or revoked
+    SessionRevoked,
+
+    /// User deleted their account
+    AccountDeleted,
+
+    /// User was given a different role from the CLI
+    RoleChanged,
+}
+
+impl AuditKind {
+    pub const ALL: [AuditKind; 10] = [
+        AuditKind::Login,
+        AuditKind::StateRejected,
+        AuditKind::BetPlaced,
+        AuditKind::BetReplaced,
+        AuditKind::Payout,
+        AuditKind::BalanceAdjusted,
+        AuditKind::BetVoided,
+        AuditKind::SessionRevoked,
+        AuditKind::AccountDeleted,
+        AuditKind::RoleChanged,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AuditKind::Login => "login",
+            AuditKind::StateRejected => "state_rejected",
+            AuditKind::BetPlaced => "bet_placed",
+            AuditKind::BetReplaced => "bet_replaced",
+            AuditKind::Payout => "payout",
+            AuditKind::BalanceAdjusted => "balance_adjusted",
+            AuditKind::BetVoided => "bet_voided",
+            AuditKind::SessionRevoked => "session_revoked",
+            AuditKind::AccountDeleted => "account_deleted",
+            AuditKind::RoleChanged => "role_changed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+/// Event that is about to be recorded.
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub kind: AuditKind,
+
+    /// User the event concerns, if it's known
+    pub user: Option<UserId>,
+
+    /// Admin that acted on the user's behalf
+    pub actor: Option<UserId>,
+
+    /// Description of what happened
+    pub details: String,
+
+    /// Client that caused the event, if it came from a request
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+impl AuditEvent {
+    /// Event caused by the user themselves, or by the system on their behalf.
+    pub fn new(kind: AuditKind, user: Option<UserId>, details: impl Into<String>) -> Self {
+        Self {
+            kind,
+            user,
+            actor: None,
+            details: details.into(),
+            ip: None,
+            user_agent: None,
+        }
+    }
+}
+
+/// Event, as it appears in the database.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AuditRecord {
+    pub id: i64,
+    pub user: Option<i64>,
+
+    /// Provider identifier of the user, if there is one
+    pub identifier: Option<String>,
+
+    pub actor: Option<i64>,
+    pub kind: String,
+    pub details: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created: NaiveDateTime,
+}
+
+/// Which events to list. Every event matches when nothing is set.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub user: Option<UserId>,
+    pub kind: Option<AuditKind>,
+}
+
+/// Append-only storage for audit events.
+#[async_trait]
+pub trait AuditRepository: Send + Sync {
+    async fn insert(&self, event: &AuditEvent) -> Result<(), DbError>;
+
+    /// List events matching the filter, most recent first.
+    async fn list(&self, filter: &AuditFilter, limit: i64) -> Result<Vec<AuditRecord>, DbError>;
+}
+
+#[derive(Clone)]
+pub struct AuditService {
+    db: Arc<dyn AuditRepository>,
+}
+
+impl AuditService {
+    pub fn new(db: Arc<dyn AuditRepository>) -> Self {
+        Self { db }
+    }
+
+    /// Record an event. Failures are logged rather than returned, so that the action being
+    /// audited isn't undone after it has happened.
+    pub async fn record(&self, event: AuditEvent) {
+        if let Err(e) = self.db.insert(&event).await {
+            tracing::error!(
+                kind = event.kind.name(),
+                user = event.user.map(i64::from),
+                "unable to record audit event: {e}"
+            );
+        }
+    }
+
+    /// The user's own events, most recent first.
+    pub async fn for_user(&self, user: UserId) -> Result<Vec<AuditRecord>, DbError> {
+        self.list(&AuditFilter {
+            user: Some(user),
+            kind: None,
+        })
+        .await
+    }
+
+    /// Events matching the filter, most recent first.
+    pub async fn list(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, DbError> {
+        self.db.list(filter, LIST_LIMIT).await
+    }
+}
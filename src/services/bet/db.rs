@@ -1,12 +1,18 @@
-use chrono::{NaiveDate, Utc};
-use sqlx::SqlitePool;
+use async_trait::async_trait;
+use chrono::NaiveDate;
 
-use crate::user::UserId;
+use crate::{
+    services::{
+        weather::{Weather, WeatherSource},
+        DbError,
+    },
+    user::UserId,
+};
 
 use super::{Bet, BetOutcome, Payout};
 
 /// Entire bet record, as it appears in the database.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct BetRecord {
     /// Date the bet is for
     pub date: NaiveDate,
@@ -76,185 +82,61 @@ impl From<BetRecord> for Payout {
     }
 }
 
-#[derive(Clone)]
-pub struct Db {
-    pool: SqlitePool,
-}
-
-impl Db {
-    pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
-    }
-
-    pub async fn upsert_bet(&self, user: UserId, bet: &BetRecord) {
-        let mut tx = self.pool.begin().await.unwrap();
-
-        // Get the current wager
-        let previous_wager = sqlx::query_scalar!(
-            "SELECT wager
-                FROM bets
-                WHERE user = ? AND date = ?;",
-            user,
-            bet.date
-        )
-        .fetch_optional(tx.as_mut())
-        .await
-        .unwrap()
-        .unwrap_or_default();
-
-        // Insert the new bet
-        sqlx::query!(
-            "INSERT INTO bets (user, date, temperature, range, rain, wager, rain_payout, temperature_payout)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-                ON CONFLICT (user, date) DO UPDATE
-                    SET temperature = ?, range = ?, rain = ?, wager = ?, rain_payout = ?, temperature_payout = ?;",
-            // Insert values
-            user,
-            bet.date,
-            bet.temperature,
-            bet.range,
-            bet.rain,
-            bet.wager,
-            bet.rain_payout,
-            bet.temperature_payout,
-            // Update values
-            bet.temperature,
-            bet.range,
-            bet.rain,
-            bet.wager,
-            bet.rain_payout,
-            bet.temperature_payout,
-        )
-        .execute(tx.as_mut())
-        .await
-        .unwrap();
-
-        // Update the user's balance
-        let d_balance = previous_wager - bet.wager;
-        let balance = sqlx::query_scalar!(
-            "UPDATE users SET balance = balance + ? WHERE id = ? RETURNING balance;",
-            d_balance,
-            user
-        )
-        .fetch_one(tx.as_mut())
-        .await
-        .unwrap();
-
-        if balance >= 0.0 {
-            // Finalise the transaction
-            tx.commit().await.unwrap();
-        } else {
-            tx.rollback().await.unwrap();
-        }
-    }
-
-    pub async fn find_bet(&self, user: UserId, date: NaiveDate) -> Option<BetRecord> {
-        sqlx::query_as!(
-            BetRecord,
-            "SELECT date, temperature, range, rain, wager, rain_payout, temperature_payout
-                FROM bets
-                WHERE user = ? and date = ?;",
-            user,
-            date,
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .unwrap()
-    }
+/// Storage for bets, payouts, and the balance changes that go along with them.
+#[async_trait]
+pub trait BetRepository: Send + Sync {
+    /// Insert or replace a bet, updating the user's balance. Returns whether the user's balance was
+    /// sufficient for the bet to be placed.
+    async fn upsert_bet(&self, user: UserId, bet: &BetRecord) -> Result<bool, DbError>;
 
-    pub async fn record_payout(&self, user: UserId, date: NaiveDate, outcome: &BetOutcome) {
-        // Begin a new transaction
-        let mut tx = self.pool.begin().await.unwrap();
+    /// Remove a bet that hasn't been paid out, refunding the wager to the user. Returns the
+    /// removed bet, if one existed.
+    async fn delete_bet(&self, user: UserId, date: NaiveDate)
+        -> Result<Option<BetRecord>, DbError>;
 
-        // Record the payout
-        sqlx::query!(
-            "INSERT INTO payouts (user, date, rain_correct, temperature_correct)
-                VALUES (?, ?, ?, ?);",
-            user,
-            date,
-            outcome.rain,
-            outcome.temperature
-        )
-        .execute(tx.as_mut())
-        .await
-        .unwrap();
+    async fn find_bet(&self, user: UserId, date: NaiveDate) -> Result<Option<BetRecord>, DbError>;
 
-        sqlx::query!(
-            "UPDATE users
-                -- Perform the actual balance update
-                SET balance = users.balance + d.balance
-                FROM (
-                    -- Build the balance change amount
-                    SELECT (
-                        IFNULL(
-                            -- Select the temperature payout from the bet
-                            (SELECT temperature_payout
-                                FROM bets
-                                -- Only include the temperature payout if it's correct
-                                WHERE ?
-                                    AND date = ?
-                                    AND user = ?),
-                            0
-                        ) + IFNULL(
-                            -- Do the same for the rain payout
-                            (SELECT rain_payout
-                                FROM bets
-                                WHERE ?
-                                    AND date = ?
-                                    AND user = ?),
-                            0
-                        )
-                    ) AS balance
-                ) AS d
-                WHERE id = ?;",
-            // Temperature payout information
-            outcome.temperature,
-            date,
-            user,
-            // Rain payout information
-            outcome.rain,
-            date,
-            user,
-            // User to update
-            user
-        )
-        .execute(tx.as_mut())
-        .await
-        .unwrap();
-
-        tx.commit().await.unwrap();
-    }
+    /// Record the outcome of a bet, adding the payout to the user's balance.
+    async fn record_payout(
+        &self,
+        user: UserId,
+        date: NaiveDate,
+        outcome: &BetOutcome,
+    ) -> Result<(), DbError>;
 
     /// Retrieve all bets that are ready to be paid out.
-    pub async fn ready_bets(&self, user: UserId) -> Vec<BetRecord> {
-        // WARN: This will mix UTC dates with user locale dates
-        let now = Utc::now().date_naive();
-
-        // Select all bets that don't have a corresponding payout
-        sqlx::query_as!(
-            BetRecord,
-            "SELECT date, temperature, range, rain, wager, rain_payout, temperature_payout
-                FROM bets
-                WHERE user = ?
-                    AND date < ?
-                    AND (
-                        SELECT COUNT(*)
-                            FROM payouts
-                            WHERE payouts.date = bets.date
-                                AND payouts.user = bets.user
-                    ) = 0;",
-            user,
-            now
-        )
-        .fetch_all(&self.pool)
-        .await
-        .unwrap()
-    }
-
-    pub async fn get_balance(&self, user: UserId) -> f64 {
-        sqlx::query_scalar!("SELECT balance FROM users WHERE id = ?;", user)
-            .fetch_one(&self.pool)
-            .await
-            .unwrap()
-    }
+    async fn ready_bets(&self, user: UserId) -> Result<Vec<BetRecord>, DbError>;
+
+    /// Retrieve every user's bet for a date that hasn't been paid out.
+    async fn unsettled_bets(&self, date: NaiveDate) -> Result<Vec<(UserId, BetRecord)>, DbError>;
+
+    /// Remove every bet for a date, reversing any payouts and refunding the wagers. Each refund is
+    /// recorded as a balance adjustment with the given reason. Returns the amount refunded to each
+    /// user, which is negative if the payout was larger than the wager.
+    async fn void_bets(
+        &self,
+        date: NaiveDate,
+        reason: &str,
+        admin: Option<UserId>,
+    ) -> Result<Vec<(UserId, f64)>, DbError>;
+
+    /// Replace the provisional observed weather for a date. If the weather changed, the correction
+    /// is recorded and every bet for the date that was paid out is resettled, with the change in
+    /// payout applied to the user's balance as a balance adjustment. Returns the change in payout
+    /// for each resettled bet, or `None` if the date doesn't have provisional weather.
+    async fn resettle(
+        &self,
+        date: NaiveDate,
+        weather: &Weather,
+        source: WeatherSource,
+    ) -> Result<Option<Vec<(UserId, f64)>>, DbError>;
+
+    /// Retrieve every bet the user has placed, along with whether the rain and temperature were
+    /// correct if the bet has been paid out. Most recent bets are first.
+    async fn history(
+        &self,
+        user: UserId,
+    ) -> Result<Vec<(BetRecord, Option<(bool, bool)>)>, DbError>;
+
+    async fn get_balance(&self, user: UserId) -> Result<f64, DbError>;
 }
@@ -1,18 +1,26 @@
 mod db;
 
+use std::sync::Arc;
+
 use chrono::{NaiveDate, Utc};
 use chrono_tz::Australia::Melbourne;
-use futures::StreamExt;
+use futures::TryStreamExt;
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
+use utoipa::ToSchema;
 
-use crate::user::UserId;
+use crate::{config::GameConfig, user::UserId};
 
-use self::db::{BetRecord, Db};
+pub use self::db::{BetRecord, BetRepository};
 
-use super::weather::{Forecast, Weather, WeatherService};
+use super::{
+    audit::{AuditEvent, AuditKind, AuditService},
+    events::{Event, EventService},
+    weather::{Forecast, Weather, WeatherError, WeatherService, WeatherSource},
+    webhooks::{WebhookEvent, WebhookService},
+    DbError,
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Bet {
     /// Temperature that was guessed
     pub temperature: f64,
@@ -32,7 +40,12 @@ impl BetRecord {
         let rain = self.rain == weather.rain;
         let temperature = (self.temperature - weather.temperature).abs() <= self.range;
 
-        return BetOutcome {
+        self.settled_outcome(rain, temperature)
+    }
+
+    /// Build the outcome of the bet, given which parts of the bet were correct.
+    pub fn settled_outcome(&self, rain: bool, temperature: bool) -> BetOutcome {
+        BetOutcome {
             rain,
             temperature,
             payout: [
@@ -42,18 +55,34 @@ impl BetRecord {
             .into_iter()
             .flatten()
             .sum(),
-        };
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct BetOutcome {
     pub rain: bool,
     pub temperature: bool,
     pub payout: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A bet that has been placed, along with its outcome if it has been paid out.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BetHistory {
+    /// Date the bet is for
+    pub date: NaiveDate,
+
+    /// The bet that was placed
+    pub bet: Bet,
+
+    /// Maximum payout of the bet
+    pub payout: Payout,
+
+    /// Outcome of the bet, if it has been paid out
+    pub outcome: Option<BetOutcome>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Payout {
     /// Payout if rain is correct
     pub rain: f64,
@@ -63,30 +92,31 @@ pub struct Payout {
 }
 
 impl Payout {
-    const MAX_TEMPERATURE_MULTIPLIER: f64 = 5.0;
-    const DAY_MULTIPLIER: f64 = 0.2;
-    const RAIN_MULTIPLIER: f64 = 0.75;
-
-    fn day_multiplier(date: NaiveDate) -> f64 {
+    fn day_multiplier(game: &GameConfig, date: NaiveDate) -> f64 {
         // TODO: This must be locale aware, currently is mixing UTC date with local date
-        Self::DAY_MULTIPLIER * (date - Utc::now().date_naive()).num_days() as f64
+        game.day_multiplier * (date - Utc::now().date_naive()).num_days() as f64
     }
 
-    pub fn rain_multiplier(date: NaiveDate) -> f64 {
-        Self::day_multiplier(date) + Self::RAIN_MULTIPLIER
+    pub fn rain_multiplier(game: &GameConfig, date: NaiveDate) -> f64 {
+        Self::day_multiplier(game, date) + game.rain_multiplier
     }
 
-    pub fn temperature_multiplier(date: NaiveDate, bet: &Bet, forecast: &Forecast) -> f64 {
+    pub fn temperature_multiplier(
+        game: &GameConfig,
+        date: NaiveDate,
+        bet: &Bet,
+        forecast: &Forecast,
+    ) -> f64 {
         let x = 1.0 - (bet.range / (forecast.maximum_temperature - forecast.minimum_temperature));
         let y = x.clamp(0.0, 1.0).powf(3.0);
 
-        Self::day_multiplier(date) + (y * Self::MAX_TEMPERATURE_MULTIPLIER)
+        Self::day_multiplier(game, date) + (y * game.max_temperature_multiplier)
     }
 
-    pub fn max_payout(bet: &Bet, date: NaiveDate, forecast: &Forecast) -> Self {
+    pub fn max_payout(game: &GameConfig, bet: &Bet, date: NaiveDate, forecast: &Forecast) -> Self {
         Self {
-            rain: Self::rain_multiplier(date) * bet.wager,
-            temperature: Self::temperature_multiplier(date, &bet, forecast) * bet.wager,
+            rain: Self::rain_multiplier(game, date) * bet.wager,
+            temperature: Self::temperature_multiplier(game, date, bet, forecast) * bet.wager,
         }
     }
 
@@ -95,33 +125,70 @@ impl Payout {
     }
 }
 
-#[derive(Clone, Debug, thiserror::Error)]
+#[derive(Debug, thiserror::Error)]
 pub enum BetError {
-    #[error("cannot create a bet for today")]
-    Today,
-
     #[error("cannot create a bet that's less than $0")]
     NegativeBet,
 
     #[error("temperature range cannot be less than 0")]
     NegativeRange,
+
+    #[error("no bet exists for this date")]
+    NotFound,
+
+    #[error("bets for today or earlier are locked in")]
+    Locked,
+
+    #[error("bets can't be settled until the day is over")]
+    NotFinished,
+
+    #[error("balance is too low to place this bet")]
+    InsufficientBalance,
+
+    #[error(transparent)]
+    Weather(#[from] WeatherError),
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+/// Bets for today or earlier can't be changed, as the weather may already be known.
+fn check_not_locked(date: NaiveDate, today: NaiveDate) -> Result<(), BetError> {
+    if date <= today {
+        return Err(BetError::Locked);
+    }
+
+    Ok(())
 }
 
 #[derive(Clone)]
 pub struct BetService {
     weather_service: WeatherService,
-    db: Db,
+    events: EventService,
+    webhooks: WebhookService,
+    audit: AuditService,
+    db: Arc<dyn BetRepository>,
 }
 
 impl BetService {
-    pub(super) fn new(pool: SqlitePool, weather_service: WeatherService) -> Self {
+    pub(super) fn new(
+        db: Arc<dyn BetRepository>,
+        weather_service: WeatherService,
+        events: EventService,
+        webhooks: WebhookService,
+        audit: AuditService,
+    ) -> Self {
         Self {
             weather_service,
-            db: Db::new(pool),
+            events,
+            webhooks,
+            audit,
+            db,
         }
     }
 
     /// Place a bet for the given user and date with the specified payout.
+    #[tracing::instrument(skip(self, payout))]
     pub async fn place(
         &self,
         user: UserId,
@@ -142,52 +209,249 @@ impl BetService {
         // Determine today's date
         let today = Utc::now().with_timezone(&Melbourne).naive_local().date();
 
-        // Make sure not betting on today, and that the bet amount isn't below zero
-        if date == today {
-            return Err(BetError::Today);
-        }
+        // Make sure not betting on a day that has already started
+        check_not_locked(date, today)?;
+
+        let wager = bet.wager;
+        let replacing = self.db.find_bet(user, date).await?.is_some();
+        let event = if replacing {
+            WebhookEvent::BetReplaced {
+                date,
+                bet: bet.clone(),
+                payout: payout.clone(),
+            }
+        } else {
+            WebhookEvent::BetPlaced {
+                date,
+                bet: bet.clone(),
+                payout: payout.clone(),
+            }
+        };
 
         // Insert the bet into the database
-        self.db
+        if !self
+            .db
             .upsert_bet(user, &BetRecord::new(date, bet, payout))
+            .await?
+        {
+            return Err(BetError::InsufficientBalance);
+        }
+
+        metrics::counter!("bets_placed_total").increment(1);
+        metrics::histogram!("bet_wager_dollars").record(wager);
+
+        let (kind, verb) = if replacing {
+            (AuditKind::BetReplaced, "replaced")
+        } else {
+            (AuditKind::BetPlaced, "placed")
+        };
+        self.audit
+            .record(AuditEvent::new(
+                kind,
+                Some(user),
+                format!("{verb} ${wager:.2} bet for {date}"),
+            ))
             .await;
 
+        self.webhooks.send(user, event).await;
+        self.balance_changed(user).await;
+
         Ok(())
     }
 
+    /// Cancel the bet for the given user and date, refunding the wager.
+    pub async fn cancel(&self, user: UserId, date: NaiveDate) -> Result<Bet, BetError> {
+        // Determine today's date
+        let today = Utc::now().with_timezone(&Melbourne).naive_local().date();
+
+        check_not_locked(date, today)?;
+
+        let bet = self
+            .db
+            .delete_bet(user, date)
+            .await?
+            .ok_or(BetError::NotFound)?;
+
+        self.balance_changed(user).await;
+
+        Ok(bet.into())
+    }
+
     /// Find a bet for the given date.
-    pub async fn find_bet(&self, user: UserId, date: NaiveDate) -> Option<Bet> {
-        self.db.find_bet(user, date).await.map(|bet| bet.into())
+    pub async fn find_bet(&self, user: UserId, date: NaiveDate) -> Result<Option<Bet>, DbError> {
+        Ok(self.db.find_bet(user, date).await?.map(|bet| bet.into()))
     }
 
     // Payout all ready bets for the user
-    pub async fn payout(&self, user: UserId) {
-        let ready_bets = self.db.ready_bets(user).await;
+    #[tracing::instrument(skip(self))]
+    pub async fn payout(&self, user: UserId) -> Result<(), BetError> {
+        let ready_bets = self.db.ready_bets(user).await?;
 
         for bet in ready_bets {
-            // Determine the outcome
-            let outcome = bet.outcome(
-                &self
-                    .weather_service
-                    .get_historical_weather(bet.date)
-                    .await
-                    .unwrap(),
-            );
+            let weather = self
+                .weather_service
+                .get_historical_weather(bet.date)
+                .await?;
+
+            self.settle_bet(user, &bet, &weather).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Pay out every user's bet for a date that has finished, returning the outcome for each user.
+    #[tracing::instrument(skip(self))]
+    pub async fn settle(&self, date: NaiveDate) -> Result<Vec<(UserId, BetOutcome)>, BetError> {
+        let today = Utc::now().with_timezone(&Melbourne).naive_local().date();
+        if date >= today {
+            return Err(BetError::NotFinished);
+        }
+
+        let bets = self.db.unsettled_bets(date).await?;
+        if bets.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let weather = self.weather_service.get_historical_weather(date).await?;
+
+        let mut outcomes = Vec::with_capacity(bets.len());
+        for (user, bet) in bets {
+            outcomes.push((user, self.settle_bet(user, &bet, &weather).await?));
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Void every bet for a date, such as when the weather data for the day was wrong. Any payouts
+    /// are reversed and the wagers are refunded, returning the amount refunded to each user.
+    #[tracing::instrument(skip(self))]
+    pub async fn void(
+        &self,
+        date: NaiveDate,
+        reason: &str,
+        admin: Option<UserId>,
+    ) -> Result<Vec<(UserId, f64)>, DbError> {
+        let refunds = self
+            .db
+            .void_bets(date, &format!("voided bet for {date}: {reason}"), admin)
+            .await?;
+
+        tracing::info!("voided {} bets", refunds.len());
+
+        for (user, refund) in &refunds {
+            self.audit
+                .record(AuditEvent {
+                    actor: admin,
+                    ..AuditEvent::new(
+                        AuditKind::BetVoided,
+                        Some(*user),
+                        format!("voided bet for {date}, refunding ${refund:.2}: {reason}"),
+                    )
+                })
+                .await;
+
+            self.balance_changed(*user).await;
+        }
 
-            // Mark this bet as payed out
-            self.db.record_payout(user, bet.date, &outcome).await;
+        Ok(refunds)
+    }
+
+    /// Replace provisional observed weather, resettling any bets that were paid out with it. Dates
+    /// that don't have provisional weather are skipped. Returns the number of dates that were
+    /// finalised, along with the change in payout for each resettled bet.
+    #[tracing::instrument(skip(self, weather))]
+    pub async fn finalise_weather(
+        &self,
+        weather: &[(NaiveDate, Weather)],
+        source: WeatherSource,
+    ) -> Result<(usize, Vec<(UserId, f64)>), DbError> {
+        let mut finalised = 0;
+        let mut resettled = Vec::new();
+
+        for (date, weather) in weather {
+            let Some(bets) = self.db.resettle(*date, weather, source).await? else {
+                continue;
+            };
+
+            if !bets.is_empty() {
+                tracing::warn!(
+                    "observed weather for {date} changed, resettled {} bets",
+                    bets.len()
+                );
+            }
+
+            finalised += 1;
+            resettled.extend(bets);
+        }
+
+        metrics::counter!("bets_resettled_total").increment(resettled.len() as u64);
+
+        for (user, _) in &resettled {
+            self.balance_changed(*user).await;
+        }
+
+        Ok((finalised, resettled))
+    }
+
+    /// Let the user's open pages and webhooks know that their balance changed.
+    async fn balance_changed(&self, user: UserId) {
+        self.events.publish(Event::Balance(user));
+
+        match self.db.get_balance(user).await {
+            Ok(balance) => {
+                self.webhooks
+                    .send(user, WebhookEvent::BalanceChanged { balance })
+                    .await
+            }
+            Err(e) => tracing::error!("unable to get balance for webhook: {e}"),
         }
     }
 
-    pub async fn get_ready(&self, user: UserId) -> Vec<(NaiveDate, BetOutcome)> {
+    /// Determine the outcome of a bet, and mark it as paid out.
+    async fn settle_bet(
+        &self,
+        user: UserId,
+        bet: &BetRecord,
+        weather: &Weather,
+    ) -> Result<BetOutcome, BetError> {
+        let outcome = bet.outcome(weather);
+
+        self.db.record_payout(user, bet.date, &outcome).await?;
+
+        metrics::counter!("payouts_total").increment(1);
+        metrics::histogram!("payout_dollars").record(outcome.payout);
+
+        self.audit
+            .record(AuditEvent::new(
+                AuditKind::Payout,
+                Some(user),
+                format!("paid out ${:.2} for {}", outcome.payout, bet.date),
+            ))
+            .await;
+
+        self.webhooks
+            .send(
+                user,
+                WebhookEvent::BetSettled {
+                    date: bet.date,
+                    outcome: outcome.clone(),
+                },
+            )
+            .await;
+        self.balance_changed(user).await;
+
+        Ok(outcome)
+    }
+
+    pub async fn get_ready(&self, user: UserId) -> Result<Vec<(NaiveDate, BetOutcome)>, BetError> {
         use futures::stream::FuturesUnordered;
 
         self.db
             .ready_bets(user)
-            .await
+            .await?
             .into_iter()
             .map(|bet| async move {
-                Some((
+                Ok((
                     bet.date,
                     bet.outcome(
                         &self
@@ -198,12 +462,57 @@ impl BetService {
                 ))
             })
             .collect::<FuturesUnordered<_>>()
-            .filter_map(|weather| async { weather })
-            .collect::<Vec<_>>()
+            .try_collect::<Vec<_>>()
             .await
     }
 
-    pub async fn get_balance(&self, user: UserId) -> f64 {
+    /// Get every bet the user has placed, most recent first.
+    pub async fn history(&self, user: UserId) -> Result<Vec<BetHistory>, DbError> {
+        Ok(self
+            .db
+            .history(user)
+            .await?
+            .into_iter()
+            .map(|(bet, correct)| BetHistory {
+                date: bet.date,
+                payout: Payout::from(&bet),
+                outcome: correct.map(|(rain, temperature)| bet.settled_outcome(rain, temperature)),
+                bet: bet.into(),
+            })
+            .collect())
+    }
+
+    pub async fn get_balance(&self, user: UserId) -> Result<f64, DbError> {
         self.db.get_balance(user).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 10, day).unwrap()
+    }
+
+    #[test]
+    fn future_bets_are_open() {
+        assert!(check_not_locked(date(21), date(20)).is_ok());
+    }
+
+    #[test]
+    fn todays_bets_are_locked() {
+        assert!(matches!(
+            check_not_locked(date(20), date(20)),
+            Err(BetError::Locked)
+        ));
+    }
+
+    #[test]
+    fn past_bets_are_locked() {
+        assert!(matches!(
+            check_not_locked(date(19), date(20)),
+            Err(BetError::Locked)
+        ));
+    }
+}
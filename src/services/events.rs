@@ -0,0 +1,114 @@
+use futures::{stream, Stream, StreamExt};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use crate::user::UserId;
+
+/// Number of events that can be waiting for a slow subscriber before it misses some.
+const CAPACITY: usize = 256;
+
+/// Something that changed, which open pages should be updated with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The user's balance changed
+    Balance(UserId),
+
+    /// A day has finished, so every user may have payouts ready
+    PayoutsReady,
+
+    /// A new forecast is available
+    Forecast,
+}
+
+/// Broadcasts events to everyone that is subscribed. Events are only delivered within this
+/// process, so changes made by the CLI or another replica won't be seen.
+#[derive(Clone)]
+pub struct EventService {
+    sender: broadcast::Sender<Event>,
+    closed: CancellationToken,
+}
+
+impl Default for EventService {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::Sender::new(CAPACITY),
+            closed: CancellationToken::new(),
+        }
+    }
+}
+
+impl EventService {
+    pub fn publish(&self, event: Event) {
+        // Nobody may be subscribed, which is fine
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribe to the events that are relevant to the user. If events are missed because the
+    /// subscriber fell behind, everything is reported as changed. The stream ends once the service
+    /// is closed.
+    pub fn subscribe(&self, user: UserId) -> impl Stream<Item = Event> {
+        let receiver = self.sender.subscribe();
+
+        stream::unfold(receiver, move |mut receiver| async move {
+            loop {
+                let events = match receiver.recv().await {
+                    Ok(Event::Balance(other)) if other != user => continue,
+                    Ok(event) => vec![event],
+                    Err(RecvError::Lagged(_)) => vec![Event::Balance(user), Event::Forecast],
+                    Err(RecvError::Closed) => return None,
+                };
+
+                return Some((stream::iter(events), receiver));
+            }
+        })
+        .flatten()
+        .take_until(self.closed.clone().cancelled_owned())
+    }
+
+    /// End every subscription, such as when shutting down.
+    pub fn close(&self) {
+        self.closed.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn delivers_relevant_events() {
+        let events = EventService::default();
+        let user = UserId::from(1);
+        let mut stream = Box::pin(events.subscribe(user));
+
+        events.publish(Event::Balance(UserId::from(2)));
+        events.publish(Event::Balance(user));
+        events.publish(Event::PayoutsReady);
+        events.publish(Event::Forecast);
+
+        // Other users' balances are skipped
+        assert_eq!(stream.next().await, Some(Event::Balance(user)));
+        assert_eq!(stream.next().await, Some(Event::PayoutsReady));
+        assert_eq!(stream.next().await, Some(Event::Forecast));
+        assert_eq!(stream.next().now_or_never(), None);
+
+        events.close();
+        assert_eq!(stream.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn reports_everything_changed_when_lagging() {
+        let events = EventService::default();
+        let user = UserId::from(1);
+        let mut stream = Box::pin(events.subscribe(user));
+
+        for _ in 0..=CAPACITY {
+            events.publish(Event::Forecast);
+        }
+
+        assert_eq!(stream.next().await, Some(Event::Balance(user)));
+        assert_eq!(stream.next().await, Some(Event::Forecast));
+    }
+}
@@ -0,0 +1,361 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Australia::Melbourne;
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    bet::BetService,
+    events::{Event, EventService},
+    notifications::{NotificationError, NotificationService, NOTIFICATION_TIME},
+    weather::{WeatherError, WeatherService, WeatherSource},
+    DbError,
+};
+
+/// Time after local midnight that the forecast is prefetched, giving the upstream a chance to
+/// publish the new day.
+const PREFETCH_TIME: NaiveTime = match NaiveTime::from_hms_opt(0, 5, 0) {
+    Some(time) => time,
+    None => unreachable!(),
+};
+
+/// How often to check whether the archive has published observations for settled dates.
+const BACKFILL_PERIOD: Duration = Duration::hours(6);
+
+/// How long a replica holds on to a job once it starts running it. Every replica schedules every
+/// job, so the others skip it until then, even if their clocks or schedules have drifted apart. It
+/// must be longer than a run takes, but shorter than the time between runs.
+const JOB_LEASE: Duration = Duration::minutes(30);
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    #[error(transparent)]
+    Weather(#[from] WeatherError),
+
+    #[error(transparent)]
+    Notification(#[from] NotificationError),
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+/// Background jobs that are run on a schedule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Job {
+    /// Fetch the forecast for the coming week, so that the first visitor of the day doesn't have
+    /// to wait for it.
+    ForecastPrefetch,
+
+    /// Save observed weather from the archive for dates that bets were placed on, and replace
+    /// provisional weather once the archive has it.
+    HistoricalBackfill,
+
+    /// Email and push users the notifications they've chosen to receive.
+    Notifications,
+
+    /// Let open pages know that the previous day's bets can be paid out. Bets are ready once the
+    /// day has finished in UTC, so this runs at UTC midnight.
+    PayoutsReady,
+}
+
+impl Job {
+    pub const ALL: [Job; 4] = [
+        Job::ForecastPrefetch,
+        Job::HistoricalBackfill,
+        Job::Notifications,
+        Job::PayoutsReady,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Job::ForecastPrefetch => "forecast-prefetch",
+            Job::HistoricalBackfill => "historical-backfill",
+            Job::Notifications => "notifications",
+            Job::PayoutsReady => "payouts-ready",
+        }
+    }
+
+    /// Determine when the job should next run.
+    fn next_run(self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Job::ForecastPrefetch => next_local_time(now, PREFETCH_TIME),
+            Job::HistoricalBackfill => now + BACKFILL_PERIOD,
+            Job::Notifications => next_local_time(now, NOTIFICATION_TIME),
+            Job::PayoutsReady => (now.date_naive() + Duration::days(1))
+                .and_time(NaiveTime::MIN)
+                .and_utc(),
+        }
+    }
+}
+
+/// Next time after `now` that it will be `time` in Melbourne.
+fn next_local_time(now: DateTime<Utc>, time: NaiveTime) -> DateTime<Utc> {
+    let local = now.with_timezone(&Melbourne);
+    let date = if local.time() < time {
+        local.date_naive()
+    } else {
+        local.date_naive() + Duration::days(1)
+    };
+
+    Melbourne
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|next| next.with_timezone(&Utc))
+        .unwrap_or(now + Duration::days(1))
+}
+
+/// Most recent run of a job, as it appears in the database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct JobRun {
+    /// Name of the job
+    pub job: String,
+
+    /// When the run started
+    pub started: NaiveDateTime,
+
+    /// When the run finished, if it has
+    pub finished: Option<NaiveDateTime>,
+
+    /// Whether the run succeeded, once it has finished
+    pub succeeded: Option<bool>,
+
+    /// Summary of the run, or the error it failed with
+    pub message: Option<String>,
+}
+
+/// Current status of a job.
+#[derive(Debug, Clone)]
+pub struct JobStatus {
+    pub job: Job,
+
+    /// Most recent run of the job
+    pub last_run: Option<JobRun>,
+
+    /// When the job is next scheduled to run
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// Storage for job runs, along with the queries that jobs need.
+#[async_trait]
+pub trait JobRepository: Send + Sync {
+    /// Take the job for `lease`, unless another replica already holds it. Produces whether it was
+    /// taken.
+    async fn lock(&self, job: &str, lease: Duration) -> Result<bool, DbError>;
+
+    /// Record that a run of the job has started, returning the ID of the run.
+    async fn start_run(&self, job: &str) -> Result<i64, DbError>;
+
+    async fn finish_run(&self, id: i64, succeeded: bool, message: &str) -> Result<(), DbError>;
+
+    /// Get the most recent run of each job that has been run.
+    async fn last_runs(&self) -> Result<Vec<JobRun>, DbError>;
+
+    /// Find all dates before `before` that have bets, but no observed weather.
+    async fn dates_missing_weather(&self, before: NaiveDate) -> Result<Vec<NaiveDate>, DbError>;
+}
+
+#[derive(Clone)]
+pub struct JobService {
+    db: Arc<dyn JobRepository>,
+    weather: WeatherService,
+    bet: BetService,
+    notifications: NotificationService,
+    events: EventService,
+    next_runs: Arc<Mutex<HashMap<Job, DateTime<Utc>>>>,
+}
+
+impl JobService {
+    pub fn new(
+        db: Arc<dyn JobRepository>,
+        weather: WeatherService,
+        bet: BetService,
+        notifications: NotificationService,
+        events: EventService,
+    ) -> Self {
+        Self {
+            db,
+            weather,
+            bet,
+            notifications,
+            events,
+            next_runs: Default::default(),
+        }
+    }
+
+    /// Run a job immediately, recording the outcome, unless another replica recently ran it.
+    #[tracing::instrument(skip(self))]
+    pub async fn run(&self, job: Job) -> Result<(), DbError> {
+        let name = job.name();
+        if !self.db.lock(name, JOB_LEASE).await? {
+            tracing::info!("job {name} skipped, as another replica is running it");
+            return Ok(());
+        }
+
+        let id = self.db.start_run(name).await?;
+
+        let result = match job {
+            Job::ForecastPrefetch => self.prefetch_forecast().await,
+            Job::HistoricalBackfill => self.backfill_historical_weather().await,
+            Job::Notifications => self
+                .notifications
+                .send_daily()
+                .await
+                .map_err(JobError::from),
+            Job::PayoutsReady => {
+                self.events.publish(Event::PayoutsReady);
+                Ok("published".to_string())
+            }
+        };
+
+        let (succeeded, message) = match result {
+            Ok(message) => {
+                tracing::info!("job {name} succeeded: {message}");
+                (true, message)
+            }
+            Err(e) => {
+                tracing::error!("job {name} failed: {e}");
+                (false, e.to_string())
+            }
+        };
+
+        self.db.finish_run(id, succeeded, &message).await
+    }
+
+    /// Run the job straight away, and then continue running it on its schedule until `shutdown` is
+    /// cancelled. A run that is in progress when cancelled is allowed to finish.
+    pub async fn continuously_run(self, job: Job, shutdown: CancellationToken) {
+        while !shutdown.is_cancelled() {
+            if let Err(e) = self.run(job).await {
+                tracing::error!("unable to record run of job {}: {e}", job.name());
+            }
+
+            let now = Utc::now();
+            let next_run = job.next_run(now);
+            self.next_runs.lock().unwrap().insert(job, next_run);
+
+            tokio::select! {
+                _ = tokio::time::sleep((next_run - now).to_std().unwrap_or_default()) => {}
+                _ = shutdown.cancelled() => {}
+            }
+        }
+
+        self.next_runs.lock().unwrap().remove(&job);
+    }
+
+    /// Get the status of every job.
+    pub async fn status(&self) -> Result<Vec<JobStatus>, DbError> {
+        let mut last_runs = self
+            .db
+            .last_runs()
+            .await?
+            .into_iter()
+            .map(|run| (run.job.clone(), run))
+            .collect::<HashMap<_, _>>();
+
+        let next_runs = self.next_runs.lock().unwrap();
+
+        Ok(Job::ALL
+            .into_iter()
+            .map(|job| JobStatus {
+                job,
+                last_run: last_runs.remove(job.name()),
+                next_run: next_runs.get(&job).copied(),
+            })
+            .collect())
+    }
+
+    async fn prefetch_forecast(&self) -> Result<String, JobError> {
+        let today = Utc::now().with_timezone(&Melbourne).date_naive();
+
+        let forecast = self
+            .weather
+            .get_forecast(today, today + Duration::weeks(1))
+            .await?;
+
+        self.events.publish(Event::Forecast);
+
+        Ok(format!(
+            "{} days available, {} stale",
+            forecast.len(),
+            forecast.iter().filter(|(_, day)| day.stale).count()
+        ))
+    }
+
+    async fn backfill_historical_weather(&self) -> Result<String, JobError> {
+        let today = Utc::now().with_timezone(&Melbourne).date_naive();
+
+        // Find any past dates that have bets, but no observed weather
+        let missing = self.db.dates_missing_weather(today).await?;
+
+        let saved = match (missing.first(), missing.last()) {
+            (Some(start), Some(end)) => {
+                self.weather
+                    .backfill_historical_weather(*start, *end)
+                    .await?
+            }
+            _ => 0,
+        };
+
+        // Replace weather that was filled in from the forecast, once the archive has the day
+        let provisional = self.weather.provisional_dates().await?;
+
+        let (finalised, resettled) = match (provisional.first(), provisional.last()) {
+            (Some(start), Some(end)) => {
+                let archive = self
+                    .weather
+                    .get_archive(*start, *end)
+                    .await?
+                    .into_iter()
+                    .filter(|(date, _)| provisional.contains(date))
+                    .collect::<Vec<_>>();
+
+                self.bet
+                    .finalise_weather(&archive, WeatherSource::Archive)
+                    .await?
+            }
+            _ => (0, Vec::new()),
+        };
+
+        Ok(format!(
+            "{} dates missing, {saved} days saved from the archive, {finalised} of {} provisional \
+                days finalised, {} bets resettled",
+            missing.len(),
+            provisional.len(),
+            resettled.len()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedules_payouts_ready_at_utc_midnight() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 23, 59, 59).unwrap();
+        assert_eq!(
+            Job::PayoutsReady.next_run(now),
+            Utc.with_ymd_and_hms(2024, 6, 2, 0, 0, 0).unwrap()
+        );
+
+        let midnight = Utc.with_ymd_and_hms(2024, 6, 2, 0, 0, 0).unwrap();
+        assert_eq!(
+            Job::PayoutsReady.next_run(midnight),
+            Utc.with_ymd_and_hms(2024, 6, 3, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn schedules_prefetch_after_local_midnight() {
+        // 00:05 in Melbourne during standard time (UTC+10)
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            Job::ForecastPrefetch.next_run(now),
+            Utc.with_ymd_and_hms(2024, 6, 1, 14, 5, 0).unwrap()
+        );
+    }
+}
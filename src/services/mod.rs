@@ -1,31 +1,139 @@
 use reqwest::Client;
-use sqlx::SqlitePool;
 
-use self::{bet::BetService, oauth::OAuthService, state::StateService, weather::WeatherService};
+use crate::{config::Config, db::Repositories};
 
+use self::{
+    audit::AuditService, bet::BetService, events::EventService, jobs::JobService,
+    notifications::NotificationService, oauth::OAuthService, push::PushService,
+    rate_limit::RateLimitService, session::SessionService, state::StateService,
+    token::TokenService, user::UserService, weather::WeatherService, webhooks::WebhookService,
+};
+
+pub mod audit;
 pub mod bet;
+pub mod events;
+pub mod jobs;
+pub mod notifications;
 pub mod oauth;
+pub mod outbound;
+pub mod push;
+pub mod rate_limit;
+pub mod session;
 pub mod state;
+pub mod token;
+pub mod user;
 pub mod weather;
+pub mod webhooks;
+
+/// Errors that can occur whilst interacting with the database or session store.
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error("database query failed: {0}")]
+    Query(#[from] sqlx::Error),
+
+    #[error("database migration failed: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+
+    #[error("`{0}` databases aren't supported, use `sqlite` or `postgres`")]
+    UnsupportedDatabase(String),
+
+    #[error("session store failed: {0}")]
+    SessionStore(#[from] tower_sessions::session_store::Error),
+
+    #[error("session failed: {0}")]
+    Session(#[from] tower_sessions::session::Error),
+}
 
 #[derive(Clone)]
 pub struct Services {
     pub bet: BetService,
     pub weather: WeatherService,
     pub oauth: OAuthService,
+    pub session: SessionService,
     pub state: StateService,
+    pub token: TokenService,
+    pub user: UserService,
+    pub jobs: JobService,
+    pub notifications: NotificationService,
+    pub events: EventService,
+    pub webhooks: WebhookService,
+    pub push: PushService,
+    pub rate_limit: RateLimitService,
+    pub audit: AuditService,
 }
 
 impl Services {
-    pub fn new(pool: SqlitePool, client: Client) -> Self {
-        let weather = WeatherService::new(pool.clone(), client.clone());
-        let state = StateService::new(pool.clone());
+    pub fn new(repositories: &Repositories, client: Client, config: &Config) -> Self {
+        let events = EventService::default();
+        let audit = AuditService::new(repositories.audit.clone());
+        let weather = WeatherService::new(
+            repositories.weather.clone(),
+            client.clone(),
+            &config.weather,
+        );
+        let state = StateService::new(repositories.states.clone(), audit.clone());
+        let session = SessionService::new(
+            repositories.sessions.clone(),
+            repositories.session_store.clone(),
+            audit.clone(),
+        );
+        let token = TokenService::new(repositories.tokens.clone());
+        let webhooks = WebhookService::new(repositories.webhooks.clone(), &config.webhooks);
+        let push = PushService::new(repositories.push.clone(), config.push.as_ref());
+
+        let bet = BetService::new(
+            repositories.bets.clone(),
+            weather.clone(),
+            events.clone(),
+            webhooks.clone(),
+            audit.clone(),
+        );
+
+        let notifications = NotificationService::new(
+            repositories.notifications.clone(),
+            bet.clone(),
+            weather.clone(),
+            push.clone(),
+            config.email.as_ref(),
+        );
 
         Self {
-            bet: BetService::new(pool.clone(), weather.clone()),
-            oauth: OAuthService::new(pool, client.clone(), state.clone()),
+            oauth: OAuthService::new(
+                repositories.users.clone(),
+                client.clone(),
+                state.clone(),
+                config.oauth.clone(),
+                config.game.default_balance,
+            ),
+            user: UserService::new(
+                repositories.users.clone(),
+                session.clone(),
+                token.clone(),
+                events.clone(),
+                webhooks.clone(),
+                audit.clone(),
+            ),
+            jobs: JobService::new(
+                repositories.jobs.clone(),
+                weather.clone(),
+                bet.clone(),
+                notifications.clone(),
+                events.clone(),
+            ),
+            notifications,
+            bet,
             weather,
             state,
+            session,
+            token,
+            events,
+            webhooks,
+            push,
+            audit,
+            rate_limit: RateLimitService::new(
+                &config.rate_limit,
+                config.server.trust_forwarded_for,
+            ),
         }
     }
 }
@@ -0,0 +1,501 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Australia::Melbourne;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::{
+    config::{EmailConfig, SmtpTls},
+    user::UserId,
+    MELBOURNE,
+};
+
+use super::{
+    bet::{BetError, BetService},
+    push::{PushMessage, PushService},
+    weather::{WeatherError, WeatherService},
+    DbError,
+};
+
+/// Local time after which the day's notifications are sent.
+pub const NOTIFICATION_TIME: NaiveTime = match NaiveTime::from_hms_opt(18, 0, 0) {
+    Some(time) => time,
+    None => unreachable!(),
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotificationError {
+    #[error("email address is invalid: {0}")]
+    Address(#[from] lettre::address::AddressError),
+
+    #[error("unable to build email: {0}")]
+    Build(#[from] lettre::error::Error),
+
+    #[error("unable to send email: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+
+    #[error(transparent)]
+    Bet(#[from] BetError),
+
+    #[error(transparent)]
+    Weather(#[from] WeatherError),
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+/// Kinds of notification that users can choose to receive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationKind {
+    /// A bet from yesterday has settled, and is ready to be paid out
+    BetSettled,
+
+    /// Forecast for tomorrow
+    ForecastDigest,
+
+    /// No bets have been placed for the coming week
+    BetReminder,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 3] = [
+        NotificationKind::BetSettled,
+        NotificationKind::ForecastDigest,
+        NotificationKind::BetReminder,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NotificationKind::BetSettled => "bet_settled",
+            NotificationKind::ForecastDigest => "forecast_digest",
+            NotificationKind::BetReminder => "bet_reminder",
+        }
+    }
+
+    /// Whether users that haven't chosen receive the notification.
+    pub fn enabled_by_default(self) -> bool {
+        matches!(self, NotificationKind::BetSettled)
+    }
+}
+
+/// User that a notification can be sent to.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Recipient {
+    pub user: UserId,
+
+    /// Address that emails are sent to, if the user has one
+    pub email: Option<String>,
+}
+
+/// Storage for notification preferences, and the notifications that have been sent.
+#[async_trait]
+pub trait NotificationRepository: Send + Sync {
+    /// Get the name of each notification the user has chosen whether to receive, and whether they
+    /// chose to receive it.
+    async fn preferences(&self, user: UserId) -> Result<Vec<(String, bool)>, DbError>;
+
+    async fn set_preference(&self, user: UserId, kind: &str, enabled: bool) -> Result<(), DbError>;
+
+    /// Find users with an email address or push subscription that want the notification, and
+    /// haven't already been sent it for `date`. Users that haven't chosen are included if `default` is set. Banned users are
+    /// never included.
+    async fn recipients(
+        &self,
+        kind: &str,
+        default: bool,
+        date: NaiveDate,
+    ) -> Result<Vec<Recipient>, DbError>;
+
+    /// Record that the notification for `date` was sent to the user.
+    async fn record_sent(&self, user: UserId, kind: &str, date: NaiveDate) -> Result<(), DbError>;
+}
+
+/// Sends emails through the configured SMTP server.
+#[derive(Clone)]
+struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    fn new(config: &EmailConfig) -> Result<Self, NotificationError> {
+        let mut builder = match config.tls {
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+            }
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        };
+
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse()?,
+        })
+    }
+
+    async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), NotificationError> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(subject)
+            .body(format!(
+                "{body}\n\n--\nYou can choose which emails you receive from the settings page."
+            ))?;
+
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct NotificationService {
+    db: Arc<dyn NotificationRepository>,
+    bet: BetService,
+    weather: WeatherService,
+    push: PushService,
+    mailer: Option<Mailer>,
+}
+
+impl NotificationService {
+    pub fn new(
+        db: Arc<dyn NotificationRepository>,
+        bet: BetService,
+        weather: WeatherService,
+        push: PushService,
+        config: Option<&EmailConfig>,
+    ) -> Self {
+        let mailer = config.and_then(|config| {
+            Mailer::new(config)
+                .inspect_err(|e| tracing::error!("email is disabled: {e}"))
+                .ok()
+        });
+
+        Self {
+            db,
+            bet,
+            weather,
+            push,
+            mailer,
+        }
+    }
+
+    /// Whether the user receives each kind of notification.
+    pub async fn preferences(
+        &self,
+        user: UserId,
+    ) -> Result<Vec<(NotificationKind, bool)>, DbError> {
+        let chosen = self.db.preferences(user).await?;
+
+        Ok(NotificationKind::ALL
+            .into_iter()
+            .map(|kind| {
+                let enabled = chosen
+                    .iter()
+                    .find(|(name, _)| name == kind.name())
+                    .map(|(_, enabled)| *enabled)
+                    .unwrap_or(kind.enabled_by_default());
+
+                (kind, enabled)
+            })
+            .collect())
+    }
+
+    pub async fn set_preferences(
+        &self,
+        user: UserId,
+        preferences: &[(NotificationKind, bool)],
+    ) -> Result<(), DbError> {
+        for (kind, enabled) in preferences {
+            self.db.set_preference(user, kind.name(), *enabled).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the day's notifications to everyone that hasn't already received them, returning a
+    /// summary of what was sent. Nothing is sent before [`NOTIFICATION_TIME`].
+    #[tracing::instrument(skip(self))]
+    pub async fn send_daily(&self) -> Result<String, NotificationError> {
+        if self.mailer.is_none() && !self.push.is_configured() {
+            return Ok("neither email nor push is configured".to_string());
+        }
+
+        let now = Utc::now().with_timezone(&Melbourne).naive_local();
+        if now.time() < NOTIFICATION_TIME {
+            return Ok(format!("not sending until {NOTIFICATION_TIME}"));
+        }
+
+        let today = now.date();
+        let settled = self.send_settled(today - Duration::days(1)).await?;
+        let digests = self.send_digests(today + Duration::days(1)).await?;
+        let reminders = self.send_reminders(today).await?;
+
+        Ok(format!(
+            "sent {settled} settled bet, {digests} forecast digest and {reminders} reminder \
+                notifications"
+        ))
+    }
+
+    /// Notify each user whose bet for `date` is waiting to be paid out.
+    async fn send_settled(&self, date: NaiveDate) -> Result<usize, NotificationError> {
+        let kind = NotificationKind::BetSettled;
+        let mut sent = 0;
+
+        for recipient in self
+            .db
+            .recipients(kind.name(), kind.enabled_by_default(), date)
+            .await?
+        {
+            // Bets that have already been paid out don't need a reminder
+            let Some((_, outcome)) = self
+                .bet
+                .get_ready(recipient.user)
+                .await?
+                .into_iter()
+                .find(|(ready, _)| *ready == date)
+            else {
+                continue;
+            };
+
+            let result = if outcome.payout > 0.0 {
+                format!("won ${:.2}, which is ready to collect", outcome.payout)
+            } else {
+                "didn't win anything this time".to_string()
+            };
+
+            let body =
+                format!(
+                "Your bet for {} has settled. You guessed the rain {} and the temperature {}, and \
+                    {result}.",
+                format_date(date),
+                if outcome.rain { "correctly" } else { "incorrectly" },
+                if outcome.temperature { "correctly" } else { "incorrectly" },
+            );
+
+            sent += self
+                .send(kind, date, &recipient, "Your bet has settled", body)
+                .await?;
+        }
+
+        Ok(sent)
+    }
+
+    /// Send everyone that wants it the forecast for `date`.
+    async fn send_digests(&self, date: NaiveDate) -> Result<usize, NotificationError> {
+        let kind = NotificationKind::ForecastDigest;
+        let recipients = self
+            .db
+            .recipients(kind.name(), kind.enabled_by_default(), date)
+            .await?;
+
+        if recipients.is_empty() {
+            return Ok(0);
+        }
+
+        let forecast = self.weather.get_daily_forecast(date, MELBOURNE).await?;
+        let body = format!(
+            "The forecast for {} is {:.1}° to {:.1}°, with a {:.0}% chance of rain. Bets for it \
+                close at midnight.",
+            format_date(date),
+            forecast.minimum_temperature,
+            forecast.maximum_temperature,
+            forecast.rain * 100.0,
+        );
+
+        let mut sent = 0;
+        for recipient in recipients {
+            sent += self
+                .send(kind, date, &recipient, "Tomorrow's forecast", body.clone())
+                .await?;
+        }
+
+        Ok(sent)
+    }
+
+    /// Remind users that haven't placed any bets for the coming week, at most once a week.
+    async fn send_reminders(&self, today: NaiveDate) -> Result<usize, NotificationError> {
+        let kind = NotificationKind::BetReminder;
+        let week = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+        let next_week = today + Duration::weeks(1);
+
+        let mut sent = 0;
+        for recipient in self
+            .db
+            .recipients(kind.name(), kind.enabled_by_default(), week)
+            .await?
+        {
+            if self
+                .bet
+                .history(recipient.user)
+                .await?
+                .iter()
+                .any(|bet| bet.date > today && bet.date <= next_week)
+            {
+                continue;
+            }
+
+            let body = "You don't have any bets placed for the coming week. The forecast is up, \
+                so now's a good time to place some."
+                .to_string();
+
+            sent += self
+                .send(kind, week, &recipient, "No bets for the coming week", body)
+                .await?;
+        }
+
+        Ok(sent)
+    }
+
+    /// Send a notification by email and push, recording that it was sent if either succeeded.
+    /// Failures to send are logged rather than stopping the remaining notifications, returning the
+    /// number that were sent.
+    async fn send(
+        &self,
+        kind: NotificationKind,
+        date: NaiveDate,
+        recipient: &Recipient,
+        subject: &str,
+        body: String,
+    ) -> Result<usize, DbError> {
+        let mut delivered = false;
+
+        if let (Some(mailer), Some(email)) = (&self.mailer, &recipient.email) {
+            match mailer.send(email, subject, body.clone()).await {
+                Ok(()) => delivered = true,
+                Err(e) => tracing::warn!(
+                    "unable to email {} notification to user {}: {e}",
+                    kind.name(),
+                    recipient.user
+                ),
+            }
+        }
+
+        let message = PushMessage {
+            title: subject.to_string(),
+            body,
+        };
+        if self.push.send(recipient.user, &message).await? > 0 {
+            delivered = true;
+        }
+
+        if !delivered {
+            return Ok(0);
+        }
+
+        self.db
+            .record_sent(recipient.user, kind.name(), date)
+            .await?;
+
+        metrics::counter!("notifications_sent_total", "kind" => kind.name()).increment(1);
+
+        Ok(1)
+    }
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%A, %B %-d").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Accept a single email over plain SMTP, producing the commands that were sent along with the
+    /// message.
+    async fn receive_email(listener: TcpListener) -> (Vec<String>, String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+
+        let mut commands = Vec::new();
+        let mut message = String::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let reply: &[u8] = match line.split(' ').next().unwrap_or_default() {
+                "EHLO" => b"250 localhost\r\n",
+                "DATA" => {
+                    writer.write_all(b"354 go ahead\r\n").await.unwrap();
+
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        if line == "." {
+                            break;
+                        }
+                        message.push_str(&line);
+                        message.push('\n');
+                    }
+
+                    b"250 queued\r\n"
+                }
+                "QUIT" => {
+                    writer.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => b"250 ok\r\n",
+            };
+
+            commands.push(line);
+            writer.write_all(reply).await.unwrap();
+        }
+
+        (commands, message)
+    }
+
+    #[tokio::test]
+    async fn emails_notification() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(receive_email(listener));
+
+        let mailer = Mailer::new(&EmailConfig {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            from: "Cloud Casino <casino@example.com>".to_string(),
+        })
+        .unwrap();
+        mailer
+            .send(
+                "alice@example.com",
+                "Your bet has settled",
+                "Your bet for Monday, June 3 has settled.".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let (commands, message) = server.await.unwrap();
+        assert!(commands.contains(&"MAIL FROM:<casino@example.com>".to_string()));
+        assert!(commands.contains(&"RCPT TO:<alice@example.com>".to_string()));
+
+        let (headers, body) = message.split_once("\n\n").unwrap();
+        let headers = headers.lines().collect::<Vec<_>>();
+        assert!(headers.contains(&"From: \"Cloud Casino\" <casino@example.com>"));
+        assert!(headers.contains(&"To: alice@example.com"));
+        assert!(headers.contains(&"Subject: Your bet has settled"));
+        assert_eq!(
+            body,
+            "Your bet for Monday, June 3 has settled.\n\n--\nYou can choose which emails you \
+                receive from the settings page.\n"
+        );
+    }
+}
@@ -1,58 +1,87 @@
+use std::{collections::HashMap, sync::Arc};
+
 use reqwest::{
     header::{ACCEPT, AUTHORIZATION, USER_AGENT},
     Client,
 };
-use sqlx::SqlitePool;
 use url::Url;
 
-use super::state::StateService;
+use crate::{config::ProviderConfig, user::UserId};
 
-mod env {
-    pub static OAUTH_CLIENT_ID: &str = "OAUTH_CLIENT_ID";
-    pub static OAUTH_CLIENT_SECRET: &str = "OAUTH_CLIENT_SECRET";
-    pub static OAUTH_AUTHORIZATION_URL: &str = "OAUTH_AUTHORIZATION_URL";
-    pub static OAUTH_ACCESS_TOKEN_URL: &str = "OAUTH_ACCESS_TOKEN_URL";
-}
+use super::{state::StateService, user::UserRepository, DbError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum OAuthError {
+    #[error("provider `{0}` is not configured")]
+    NotConfigured(String),
+
+    #[error("state value is invalid or has expired")]
+    InvalidState,
 
-const DEFAULT_BALANCE: f64 = 100.0;
+    #[error("request to provider failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("provider response is missing `{0}`")]
+    InvalidResponse(&'static str),
+
+    #[error("user has been banned")]
+    Banned,
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
 
 #[derive(Clone)]
 pub struct OAuthService {
-    pool: SqlitePool,
+    users: Arc<dyn UserRepository>,
     client: Client,
     state: StateService,
+    providers: HashMap<String, ProviderConfig>,
+    default_balance: f64,
 }
 
 impl OAuthService {
-    pub fn new(pool: SqlitePool, client: Client, state: StateService) -> Self {
+    pub fn new(
+        users: Arc<dyn UserRepository>,
+        client: Client,
+        state: StateService,
+        providers: HashMap<String, ProviderConfig>,
+        default_balance: f64,
+    ) -> Self {
         Self {
-            pool,
+            users,
             client,
             state,
+            providers,
+            default_balance,
         }
     }
 
-    pub async fn generate_authorization_url(&self, provider: impl AsRef<str>) -> Option<Url> {
-        let identifier = provider.as_ref().to_uppercase();
+    /// Fetch the config for the provider.
+    fn provider(&self, provider: &str) -> Result<&ProviderConfig, OAuthError> {
+        self.providers
+            .get(provider)
+            .ok_or_else(|| OAuthError::NotConfigured(provider.to_string()))
+    }
 
-        // Fetch required environment variables
-        let client_id = std::env::var(format!("{}_{identifier}", env::OAUTH_CLIENT_ID)).ok()?;
-        let mut authorization_url = {
-            let raw =
-                std::env::var(format!("{}_{identifier}", env::OAUTH_AUTHORIZATION_URL)).ok()?;
-            Url::parse(&raw).ok()?
-        };
+    pub async fn generate_authorization_url(
+        &self,
+        provider: impl AsRef<str>,
+    ) -> Result<Url, OAuthError> {
+        let config = self.provider(provider.as_ref())?;
+        let identifier = provider.as_ref().to_uppercase();
+        let mut authorization_url: Url = config.authorization_url.clone();
 
         // Generate some state value
-        let state = self.state.generate(format!("{identifier}-oauth")).await;
+        let state = self.state.generate(format!("{identifier}-oauth")).await?;
 
         // Build
         authorization_url
             .query_pairs_mut()
-            .append_pair("client_id", &client_id)
+            .append_pair("client_id", &config.client_id)
             .append_pair("state", &state);
 
-        Some(authorization_url)
+        Ok(authorization_url)
     }
 
     pub async fn complete_flow(
@@ -60,33 +89,25 @@ impl OAuthService {
         provider: impl AsRef<str>,
         state: String,
         code: String,
-    ) -> Option<i64> {
+    ) -> Result<i64, OAuthError> {
+        let config = self.provider(provider.as_ref())?;
         let identifier = provider.as_ref().to_uppercase();
 
         // Make sure state is valid
         if !self
             .state
             .redeem(format!("{identifier}-oauth"), state)
-            .await
+            .await?
         {
-            return None;
+            return Err(OAuthError::InvalidState);
         }
 
-        // Fetch required environment variables
-        let client_id = std::env::var(format!("{}_{identifier}", env::OAUTH_CLIENT_ID)).ok()?;
-        let client_secret =
-            std::env::var(format!("{}_{identifier}", env::OAUTH_CLIENT_SECRET)).ok()?;
-        let mut access_token_url = {
-            let raw =
-                std::env::var(format!("{}_{identifier}", env::OAUTH_ACCESS_TOKEN_URL)).ok()?;
-            Url::parse(&raw).ok()?
-        };
-
         // Build the URL
+        let mut access_token_url = config.access_token_url.clone();
         access_token_url
             .query_pairs_mut()
-            .append_pair("client_id", &client_id)
-            .append_pair("client_secret", &client_secret)
+            .append_pair("client_id", &config.client_id)
+            .append_pair("client_secret", &config.client_secret)
             .append_pair("code", &code);
 
         // Fetch the access token from the response
@@ -95,51 +116,67 @@ impl OAuthService {
             .post(access_token_url)
             .header(ACCEPT, "application/json")
             .send()
-            .await
-            .unwrap()
+            .await?
+            .error_for_status()?
             .json::<serde_json::Value>()
-            .await
-            .unwrap()["access_token"]
+            .await?["access_token"]
             .as_str()
-            .unwrap()
+            .ok_or(OAuthError::InvalidResponse("access_token"))?
             .to_string();
 
         match provider.as_ref() {
             "github" => {
-                // Fetch the username from github
-                let username = self
+                // Fetch the username and email from github
+                let user_info = self
                     .client
                     .get("https://api.github.com/user")
                     .header(ACCEPT, "application/json")
                     .header(AUTHORIZATION, format!("Bearer {access_token}"))
                     .header(USER_AGENT, "cloud-casino")
                     .send()
-                    .await
-                    .unwrap()
+                    .await?
+                    .error_for_status()?
                     .json::<serde_json::Value>()
-                    .await
-                    .unwrap()["login"]
+                    .await?;
+
+                let username = user_info["login"]
                     .as_str()
-                    .unwrap()
-                    .to_string();
-
-                return Some(
-                    // Attempt to fetch or insert into the database
-                    sqlx::query_scalar!(
-                        "INSERT INTO users (balance, last_login, created, auth_provider, auth_identifier)
-                            VALUES (?, DATETIME(), DATETIME(), 'github', ?)
-                            ON CONFLICT(auth_provider, auth_identifier)
-                                DO UPDATE SET last_login = DATETIME()
-                            RETURNING id;",
-                        DEFAULT_BALANCE,
-                        username
-                    )
-                        .fetch_one(&self.pool)
-                        .await
-                        .unwrap()
-                );
+                    .ok_or(OAuthError::InvalidResponse("login"))?;
+
+                let id = self.upsert_user("github", username).await?;
+
+                // The email is only provided if the user has made it public
+                if let Some(email) = user_info["email"].as_str() {
+                    self.users.set_email(UserId::from(id), email).await?;
+                }
+
+                Ok(id)
             }
-            _ => unreachable!("provider hasn't been implemented"),
+            provider => Err(OAuthError::NotConfigured(provider.to_string())),
+        }
+    }
+
+    /// Fetch the user with the given provider and identifier, creating them if they don't exist.
+    /// Banned users aren't allowed to log in.
+    pub async fn upsert_user(
+        &self,
+        provider: impl AsRef<str>,
+        identifier: impl AsRef<str>,
+    ) -> Result<i64, OAuthError> {
+        let id = self
+            .users
+            .upsert(provider.as_ref(), identifier.as_ref(), self.default_balance)
+            .await?;
+
+        if self
+            .users
+            .find(UserId::from(id))
+            .await?
+            .is_some_and(|user| user.banned)
+        {
+            return Err(OAuthError::Banned);
         }
+
+        Ok(id)
     }
 }
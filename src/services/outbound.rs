@@ -0,0 +1,209 @@
+//! Requests to URLs that users provide, such as webhooks and push services. Unless insecure URLs are
+//! allowed for local development, they must use `https` and can only reach public addresses, so that
+//! users can't make the server send requests into its own network.
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect, Client,
+};
+use url::{Host, Url};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum UrlError {
+    #[error("must be an absolute `http` or `https` URL")]
+    Invalid,
+
+    #[error("must use `https`")]
+    Insecure,
+
+    #[error("must be for a public address")]
+    PrivateAddress,
+}
+
+/// Whether the address can be reached from the internet, rather than being the server itself or
+/// something on its network.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                // "This network", including the unspecified address
+                || a == 0
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // Shared address space, used for carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+
+            let first = ip.segments()[0];
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local
+                || (first & 0xfe00) == 0xfc00
+                // Link-local
+                || (first & 0xffc0) == 0xfe80
+                // NAT64, which would be translated to any IPv4 address
+                || ip.segments()[..6] == [0x64, 0xff9b, 0, 0, 0, 0])
+        }
+    }
+}
+
+/// Resolve the host, only succeeding if every address it resolves to is public.
+async fn resolve_public(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let addrs = tokio::net::lookup_host((host, port))
+        .await?
+        .collect::<Vec<_>>();
+
+    if addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("`{host}` resolves to a private address"),
+        ));
+    }
+
+    Ok(addrs)
+}
+
+/// Resolver that refuses to connect to private addresses. The address is checked as the connection
+/// is made, so a host can't resolve to a public address when the URL is checked and a private one
+/// when the request is sent.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addrs = resolve_public(&host, 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Client for user provided URLs. Redirects are never followed, as they could lead anywhere.
+pub fn client(allow_insecure: bool) -> Client {
+    let mut client = Client::builder()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .redirect(redirect::Policy::none());
+    if !allow_insecure {
+        client = client.dns_resolver(Arc::new(PublicResolver));
+    }
+
+    client.build().expect("outbound client is valid")
+}
+
+/// Check that requests can be sent to the URL, without resolving its host.
+pub fn check_url(url: &Url, allow_insecure: bool) -> Result<(), UrlError> {
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(UrlError::Invalid);
+    }
+
+    if allow_insecure {
+        return Ok(());
+    }
+
+    if url.scheme() != "https" {
+        return Err(UrlError::Insecure);
+    }
+
+    let ip = match url.host() {
+        Some(Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(Host::Ipv6(ip)) => IpAddr::V6(ip),
+        _ => return Ok(()),
+    };
+    if !is_public(ip) {
+        return Err(UrlError::PrivateAddress);
+    }
+
+    Ok(())
+}
+
+/// Check that requests can be sent to the URL, resolving its host to ensure that it's public.
+pub async fn check_url_resolved(url: &Url, allow_insecure: bool) -> Result<(), UrlError> {
+    check_url(url, allow_insecure)?;
+
+    if let (false, Some(Host::Domain(domain))) = (allow_insecure, url.host()) {
+        resolve_public(domain, url.port_or_known_default().unwrap_or_default())
+            .await
+            .map_err(|_| UrlError::PrivateAddress)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "100.64.0.1",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn only_public_https_urls_are_allowed() {
+        for (url, allowed) in [
+            ("https://example.com/hook", Ok(())),
+            ("http://example.com/hook", Err(UrlError::Insecure)),
+            ("https://127.0.0.1/hook", Err(UrlError::PrivateAddress)),
+            ("https://[::1]/hook", Err(UrlError::PrivateAddress)),
+            ("ftp://example.com/hook", Err(UrlError::Invalid)),
+        ] {
+            assert_eq!(
+                check_url(&Url::parse(url).unwrap(), false),
+                allowed,
+                "{url}"
+            );
+        }
+
+        assert_eq!(
+            check_url(&Url::parse("http://127.0.0.1/hook").unwrap(), true),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn public_addresses_are_public() {
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+}
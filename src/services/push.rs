@@ -0,0 +1,634 @@
+use std::{sync::Arc, time::Duration};
+
+use aes_gcm::{aead::Aead, Aes128Gcm, KeyInit};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{NaiveDateTime, Utc};
+use hkdf::Hkdf;
+use p256::{
+    ecdh,
+    ecdsa::{signature::Signer, Signature, SigningKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+    PublicKey, SecretKey,
+};
+use rand::{rngs::OsRng, thread_rng, RngCore};
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use url::Url;
+
+use crate::{config::PushConfig, user::UserId};
+
+use super::{
+    outbound::{self, UrlError},
+    DbError,
+};
+
+/// Maximum time a push service has to respond.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the push service should hold on to a message for a browser that is offline.
+const TTL_SECS: u32 = 24 * 60 * 60;
+
+/// How long the VAPID token is valid for, which push services limit to 24 hours.
+const TOKEN_LIFETIME_SECS: i64 = 12 * 60 * 60;
+
+/// Size of the single record that each message is encrypted into.
+const RECORD_SIZE: u32 = 4096;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PushError {
+    #[error("VAPID private key is invalid")]
+    InvalidKey,
+
+    #[error("push subscription is invalid")]
+    InvalidSubscription,
+
+    #[error("push endpoint {0}")]
+    Endpoint(UrlError),
+
+    #[error("unable to encrypt push message")]
+    Encrypt,
+
+    #[error("unable to reach push service: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("push service responded with {0}")]
+    Rejected(StatusCode),
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+/// Subscription that a browser created with the `PushManager`, in the format produced by
+/// `PushSubscription.toJSON()`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewSubscription {
+    /// URL of the push service that messages are posted to
+    pub endpoint: String,
+
+    pub keys: SubscriptionKeys,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionKeys {
+    /// Browser's public key, base64url encoded
+    pub p256dh: String,
+
+    /// Browser's authentication secret, base64url encoded
+    pub auth: String,
+}
+
+/// Push subscription, as it appears in the database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SubscriptionRecord {
+    /// Unique ID of the subscription
+    pub id: i64,
+
+    /// URL of the push service that messages are posted to
+    pub endpoint: String,
+
+    /// Browser's public key, base64url encoded
+    pub p256dh: String,
+
+    /// Browser's authentication secret, base64url encoded
+    pub auth: String,
+
+    /// When the browser subscribed
+    pub created: NaiveDateTime,
+}
+
+/// Notification that is shown by the service worker, which opens the app when clicked.
+#[derive(Debug, Clone, Serialize)]
+pub struct PushMessage {
+    pub title: String,
+    pub body: String,
+}
+
+/// Storage for the browsers that users have subscribed.
+#[async_trait]
+pub trait PushRepository: Send + Sync {
+    /// Save a subscription for the user, replacing any other subscription with the same endpoint.
+    async fn subscribe(
+        &self,
+        user: UserId,
+        endpoint: &str,
+        p256dh: &str,
+        auth: &str,
+    ) -> Result<(), DbError>;
+
+    /// Remove one of the user's subscriptions. Returns whether the subscription existed.
+    async fn unsubscribe(&self, user: UserId, endpoint: &str) -> Result<bool, DbError>;
+
+    /// List all subscriptions belonging to a user.
+    async fn list(&self, user: UserId) -> Result<Vec<SubscriptionRecord>, DbError>;
+
+    /// Remove a subscription that the push service no longer accepts.
+    async fn remove(&self, id: i64) -> Result<(), DbError>;
+}
+
+/// Application server key that messages are signed with, identifying this server to push services
+/// (VAPID, RFC 8292).
+#[derive(Clone)]
+pub struct VapidKey {
+    signing_key: SigningKey,
+
+    /// Uncompressed public key, base64url encoded, as browsers expect it
+    public_key: String,
+}
+
+impl VapidKey {
+    /// Load the key from a base64url encoded private key.
+    pub fn new(private_key: &str) -> Result<Self, PushError> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(private_key.trim())
+            .map_err(|_| PushError::InvalidKey)?;
+        let signing_key = SigningKey::from_slice(&bytes).map_err(|_| PushError::InvalidKey)?;
+        let public_key = URL_SAFE_NO_PAD.encode(
+            signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+        );
+
+        Ok(Self {
+            signing_key,
+            public_key,
+        })
+    }
+
+    /// Generate a new private key, base64url encoded.
+    pub fn generate() -> String {
+        URL_SAFE_NO_PAD.encode(SigningKey::random(&mut OsRng).to_bytes())
+    }
+
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// Build the `Authorization` header for a request to the push service at `endpoint`.
+    fn authorization(&self, endpoint: &Url, subject: &str) -> String {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = URL_SAFE_NO_PAD.encode(
+            json!({
+                "aud": endpoint.origin().ascii_serialization(),
+                "exp": Utc::now().timestamp() + TOKEN_LIFETIME_SECS,
+                "sub": subject,
+            })
+            .to_string(),
+        );
+
+        let unsigned = format!("{header}.{claims}");
+        let signature: Signature = self.signing_key.sign(unsigned.as_bytes());
+
+        format!(
+            "vapid t={unsigned}.{}, k={}",
+            URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+            self.public_key
+        )
+    }
+}
+
+/// Pushes notifications to the browsers that users have subscribed, through their push service.
+#[derive(Clone)]
+pub struct PushService {
+    db: Arc<dyn PushRepository>,
+    client: Client,
+    key: Option<VapidKey>,
+    subject: String,
+    allow_insecure: bool,
+}
+
+impl PushService {
+    pub fn new(db: Arc<dyn PushRepository>, config: Option<&PushConfig>) -> Self {
+        let allow_insecure = config.is_some_and(|config| config.allow_insecure);
+
+        let key = config.and_then(|config| {
+            VapidKey::new(&config.private_key)
+                .inspect_err(|e| tracing::error!("push is disabled: {e}"))
+                .ok()
+        });
+
+        Self {
+            db,
+            client: outbound::client(allow_insecure),
+            key,
+            subject: config
+                .map(|config| config.subject.clone())
+                .unwrap_or_default(),
+            allow_insecure,
+        }
+    }
+
+    /// Public key that browsers must subscribe with, if push is configured.
+    pub fn public_key(&self) -> Option<&str> {
+        self.key.as_ref().map(VapidKey::public_key)
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub async fn subscribe(
+        &self,
+        user: UserId,
+        subscription: &NewSubscription,
+    ) -> Result<(), PushError> {
+        let endpoint =
+            Url::parse(&subscription.endpoint).map_err(|_| PushError::InvalidSubscription)?;
+        outbound::check_url_resolved(&endpoint, self.allow_insecure)
+            .await
+            .map_err(PushError::Endpoint)?;
+
+        // Ensure that messages can be encrypted for the browser
+        decode_keys(&subscription.keys.p256dh, &subscription.keys.auth)?;
+
+        self.db
+            .subscribe(
+                user,
+                &subscription.endpoint,
+                &subscription.keys.p256dh,
+                &subscription.keys.auth,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Remove one of the user's subscriptions. Returns whether the subscription existed.
+    pub async fn unsubscribe(&self, user: UserId, endpoint: &str) -> Result<bool, DbError> {
+        self.db.unsubscribe(user, endpoint).await
+    }
+
+    pub async fn list(&self, user: UserId) -> Result<Vec<SubscriptionRecord>, DbError> {
+        self.db.list(user).await
+    }
+
+    /// Push the message to each of the user's browsers, returning how many accepted it. Failures
+    /// for individual browsers are logged, and subscriptions that have expired are removed.
+    pub async fn send(&self, user: UserId, message: &PushMessage) -> Result<usize, DbError> {
+        let Some(key) = &self.key else {
+            return Ok(0);
+        };
+
+        let payload = serde_json::to_vec(message).expect("push message is serialisable");
+
+        let mut sent = 0;
+        for subscription in self.db.list(user).await? {
+            let outcome = match self.deliver(key, &subscription, &payload).await {
+                Ok(()) => {
+                    sent += 1;
+                    "delivered"
+                }
+                Err(PushError::Rejected(StatusCode::NOT_FOUND | StatusCode::GONE)) => {
+                    tracing::info!("removing expired push subscription {}", subscription.id);
+                    self.db.remove(subscription.id).await?;
+                    "expired"
+                }
+                Err(e) => {
+                    tracing::warn!("unable to push to subscription {}: {e}", subscription.id);
+                    "failed"
+                }
+            };
+
+            metrics::counter!("push_messages_total", "outcome" => outcome).increment(1);
+        }
+
+        Ok(sent)
+    }
+
+    async fn deliver(
+        &self,
+        key: &VapidKey,
+        subscription: &SubscriptionRecord,
+        payload: &[u8],
+    ) -> Result<(), PushError> {
+        let endpoint =
+            Url::parse(&subscription.endpoint).map_err(|_| PushError::InvalidSubscription)?;
+        // Subscriptions saved before endpoints were checked may not be allowed any more
+        outbound::check_url(&endpoint, self.allow_insecure).map_err(PushError::Endpoint)?;
+        let (public_key, auth) = decode_keys(&subscription.p256dh, &subscription.auth)?;
+        let body = encrypt(&public_key, &auth, payload)?;
+
+        let response = self
+            .client
+            .post(endpoint.clone())
+            .timeout(REQUEST_TIMEOUT)
+            .header(AUTHORIZATION, key.authorization(&endpoint, &self.subject))
+            .header(CONTENT_ENCODING, "aes128gcm")
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header("ttl", TTL_SECS)
+            .body(body)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(PushError::Rejected(response.status()))
+        }
+    }
+}
+
+/// Decode the browser's public key and authentication secret.
+fn decode_keys(p256dh: &str, auth: &str) -> Result<(PublicKey, Vec<u8>), PushError> {
+    let public_key = URL_SAFE_NO_PAD
+        .decode(p256dh.trim_end_matches('='))
+        .ok()
+        .and_then(|bytes| PublicKey::from_sec1_bytes(&bytes).ok())
+        .ok_or(PushError::InvalidSubscription)?;
+
+    let auth = URL_SAFE_NO_PAD
+        .decode(auth.trim_end_matches('='))
+        .ok()
+        .filter(|auth| auth.len() == 16)
+        .ok_or(PushError::InvalidSubscription)?;
+
+    Ok((public_key, auth))
+}
+
+/// Encrypt the payload for the browser, with the `aes128gcm` content encoding (RFC 8188), using
+/// the keys derived as described in RFC 8291.
+fn encrypt(public_key: &PublicKey, auth: &[u8], payload: &[u8]) -> Result<Vec<u8>, PushError> {
+    let mut salt = [0u8; 16];
+    thread_rng().fill_bytes(&mut salt);
+
+    encrypt_with(
+        &SecretKey::random(&mut OsRng),
+        &salt,
+        public_key,
+        auth,
+        payload,
+    )
+}
+
+/// Encrypt the payload with the given ephemeral key and salt, which must never be reused.
+fn encrypt_with(
+    secret: &SecretKey,
+    salt: &[u8; 16],
+    public_key: &PublicKey,
+    auth: &[u8],
+    payload: &[u8],
+) -> Result<Vec<u8>, PushError> {
+    let server_public_key = secret.public_key().to_encoded_point(false);
+    let client_public_key = public_key.to_encoded_point(false);
+    let shared_secret = ecdh::diffie_hellman(secret.to_nonzero_scalar(), public_key.as_affine());
+
+    // Combine the shared secret with the browser's authentication secret
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(client_public_key.as_bytes());
+    key_info.extend_from_slice(server_public_key.as_bytes());
+
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(auth), shared_secret.raw_secret_bytes())
+        .expand(&key_info, &mut ikm)
+        .map_err(|_| PushError::Encrypt)?;
+
+    // Derive the content encryption key and nonce for this message
+    let hkdf = Hkdf::<Sha256>::new(Some(salt), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    hkdf.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .and_then(|_| hkdf.expand(b"Content-Encoding: nonce\0", &mut nonce))
+        .map_err(|_| PushError::Encrypt)?;
+
+    // The whole message fits in a single record, which is marked as the last
+    let mut record = payload.to_vec();
+    record.push(0x02);
+
+    let ciphertext = Aes128Gcm::new(&cek.into())
+        .encrypt(&nonce.into(), record.as_slice())
+        .map_err(|_| PushError::Encrypt)?;
+
+    let mut body = Vec::with_capacity(86 + ciphertext.len());
+    body.extend_from_slice(salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(server_public_key.len() as u8);
+    body.extend_from_slice(server_public_key.as_bytes());
+    body.extend_from_slice(&ciphertext);
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::{body::Bytes, extract::State, http::HeaderMap, routing::post, Router};
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    use super::*;
+
+    fn decode(value: &str) -> Vec<u8> {
+        URL_SAFE_NO_PAD.decode(value).unwrap()
+    }
+
+    /// Decrypt a message as the browser would, returning the payload.
+    fn decrypt(secret: &SecretKey, auth: &[u8], body: &[u8]) -> Vec<u8> {
+        let (salt, rest) = body.split_at(16);
+        let key_len = rest[4] as usize;
+        let server_public_key = PublicKey::from_sec1_bytes(&rest[5..5 + key_len]).unwrap();
+        let ciphertext = &rest[5 + key_len..];
+
+        let shared_secret =
+            ecdh::diffie_hellman(secret.to_nonzero_scalar(), server_public_key.as_affine());
+
+        let mut key_info = b"WebPush: info\0".to_vec();
+        key_info.extend_from_slice(secret.public_key().to_encoded_point(false).as_bytes());
+        key_info.extend_from_slice(server_public_key.to_encoded_point(false).as_bytes());
+
+        let mut ikm = [0u8; 32];
+        Hkdf::<Sha256>::new(Some(auth), shared_secret.raw_secret_bytes())
+            .expand(&key_info, &mut ikm)
+            .unwrap();
+
+        let hkdf = Hkdf::<Sha256>::new(Some(salt), &ikm);
+        let mut cek = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        hkdf.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+            .unwrap();
+        hkdf.expand(b"Content-Encoding: nonce\0", &mut nonce)
+            .unwrap();
+
+        let mut record = Aes128Gcm::new(&cek.into())
+            .decrypt(&nonce.into(), ciphertext)
+            .unwrap();
+        assert_eq!(
+            record.pop(),
+            Some(0x02),
+            "single record is marked as the last"
+        );
+
+        record
+    }
+
+    /// Example from RFC 8291, appendix A.
+    #[test]
+    fn encrypts_rfc_8291_example() {
+        let server_secret =
+            SecretKey::from_slice(&decode("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw")).unwrap();
+        let client_public_key = PublicKey::from_sec1_bytes(&decode(
+            "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4",
+        ))
+        .unwrap();
+        let auth = decode("BTBZMqHH6r4Tts7J_aSIgg");
+        let salt = decode("DGv6ra1nlYgDCS1FRnbzlw").try_into().unwrap();
+
+        let body = encrypt_with(
+            &server_secret,
+            &salt,
+            &client_public_key,
+            &auth,
+            b"When I grow up, I want to be a watermelon",
+        )
+        .unwrap();
+
+        assert_eq!(
+            URL_SAFE_NO_PAD.encode(body),
+            "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmY\
+                WAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgS\
+                xsj_Qulcy4a-fN"
+        );
+    }
+
+    /// Subscriptions kept in memory.
+    #[derive(Default)]
+    struct MemoryRepository(Mutex<Vec<SubscriptionRecord>>);
+
+    #[async_trait]
+    impl PushRepository for MemoryRepository {
+        async fn subscribe(
+            &self,
+            _user: UserId,
+            endpoint: &str,
+            p256dh: &str,
+            auth: &str,
+        ) -> Result<(), DbError> {
+            let mut subscriptions = self.0.lock().unwrap();
+            let id = subscriptions.len() as i64 + 1;
+            subscriptions.push(SubscriptionRecord {
+                id,
+                endpoint: endpoint.to_string(),
+                p256dh: p256dh.to_string(),
+                auth: auth.to_string(),
+                created: Utc::now().naive_utc(),
+            });
+
+            Ok(())
+        }
+
+        async fn unsubscribe(&self, _user: UserId, endpoint: &str) -> Result<bool, DbError> {
+            let mut subscriptions = self.0.lock().unwrap();
+            let count = subscriptions.len();
+            subscriptions.retain(|subscription| subscription.endpoint != endpoint);
+
+            Ok(subscriptions.len() < count)
+        }
+
+        async fn list(&self, _user: UserId) -> Result<Vec<SubscriptionRecord>, DbError> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        async fn remove(&self, id: i64) -> Result<(), DbError> {
+            self.0
+                .lock()
+                .unwrap()
+                .retain(|subscription| subscription.id != id);
+
+            Ok(())
+        }
+    }
+
+    fn service(allow_insecure: bool) -> PushService {
+        PushService::new(
+            Arc::new(MemoryRepository::default()),
+            Some(&PushConfig {
+                private_key: VapidKey::generate(),
+                subject: "mailto:test@example.com".to_string(),
+                allow_insecure,
+            }),
+        )
+    }
+
+    fn subscription(endpoint: String, secret: &SecretKey, auth: &[u8]) -> NewSubscription {
+        NewSubscription {
+            endpoint,
+            keys: SubscriptionKeys {
+                p256dh: URL_SAFE_NO_PAD.encode(secret.public_key().to_encoded_point(false)),
+                auth: URL_SAFE_NO_PAD.encode(auth),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_insecure_endpoints() {
+        let push = service(false);
+        let secret = SecretKey::random(&mut OsRng);
+
+        for endpoint in ["http://push.example.com/1", "https://127.0.0.1/1"] {
+            assert!(matches!(
+                push.subscribe(
+                    UserId::from(1),
+                    &subscription(endpoint.to_string(), &secret, &[0; 16])
+                )
+                .await,
+                Err(PushError::Endpoint(_))
+            ));
+        }
+    }
+
+    /// Send a message to a stub push service, checking that the browser could read it.
+    #[tokio::test]
+    async fn delivers_to_push_service() {
+        let (sender, mut received) = mpsc::unbounded_channel::<(HeaderMap, Bytes)>();
+        let app = Router::new()
+            .route(
+                "/push/:id",
+                post(
+                    |State(sender): State<mpsc::UnboundedSender<_>>,
+                     headers: HeaderMap,
+                     body: Bytes| async move {
+                        sender.send((headers, body)).unwrap();
+                        StatusCode::CREATED
+                    },
+                ),
+            )
+            .with_state(sender);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let push = service(true);
+        let user = UserId::from(1);
+        let secret = SecretKey::random(&mut OsRng);
+        let auth = [7; 16];
+        push.subscribe(
+            user,
+            &subscription(format!("http://{address}/push/1"), &secret, &auth),
+        )
+        .await
+        .unwrap();
+
+        let message = PushMessage {
+            title: "Bets settled".to_string(),
+            body: "You won $10.00".to_string(),
+        };
+        assert_eq!(push.send(user, &message).await.unwrap(), 1);
+
+        let (headers, body) = received.recv().await.unwrap();
+        assert_eq!(headers[CONTENT_ENCODING], "aes128gcm");
+        assert_eq!(headers["ttl"], TTL_SECS.to_string());
+        assert!(headers[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .ends_with(&format!("k={}", push.public_key().unwrap())));
+        assert_eq!(
+            decrypt(&secret, &auth, &body),
+            serde_json::to_vec(&message).unwrap()
+        );
+    }
+}
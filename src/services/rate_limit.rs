@@ -0,0 +1,244 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{RateLimit, RateLimitConfig},
+    user::UserId,
+};
+
+/// Action that is limited separately to all others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Login,
+    Bet,
+    Payout,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Login => "login",
+            Action::Bet => "bet",
+            Action::Payout => "payout",
+        }
+    }
+}
+
+/// Who an action is being limited for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Client {
+    Ip(IpAddr),
+    User(UserId),
+}
+
+/// Whole seconds to wait before retrying, for the `Retry-After` header.
+pub fn retry_after(wait: &Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+/// Requests that can currently be made, which refill continuously up to the limit.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Top up the bucket for the time since it was last updated.
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        let rate = limit.requests as f64 / Duration::from_millis(limit.period_ms).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * rate).min(limit.requests as f64);
+        self.updated = now;
+    }
+
+    fn is_full(&self, limit: &RateLimit) -> bool {
+        self.tokens >= limit.requests as f64
+    }
+}
+
+/// Limits how often each client can perform expensive or abusable actions. Counts are kept in
+/// memory, so each instance of the server enforces its own limits.
+#[derive(Clone)]
+pub struct RateLimitService {
+    config: RateLimitConfig,
+    trust_forwarded_for: bool,
+    buckets: Arc<Mutex<HashMap<(Action, Client), Bucket>>>,
+}
+
+impl RateLimitService {
+    pub fn new(config: &RateLimitConfig, trust_forwarded_for: bool) -> Self {
+        Self {
+            config: config.clone(),
+            trust_forwarded_for,
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Whether `X-Forwarded-For` can be trusted to identify the client.
+    pub fn trust_forwarded_for(&self) -> bool {
+        self.trust_forwarded_for
+    }
+
+    fn limit(&self, action: Action) -> &RateLimit {
+        match action {
+            Action::Login => &self.config.login,
+            Action::Bet => &self.config.bet,
+            Action::Payout => &self.config.payout,
+        }
+    }
+
+    /// Record an attempt at an action by each of the clients, only allowing it if none of them
+    /// have reached their limit. Otherwise, produce how long until it will be allowed.
+    pub fn check(&self, action: Action, clients: &[Client]) -> Result<(), Duration> {
+        self.check_at(action, clients, Instant::now())
+    }
+
+    fn check_at(&self, action: Action, clients: &[Client], now: Instant) -> Result<(), Duration> {
+        let limit = self.limit(action);
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let mut wait = Duration::ZERO;
+        for client in clients {
+            let bucket = buckets.entry((action, *client)).or_insert(Bucket {
+                tokens: limit.requests as f64,
+                updated: now,
+            });
+            bucket.refill(limit, now);
+
+            if bucket.tokens < 1.0 {
+                let missing = 1.0 - bucket.tokens;
+                wait = wait.max(Duration::from_secs_f64(
+                    missing * limit.period_ms as f64 / 1000.0 / limit.requests as f64,
+                ));
+            }
+        }
+
+        if !wait.is_zero() {
+            metrics::counter!("rate_limited_requests_total", "action" => action.name())
+                .increment(1);
+
+            return Err(wait);
+        }
+
+        for client in clients {
+            if let Some(bucket) = buckets.get_mut(&(action, *client)) {
+                bucket.tokens -= 1.0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forget every client that is back at their full limit.
+    pub fn delete_expired(&self) {
+        let now = Instant::now();
+
+        self.buckets.lock().unwrap().retain(|(action, _), bucket| {
+            let limit = self.limit(*action);
+            bucket.refill(limit, now);
+
+            !bucket.is_full(limit)
+        });
+    }
+
+    /// Continuously forget clients that are back at their full limit, waiting `period` between
+    /// each clean up.
+    pub async fn continuously_delete_expired(self, period: tokio::time::Duration) {
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            self.delete_expired();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    /// Two logins a second, and one bet.
+    fn service() -> RateLimitService {
+        RateLimitService::new(
+            &RateLimitConfig {
+                login: RateLimit {
+                    requests: 2,
+                    period_ms: 1000,
+                },
+                bet: RateLimit {
+                    requests: 1,
+                    period_ms: 1000,
+                },
+                ..RateLimitConfig::default()
+            },
+            false,
+        )
+    }
+
+    const IP: Client = Client::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    #[test]
+    fn limits_each_client() {
+        let rate_limit = service();
+        let now = Instant::now();
+        let user = Client::User(UserId::from(1));
+
+        assert_eq!(rate_limit.check_at(Action::Login, &[IP], now), Ok(()));
+        assert_eq!(rate_limit.check_at(Action::Login, &[IP], now), Ok(()));
+        assert_eq!(
+            rate_limit.check_at(Action::Login, &[IP], now),
+            Err(Duration::from_millis(500))
+        );
+
+        // Other clients and actions are limited separately
+        assert_eq!(rate_limit.check_at(Action::Login, &[user], now), Ok(()));
+        assert_eq!(rate_limit.check_at(Action::Bet, &[IP], now), Ok(()));
+
+        // Any client at its limit blocks the others, without using up their requests
+        assert!(rate_limit
+            .check_at(Action::Login, &[user, IP], now)
+            .is_err());
+        assert_eq!(rate_limit.check_at(Action::Login, &[user], now), Ok(()));
+    }
+
+    #[test]
+    fn refills_over_period() {
+        let rate_limit = service();
+        let now = Instant::now();
+
+        rate_limit.check_at(Action::Login, &[IP], now).unwrap();
+        rate_limit.check_at(Action::Login, &[IP], now).unwrap();
+
+        let later = now + Duration::from_millis(250);
+        assert_eq!(
+            rate_limit.check_at(Action::Login, &[IP], later),
+            Err(Duration::from_millis(250))
+        );
+
+        let later = now + Duration::from_millis(500);
+        assert_eq!(rate_limit.check_at(Action::Login, &[IP], later), Ok(()));
+        assert!(rate_limit.check_at(Action::Login, &[IP], later).is_err());
+
+        // Never beyond the limit, however long it's been
+        let later = now + Duration::from_secs(60);
+        assert_eq!(rate_limit.check_at(Action::Login, &[IP], later), Ok(()));
+        assert_eq!(rate_limit.check_at(Action::Login, &[IP], later), Ok(()));
+        assert!(rate_limit.check_at(Action::Login, &[IP], later).is_err());
+    }
+
+    #[test]
+    fn rounds_retry_after_up() {
+        assert_eq!(retry_after(&Duration::from_millis(1)), 1);
+        assert_eq!(retry_after(&Duration::from_millis(1500)), 2);
+        assert_eq!(retry_after(&Duration::ZERO), 1);
+    }
+}
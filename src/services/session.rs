@@ -0,0 +1,168 @@
+use std::{str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use tower_sessions::{cookie::time, session::Id, SessionStore};
+
+use crate::{db::AnySessionStore, user::UserId};
+
+use super::{
+    audit::{AuditEvent, AuditKind, AuditService},
+    DbError,
+};
+
+/// Sessions are expired after this period without any activity.
+pub const SESSION_INACTIVITY: time::Duration = time::Duration::days(30);
+
+/// Session information, as it appears in the database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SessionRecord {
+    /// Identifier of the session within the session store
+    pub id: String,
+
+    /// User agent the session was last used from
+    pub user_agent: Option<String>,
+
+    /// When the session was first seen
+    pub created: NaiveDateTime,
+
+    /// When the session was last used
+    pub last_seen: NaiveDateTime,
+}
+
+/// Storage for the sessions that belong to each user.
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    /// Insert a session that has just been logged in to, replacing any with the same ID.
+    async fn insert(&self, id: &str, user: UserId, user_agent: Option<&str>)
+        -> Result<(), DbError>;
+
+    /// Update when the session was last seen. Returns whether the session exists, which it won't
+    /// once it has been revoked.
+    async fn touch(
+        &self,
+        id: &str,
+        user: UserId,
+        user_agent: Option<&str>,
+    ) -> Result<bool, DbError>;
+
+    /// Whether the session belongs to the user and hasn't been revoked.
+    async fn exists(&self, id: &str, user: UserId) -> Result<bool, DbError>;
+
+    /// Remove the user's sessions that haven't been seen since `cutoff`.
+    async fn delete_inactive(&self, user: UserId, cutoff: NaiveDateTime) -> Result<(), DbError>;
+
+    /// List all of the user's sessions, most recently used first.
+    async fn list(&self, user: UserId) -> Result<Vec<SessionRecord>, DbError>;
+
+    /// Remove a session, as long as it belongs to the user. Returns whether it was removed.
+    async fn remove(&self, user: UserId, id: &str) -> Result<bool, DbError>;
+
+    /// Remove all of the user's sessions, returning the IDs of those that were removed.
+    async fn remove_all(&self, user: UserId) -> Result<Vec<String>, DbError>;
+}
+
+#[derive(Clone)]
+pub struct SessionService {
+    db: Arc<dyn SessionRepository>,
+    store: AnySessionStore,
+    audit: AuditService,
+}
+
+impl SessionService {
+    pub fn new(
+        db: Arc<dyn SessionRepository>,
+        store: AnySessionStore,
+        audit: AuditService,
+    ) -> Self {
+        Self { db, store, audit }
+    }
+
+    /// Start tracking a session that the user has just logged in to.
+    pub async fn begin(
+        &self,
+        id: Id,
+        user: UserId,
+        user_agent: Option<&str>,
+    ) -> Result<(), DbError> {
+        self.db.insert(&id.to_string(), user, user_agent).await
+    }
+
+    /// Record activity for a session belonging to the given user. Returns whether the session is
+    /// still active, as a revoked session is never brought back.
+    pub async fn touch(
+        &self,
+        id: Id,
+        user: UserId,
+        user_agent: Option<&str>,
+    ) -> Result<bool, DbError> {
+        self.db.touch(&id.to_string(), user, user_agent).await
+    }
+
+    /// Whether a session belonging to the given user is still active, without recording activity.
+    pub async fn is_active(&self, id: Id, user: UserId) -> Result<bool, DbError> {
+        self.db.exists(&id.to_string(), user).await
+    }
+
+    /// List all of the active sessions for a user, most recently used first.
+    pub async fn list(&self, user: UserId) -> Result<Vec<SessionRecord>, DbError> {
+        let cutoff = (Utc::now() - chrono::Duration::seconds(SESSION_INACTIVITY.whole_seconds()))
+            .naive_utc();
+
+        // Clear out any sessions that have since expired
+        self.db.delete_inactive(user, cutoff).await?;
+
+        self.db.list(user).await
+    }
+
+    /// Revoke a session belonging to the user, removing it from the session store. Returns
+    /// whether the session existed.
+    pub async fn revoke(&self, user: UserId, id: impl AsRef<str>) -> Result<bool, DbError> {
+        let id = id.as_ref();
+
+        let Ok(session_id) = Id::from_str(id) else {
+            return Ok(false);
+        };
+
+        // Only remove the session if it belongs to the user
+        let removed = self.db.remove(user, id).await?;
+
+        if removed {
+            self.store.delete(&session_id).await?;
+
+            self.audit
+                .record(AuditEvent::new(
+                    AuditKind::SessionRevoked,
+                    Some(user),
+                    "signed out of a session",
+                ))
+                .await;
+        }
+
+        Ok(removed)
+    }
+
+    /// Revoke all of the user's sessions, signing them out everywhere. Returns the number of
+    /// sessions that were revoked.
+    pub async fn revoke_all(&self, user: UserId) -> Result<usize, DbError> {
+        let ids = self.db.remove_all(user).await?;
+
+        for id in &ids {
+            if let Ok(session_id) = Id::from_str(id) {
+                self.store.delete(&session_id).await?;
+            }
+        }
+
+        if !ids.is_empty() {
+            self.audit
+                .record(AuditEvent::new(
+                    AuditKind::SessionRevoked,
+                    Some(user),
+                    format!("signed out of all {} sessions", ids.len()),
+                ))
+                .await;
+        }
+
+        Ok(ids.len())
+    }
+}
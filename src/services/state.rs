@@ -1,21 +1,56 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
 use base64::{engine::general_purpose::URL_SAFE, Engine};
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use rand::{thread_rng, RngCore};
-use sqlx::SqlitePool;
+
+use super::{
+    audit::{AuditEvent, AuditKind, AuditService},
+    DbError,
+};
 
 static RANDOM_BUFFER_LEN: usize = 32;
 
+/// Duration that a state value may be redeemed for after being generated.
+const STATE_TTL: Duration = Duration::minutes(10);
+
+/// Storage for generated state values.
+#[async_trait]
+pub trait StateRepository: Send + Sync {
+    async fn insert(
+        &self,
+        namespace: &str,
+        value: &str,
+        generated: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+
+    /// Mark a value as redeemed, as long as it hasn't been already and was generated after
+    /// `cutoff`. Returns whether the value was redeemed.
+    async fn redeem(
+        &self,
+        namespace: &str,
+        value: &str,
+        redeemed: DateTime<Utc>,
+        cutoff: DateTime<Utc>,
+    ) -> Result<bool, DbError>;
+
+    /// Remove all values that have been redeemed, or were generated before `cutoff`.
+    async fn delete_expired(&self, cutoff: DateTime<Utc>) -> Result<u64, DbError>;
+}
+
 #[derive(Clone)]
 pub struct StateService {
-    pool: SqlitePool,
+    db: Arc<dyn StateRepository>,
+    audit: AuditService,
 }
 
 impl StateService {
-    pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+    pub fn new(db: Arc<dyn StateRepository>, audit: AuditService) -> Self {
+        Self { db, audit }
     }
 
-    pub async fn generate(&self, namespace: impl AsRef<str>) -> String {
+    pub async fn generate(&self, namespace: impl AsRef<str>) -> Result<String, DbError> {
         let namespace = namespace.as_ref();
 
         // Generate the value
@@ -26,40 +61,54 @@ impl StateService {
             URL_SAFE.encode(bytes)
         };
 
-        let now = Utc::now();
-
         // Insert into the DB
-        sqlx::query!(
-            "INSERT INTO states (namespace, value, generated)
-                VALUES (?, ?, ?);",
-            namespace,
-            value,
-            now
-        )
-        .execute(&self.pool)
-        .await
-        .unwrap();
-
-        value
+        self.db.insert(namespace, &value, Utc::now()).await?;
+
+        Ok(value)
     }
 
-    pub async fn redeem(&self, namespace: impl AsRef<str>, value: impl AsRef<str>) -> bool {
+    pub async fn redeem(
+        &self,
+        namespace: impl AsRef<str>,
+        value: impl AsRef<str>,
+    ) -> Result<bool, DbError> {
         let namespace = namespace.as_ref();
         let value = value.as_ref();
         let now = Utc::now();
+        let cutoff = now - STATE_TTL;
+
+        let redeemed = self.db.redeem(namespace, value, now, cutoff).await?;
+
+        if !redeemed {
+            self.audit
+                .record(AuditEvent::new(
+                    AuditKind::StateRejected,
+                    None,
+                    format!("rejected state for `{namespace}`"),
+                ))
+                .await;
+        }
+
+        Ok(redeemed)
+    }
+
+    /// Remove all state values that have either been redeemed, or can no longer be redeemed.
+    pub async fn delete_expired(&self) -> Result<u64, DbError> {
+        let cutoff = Utc::now() - STATE_TTL;
+
+        self.db.delete_expired(cutoff).await
+    }
+
+    /// Continuously delete expired state values, waiting `period` between each clean up.
+    pub async fn continuously_delete_expired(self, period: tokio::time::Duration) {
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
 
-        sqlx::query!(
-            "UPDATE states
-                SET redeemed = ?
-                WHERE namespace = ? AND value = ? and redeemed IS NULL
-                RETURNING value;",
-            now,
-            namespace,
-            value
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .unwrap()
-        .is_some()
+            if let Err(e) = self.delete_expired().await {
+                tracing::error!("failed to delete expired state values: {e}");
+            }
+        }
     }
 }
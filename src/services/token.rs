@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::user::UserId;
+
+use super::DbError;
+
+static RANDOM_BUFFER_LEN: usize = 32;
+
+/// Prefix for all generated tokens, making them easy to identify.
+const TOKEN_PREFIX: &str = "cc_";
+
+/// Number of characters of the token that are stored, so the user can identify it.
+const DISPLAY_PREFIX_LEN: usize = 8;
+
+/// Personal access token, as it appears in the database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TokenRecord {
+    /// Unique ID of the token
+    pub id: i64,
+
+    /// Name given to the token
+    pub name: String,
+
+    /// Start of the token value
+    pub prefix: String,
+
+    /// When the token was created
+    pub created: NaiveDateTime,
+
+    /// Last time the token was used
+    pub last_used: Option<NaiveDateTime>,
+}
+
+/// Storage for personal access tokens, identified by the hash of their value.
+#[async_trait]
+pub trait TokenRepository: Send + Sync {
+    async fn insert(
+        &self,
+        user: UserId,
+        name: &str,
+        prefix: &str,
+        token_hash: &str,
+    ) -> Result<(), DbError>;
+
+    /// List all tokens belonging to a user, most recently created first.
+    async fn list(&self, user: UserId) -> Result<Vec<TokenRecord>, DbError>;
+
+    /// Remove one of the user's tokens. Returns whether the token existed.
+    async fn remove(&self, user: UserId, id: i64) -> Result<bool, DbError>;
+
+    /// Remove all of the user's tokens, returning how many were removed.
+    async fn remove_all(&self, user: UserId) -> Result<u64, DbError>;
+
+    /// Find the user that a token hash belongs to, recording that the token was used.
+    async fn authenticate(&self, token_hash: &str) -> Result<Option<UserId>, DbError>;
+}
+
+#[derive(Clone)]
+pub struct TokenService {
+    db: Arc<dyn TokenRepository>,
+}
+
+impl TokenService {
+    pub fn new(db: Arc<dyn TokenRepository>) -> Self {
+        Self { db }
+    }
+
+    fn hash(token: &str) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+    }
+
+    /// Create a new token for the user. The returned value is the only time that the token is
+    /// available, as only a hash of it is stored.
+    pub async fn create(&self, user: UserId, name: impl AsRef<str>) -> Result<String, DbError> {
+        let name = name.as_ref();
+
+        // Generate the value
+        let token = {
+            let mut bytes = vec![0u8; RANDOM_BUFFER_LEN];
+            thread_rng().fill_bytes(&mut bytes);
+
+            format!("{TOKEN_PREFIX}{}", URL_SAFE_NO_PAD.encode(bytes))
+        };
+
+        let prefix = &token[..TOKEN_PREFIX.len() + DISPLAY_PREFIX_LEN];
+        let token_hash = Self::hash(&token);
+
+        self.db.insert(user, name, prefix, &token_hash).await?;
+
+        Ok(token)
+    }
+
+    /// List all tokens belonging to a user.
+    pub async fn list(&self, user: UserId) -> Result<Vec<TokenRecord>, DbError> {
+        self.db.list(user).await
+    }
+
+    /// Revoke one of the user's tokens. Returns whether the token existed.
+    pub async fn revoke(&self, user: UserId, id: i64) -> Result<bool, DbError> {
+        self.db.remove(user, id).await
+    }
+
+    /// Revoke all of the user's tokens. Returns the number of tokens that were revoked.
+    pub async fn revoke_all(&self, user: UserId) -> Result<u64, DbError> {
+        self.db.remove_all(user).await
+    }
+
+    /// Find the user that a token belongs to, recording that the token was used.
+    pub async fn authenticate(&self, token: impl AsRef<str>) -> Result<Option<UserId>, DbError> {
+        let token_hash = Self::hash(token.as_ref());
+
+        self.db.authenticate(&token_hash).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::Utc;
+
+    use super::*;
+
+    /// Tokens kept in memory, along with the user and hash of each.
+    #[derive(Default)]
+    struct MemoryRepository(Mutex<Vec<(UserId, String, TokenRecord)>>);
+
+    #[async_trait]
+    impl TokenRepository for MemoryRepository {
+        async fn insert(
+            &self,
+            user: UserId,
+            name: &str,
+            prefix: &str,
+            token_hash: &str,
+        ) -> Result<(), DbError> {
+            let mut tokens = self.0.lock().unwrap();
+            let id = tokens.len() as i64 + 1;
+            tokens.push((
+                user,
+                token_hash.to_string(),
+                TokenRecord {
+                    id,
+                    name: name.to_string(),
+                    prefix: prefix.to_string(),
+                    created: Utc::now().naive_utc(),
+                    last_used: None,
+                },
+            ));
+
+            Ok(())
+        }
+
+        async fn list(&self, user: UserId) -> Result<Vec<TokenRecord>, DbError> {
+            Ok(self
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(owner, _, _)| *owner == user)
+                .map(|(_, _, token)| token.clone())
+                .collect())
+        }
+
+        async fn remove(&self, user: UserId, id: i64) -> Result<bool, DbError> {
+            let mut tokens = self.0.lock().unwrap();
+            let count = tokens.len();
+            tokens.retain(|(owner, _, token)| !(*owner == user && token.id == id));
+
+            Ok(tokens.len() < count)
+        }
+
+        async fn remove_all(&self, user: UserId) -> Result<u64, DbError> {
+            let mut tokens = self.0.lock().unwrap();
+            let count = tokens.len();
+            tokens.retain(|(owner, _, _)| *owner != user);
+
+            Ok((count - tokens.len()) as u64)
+        }
+
+        async fn authenticate(&self, token_hash: &str) -> Result<Option<UserId>, DbError> {
+            let mut tokens = self.0.lock().unwrap();
+            let Some((user, _, token)) = tokens.iter_mut().find(|(_, hash, _)| hash == token_hash)
+            else {
+                return Ok(None);
+            };
+
+            token.last_used = Some(Utc::now().naive_utc());
+            Ok(Some(*user))
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticates_until_revoked() {
+        let db = Arc::new(MemoryRepository::default());
+        let tokens = TokenService::new(db.clone());
+        let alice = UserId::from(1);
+        let bob = UserId::from(2);
+
+        let token = tokens.create(alice, "script").await.unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(tokens.authenticate(&token).await.unwrap(), Some(alice));
+        assert_eq!(tokens.authenticate("cc_made-up").await.unwrap(), None);
+
+        // Only the hash and a short prefix are stored
+        let (_, hash, record) = db.0.lock().unwrap()[0].clone();
+        assert_ne!(hash, token);
+        assert!(token.starts_with(&record.prefix));
+        assert!(record.prefix.len() < token.len());
+        assert!(record.last_used.is_some());
+
+        // Other users can't revoke it
+        assert!(!tokens.revoke(bob, record.id).await.unwrap());
+        assert!(tokens.revoke(alice, record.id).await.unwrap());
+        assert_eq!(tokens.authenticate(&token).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn generates_unique_tokens() {
+        let tokens = TokenService::new(Arc::new(MemoryRepository::default()));
+        let user = UserId::from(1);
+
+        let first = tokens.create(user, "first").await.unwrap();
+        let second = tokens.create(user, "second").await.unwrap();
+        assert_ne!(first, second);
+
+        assert_eq!(tokens.revoke_all(user).await.unwrap(), 2);
+        assert_eq!(tokens.authenticate(&first).await.unwrap(), None);
+        assert_eq!(tokens.authenticate(&second).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::user::UserId;
+
+use super::{
+    audit::{AuditEvent, AuditKind, AuditService},
+    events::{Event, EventService},
+    session::SessionService,
+    token::TokenService,
+    webhooks::{WebhookEvent, WebhookService},
+    DbError,
+};
+
+/// Role given to users that can access the admin pages.
+const ADMIN_ROLE: &str = "admin";
+
+/// Every role that a user can have.
+pub const ROLES: [&str; 2] = ["user", ADMIN_ROLE];
+
+/// User account, as it appears in the database.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct UserRecord {
+    pub id: i64,
+
+    /// Provider the user logs in with
+    pub auth_provider: String,
+
+    /// Identifier of the user with the provider
+    pub auth_identifier: String,
+
+    /// Email address provided by the login provider, if there was one
+    pub email: Option<String>,
+
+    pub balance: f64,
+
+    /// Either `user` or `admin`
+    pub role: String,
+
+    /// Whether the user has been banned
+    pub banned: bool,
+
+    /// When the user was first created
+    pub created: NaiveDateTime,
+
+    /// Last time the user logged in
+    pub last_login: NaiveDateTime,
+
+    /// When the user deleted their account, if they have
+    pub deleted: Option<NaiveDateTime>,
+}
+
+impl UserRecord {
+    pub fn is_admin(&self) -> bool {
+        self.role == ADMIN_ROLE
+    }
+}
+
+/// Manual change to a user's balance, as it appears in the database.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AdjustmentRecord {
+    pub id: i64,
+
+    /// Amount added to the balance, negative if money was taken away
+    pub amount: f64,
+
+    /// Why the balance was adjusted
+    pub reason: String,
+
+    /// Admin that made the adjustment, if it was made from the dashboard
+    pub admin: Option<i64>,
+
+    /// When the adjustment was made
+    pub created: NaiveDateTime,
+}
+
+/// Storage for user accounts.
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    /// Fetch the user with the given provider and identifier, creating them with `balance` if they
+    /// don't exist. Records that the user has logged in.
+    async fn upsert(&self, provider: &str, identifier: &str, balance: f64) -> Result<i64, DbError>;
+
+    /// Set the email address that notifications are sent to.
+    async fn set_email(&self, user: UserId, email: &str) -> Result<(), DbError>;
+
+    /// Get the role of the user, if they exist.
+    async fn role(&self, user: UserId) -> Result<Option<String>, DbError>;
+
+    /// List every user, in the order they were created.
+    async fn list(&self) -> Result<Vec<UserRecord>, DbError>;
+
+    async fn find(&self, user: UserId) -> Result<Option<UserRecord>, DbError>;
+
+    /// Find users whose ID matches `query`, or whose identifier contains it.
+    async fn search(&self, query: &str) -> Result<Vec<UserRecord>, DbError>;
+
+    /// Add `amount` to the user's balance and record why, returning the new balance if the user
+    /// exists.
+    async fn adjust_balance(
+        &self,
+        user: UserId,
+        amount: f64,
+        reason: &str,
+        admin: Option<UserId>,
+    ) -> Result<Option<f64>, DbError>;
+
+    /// List the manual adjustments made to the user's balance, most recent first.
+    async fn adjustments(&self, user: UserId) -> Result<Vec<AdjustmentRecord>, DbError>;
+
+    /// Set the user's role. Returns whether the user exists and hasn't been deleted.
+    async fn set_role(&self, user: UserId, role: &str) -> Result<bool, DbError>;
+
+    /// Mark the user as banned. Returns whether the user exists.
+    async fn ban(&self, user: UserId) -> Result<bool, DbError>;
+
+    /// Remove everything identifying about the user, keeping their bets and balance against the
+    /// anonymous account. Their tokens, webhooks, push subscriptions and notification preferences
+    /// are removed, as are the client details of their audit events. Returns whether the user
+    /// existed and hadn't already been deleted.
+    async fn delete(&self, user: UserId) -> Result<bool, DbError>;
+}
+
+#[derive(Clone)]
+pub struct UserService {
+    db: Arc<dyn UserRepository>,
+    session: SessionService,
+    token: TokenService,
+    events: EventService,
+    webhooks: WebhookService,
+    audit: AuditService,
+}
+
+impl UserService {
+    pub fn new(
+        db: Arc<dyn UserRepository>,
+        session: SessionService,
+        token: TokenService,
+        events: EventService,
+        webhooks: WebhookService,
+        audit: AuditService,
+    ) -> Self {
+        Self {
+            db,
+            session,
+            token,
+            events,
+            webhooks,
+            audit,
+        }
+    }
+
+    /// Whether the user has the admin role.
+    pub async fn is_admin(&self, user: UserId) -> Result<bool, DbError> {
+        Ok(self
+            .db
+            .role(user)
+            .await?
+            .is_some_and(|role| role == ADMIN_ROLE))
+    }
+
+    pub async fn list(&self) -> Result<Vec<UserRecord>, DbError> {
+        self.db.list().await
+    }
+
+    pub async fn find(&self, user: UserId) -> Result<Option<UserRecord>, DbError> {
+        self.db.find(user).await
+    }
+
+    pub async fn search(&self, query: &str) -> Result<Vec<UserRecord>, DbError> {
+        self.db.search(query.trim()).await
+    }
+
+    /// Add `amount` (which may be negative) to the user's balance, returning the new balance if
+    /// the user exists. The reason is kept alongside the admin that made the adjustment, which is
+    /// `None` when adjusted from the CLI.
+    #[tracing::instrument(skip(self))]
+    pub async fn adjust_balance(
+        &self,
+        user: UserId,
+        amount: f64,
+        reason: &str,
+        admin: Option<UserId>,
+    ) -> Result<Option<f64>, DbError> {
+        let balance = self.db.adjust_balance(user, amount, reason, admin).await?;
+
+        if let Some(balance) = balance {
+            self.audit
+                .record(AuditEvent {
+                    actor: admin,
+                    ..AuditEvent::new(
+                        AuditKind::BalanceAdjusted,
+                        Some(user),
+                        format!("{amount:+.2}: {reason}"),
+                    )
+                })
+                .await;
+
+            self.events.publish(Event::Balance(user));
+            self.webhooks
+                .send(user, WebhookEvent::BalanceChanged { balance })
+                .await;
+        }
+
+        Ok(balance)
+    }
+
+    pub async fn adjustments(&self, user: UserId) -> Result<Vec<AdjustmentRecord>, DbError> {
+        self.db.adjustments(user).await
+    }
+
+    /// Give the user one of [`ROLES`]. Returns whether the user exists and hasn't been deleted.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_role(&self, user: UserId, role: &str) -> Result<bool, DbError> {
+        if !self.db.set_role(user, role).await? {
+            return Ok(false);
+        }
+
+        self.audit
+            .record(AuditEvent::new(
+                AuditKind::RoleChanged,
+                Some(user),
+                format!("role set to {role}"),
+            ))
+            .await;
+
+        Ok(true)
+    }
+
+    /// Ban the user, signing them out everywhere and revoking their tokens. Returns whether the
+    /// user exists.
+    #[tracing::instrument(skip(self))]
+    pub async fn ban(&self, user: UserId) -> Result<bool, DbError> {
+        if !self.db.ban(user).await? {
+            return Ok(false);
+        }
+
+        let sessions = self.session.revoke_all(user).await?;
+        let tokens = self.token.revoke_all(user).await?;
+        tracing::info!("banned user, revoking {sessions} sessions and {tokens} tokens");
+
+        Ok(true)
+    }
+
+    /// Delete the user's account, signing them out everywhere. Their bets remain against the
+    /// anonymised account, so that totals across every user don't change. Returns whether the
+    /// user existed and hadn't already been deleted.
+    #[tracing::instrument(skip(self))]
+    pub async fn delete(&self, user: UserId) -> Result<bool, DbError> {
+        if !self.db.delete(user).await? {
+            return Ok(false);
+        }
+
+        let sessions = self.session.revoke_all(user).await?;
+        tracing::info!("deleted user, revoking {sessions} sessions");
+
+        self.audit
+            .record(AuditEvent::new(
+                AuditKind::AccountDeleted,
+                Some(user),
+                "deleted account",
+            ))
+            .await;
+
+        Ok(true)
+    }
+}
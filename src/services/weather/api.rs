@@ -1,29 +1,85 @@
-use std::fmt::Display;
+use std::sync::Arc;
 
 use chrono::{Duration, NaiveDate};
 use reqwest::{Client, Url};
 use serde::Deserialize;
 use serde_json::Value;
 
-use super::{Forecast, Weather};
+use crate::config::WeatherConfig;
+
+use super::{
+    policy::{CircuitBreaker, FetchPolicy},
+    single_flight::SingleFlight,
+    Forecast, Weather, WeatherError, WeatherSource,
+};
+
+/// Identifies identical requests to the weather API.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct FlightKey {
+    source: ApiSource,
+    location: (u64, u64),
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    parameters: &'static [&'static str],
+}
+
+impl FlightKey {
+    fn new(source: ApiSource, request: &Request) -> Self {
+        Self {
+            source,
+            location: (request.latitude.to_bits(), request.longitude.to_bits()),
+            start_date: request.start_date,
+            end_date: request.end_date,
+            parameters: request.parameters,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Api {
     client: Client,
+    policy: FetchPolicy,
+
+    /// Precipitation above which a day is considered to have rained
+    rain_threshold: f64,
+
+    forecast_url: Url,
+    archive_url: Url,
+
+    /// Breakers for each of the sources, as they are served from different hosts
+    forecast_breaker: CircuitBreaker,
+    archive_breaker: CircuitBreaker,
+
+    /// Requests that are currently in flight, so that concurrent identical requests are only made
+    /// once
+    flights: SingleFlight<FlightKey, Value, WeatherError>,
 }
 
 impl Api {
-    /// The threshold in which it is considered that it will rain. This is intended to give a small
-    /// buffer incase the rain isn't perceptable.
-    const RAIN_THRESHOLD: f64 = 0.05;
-
-    pub fn new(client: Client) -> Self {
-        Self { client }
+    pub fn new(client: Client, config: &WeatherConfig) -> Self {
+        Self {
+            client,
+            policy: FetchPolicy::new(config),
+            rain_threshold: config.rain_threshold,
+            forecast_url: config.forecast_url.clone(),
+            archive_url: config.archive_url.clone(),
+            forecast_breaker: CircuitBreaker::default(),
+            archive_breaker: CircuitBreaker::default(),
+            flights: SingleFlight::default(),
+        }
     }
 
     /// Get the forecast for a given date and location.
-    pub async fn get_daily_forecast(&self, date: NaiveDate, location: (f64, f64)) -> Forecast {
-        self.get_forecast(date, date, location).await.remove(0).1
+    pub async fn get_daily_forecast(
+        &self,
+        date: NaiveDate,
+        location: (f64, f64),
+    ) -> Result<Forecast, WeatherError> {
+        self.get_forecast(date, date, location)
+            .await?
+            .pop()
+            .map(|(_, forecast)| forecast)
+            .ok_or(WeatherError::MissingData(date))
     }
 
     /// Get the forecast for a given range of dates in a location.
@@ -32,7 +88,7 @@ impl Api {
         start: NaiveDate,
         end: NaiveDate,
         location: (f64, f64),
-    ) -> Vec<(NaiveDate, Forecast)> {
+    ) -> Result<Vec<(NaiveDate, Forecast)>, WeatherError> {
         #[derive(Deserialize)]
         struct ForecastResponse {
             temperature_2m_min: Vec<f64>,
@@ -58,10 +114,9 @@ impl Api {
                     ],
                 },
             )
-            .await
-            .unwrap();
+            .await?;
 
-        (0..)
+        Ok((0..)
             .map_while(|i| {
                 Some((
                     *response.time.get(i)?,
@@ -70,110 +125,227 @@ impl Api {
                         minimum_temperature: *response.temperature_2m_min.get(i)?,
                         maximum_temperature: *response.temperature_2m_max.get(i)?,
                         weather_code: (*response.weather_code.get(i)?).into(),
+                        stale: false,
                     },
                 ))
             })
-            .collect()
+            .collect())
     }
 
-    pub async fn get_historical(
+    /// Get the observed weather for a range of dates from the archive only. The archive lags
+    /// behind by a few days, so recent dates may be missing.
+    pub async fn get_archive(
         &self,
         start: NaiveDate,
         end: NaiveDate,
         location: (f64, f64),
-    ) -> Vec<(NaiveDate, Weather)> {
-        let request = Request {
-            start_date: start,
-            end_date: end,
-            latitude: location.0,
-            longitude: location.1,
-            parameters: &["temperature_2m_mean", "precipitation_sum"],
-        };
-
-        #[derive(Debug, Clone, Default, Deserialize)]
-        struct WeatherResponse {
-            temperature_2m_mean: Vec<f64>,
-            precipitation_sum: Vec<f64>,
-            time: Vec<NaiveDate>,
-        }
+    ) -> Result<Vec<(NaiveDate, Weather)>, WeatherError> {
+        Ok(self
+            .request::<WeatherResponse>(
+                ApiSource::Archive,
+                WeatherResponse::request(start, end, location),
+            )
+            .await?
+            .process(self.rain_threshold))
+    }
 
-        impl WeatherResponse {
-            pub fn process(self) -> Vec<(NaiveDate, Weather)> {
-                (0..)
-                    .map_while(|i| {
-                        Some((
-                            *self.time.get(i)?,
-                            Weather {
-                                rain: *self.precipitation_sum.get(i)? > Api::RAIN_THRESHOLD,
-                                temperature: *self.temperature_2m_mean.get(i)?,
-                            },
-                        ))
-                    })
-                    .collect()
+    /// Get the observed weather for a range of dates, along with where it came from. Dates that
+    /// the archive doesn't have yet are filled in from the forecast.
+    pub async fn get_historical(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        location: (f64, f64),
+    ) -> Result<Vec<(NaiveDate, Weather, WeatherSource)>, WeatherError> {
+        // Fetch from the API. The archive lags behind by a few days, so it may not have the
+        // requested dates yet, in which case they're taken from the forecast.
+        let archive = match self.get_archive(start, end, location).await {
+            Ok(archive) => archive,
+            Err(e) => {
+                tracing::warn!("unable to get weather from the archive, using the forecast: {e}");
+                Vec::new()
             }
-        }
+        };
 
-        // Fetch from the API
-        let mut weather = self
-            .request::<WeatherResponse>(ApiSource::Archive, request.clone())
-            .await
-            .unwrap_or_default()
-            .process();
+        let mut weather = archive
+            .into_iter()
+            .map(|(date, weather)| (date, weather, WeatherSource::Archive))
+            .collect::<Vec<_>>();
 
         let forecast = self
-            .request::<WeatherResponse>(ApiSource::Forecast, request)
-            .await
-            .unwrap_or_default()
-            .process();
+            .request::<WeatherResponse>(
+                ApiSource::Forecast,
+                WeatherResponse::request(start, end, location),
+            )
+            .await?
+            .process(self.rain_threshold);
 
         // Merge weather and forecast
         let mut date = start;
         while date <= end {
-            if !weather.iter().any(|&(d, _)| d == date) {
+            if !weather.iter().any(|&(d, _, _)| d == date) {
                 // Add the missing day from the forecast
-                weather.push(forecast.iter().find(|&(d, _)| *d == date).unwrap().clone());
+                let (_, day) = forecast
+                    .iter()
+                    .find(|&(d, _)| *d == date)
+                    .ok_or(WeatherError::MissingData(date))?;
+
+                weather.push((date, day.clone(), WeatherSource::Forecast));
             }
 
             date += Duration::days(1);
         }
 
-        weather
+        Ok(weather)
     }
 
-    /// Internal helper for making a request to the weather API.
+    /// Internal helper for making a request to the weather API. Identical requests that are made
+    /// concurrently will share a single response.
     async fn request<'de, T: Deserialize<'de>>(
         &self,
         source: ApiSource,
         request: Request,
-    ) -> Option<T> {
-        let url = Url::parse_with_params(&source.to_string(), request.into_iter()).unwrap();
+    ) -> Result<T, WeatherError> {
+        let api = self.clone();
+        let key = FlightKey::new(source, &request);
+
+        let daily = self
+            .flights
+            .run(key, move || async move {
+                api.request_daily(source, request).await
+            })
+            .await
+            .map_err(|e| Arc::try_unwrap(e).unwrap_or_else(WeatherError::Shared))?;
+
+        Ok(T::deserialize(daily)?)
+    }
+
+    /// Fetch the `daily` value from the weather API. Transient failures are retried with an
+    /// exponential backoff, and the source's circuit breaker is tripped if it continues to fail.
+    #[tracing::instrument(skip(self, request), fields(source = source.name()))]
+    async fn request_daily(
+        &self,
+        source: ApiSource,
+        request: Request,
+    ) -> Result<Value, WeatherError> {
+        let (breaker, url) = match source {
+            ApiSource::Forecast => (&self.forecast_breaker, &self.forecast_url),
+            ApiSource::Archive => (&self.archive_breaker, &self.archive_url),
+        };
+
+        if !breaker.allow() {
+            metrics::counter!("weather_api_requests_total", "source" => source.name(), "outcome" => "rejected")
+                .increment(1);
+
+            return Err(WeatherError::Unavailable);
+        }
+
+        let mut url = url.clone();
+        url.query_pairs_mut().extend_pairs(request);
+
+        let mut attempt = 0;
+        loop {
+            let result = self.request_once(url.clone()).await;
+
+            metrics::counter!(
+                "weather_api_requests_total",
+                "source" => source.name(),
+                "outcome" => if result.is_ok() { "success" } else { "failure" },
+            )
+            .increment(1);
+
+            match result {
+                Ok(result) => {
+                    breaker.record_success();
+                    return Ok(result);
+                }
+                Err(e) if e.is_transient() && attempt < self.policy.max_retries => {
+                    tracing::debug!("retrying weather API request after failure: {e}");
+
+                    tokio::time::sleep(self.policy.backoff_for(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    if e.is_transient() {
+                        breaker.record_failure(&self.policy);
+                    }
+
+                    return Err(e);
+                }
+            }
+        }
+    }
 
+    async fn request_once(&self, url: Url) -> Result<Value, WeatherError> {
         // Make the response
-        let response = self.client.get(url).send().await.unwrap();
+        let response = self
+            .client
+            .get(url)
+            .timeout(self.policy.timeout)
+            .send()
+            .await?
+            .error_for_status()?;
 
         // Extract the body
-        let mut body = response.json::<Value>().await.unwrap();
+        let mut body = response.json::<Value>().await?;
 
-        // Extract the 'daily' key and deserialise the value
-        T::deserialize(body["daily"].take()).ok()
+        // Extract the 'daily' key
+        Ok(body["daily"].take())
     }
 }
 
+/// Observed weather, as returned from either the forecast or archive API.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct WeatherResponse {
+    temperature_2m_mean: Vec<Option<f64>>,
+    precipitation_sum: Vec<Option<f64>>,
+    time: Vec<NaiveDate>,
+}
+
+impl WeatherResponse {
+    fn request(start: NaiveDate, end: NaiveDate, location: (f64, f64)) -> Request {
+        Request {
+            start_date: start,
+            end_date: end,
+            latitude: location.0,
+            longitude: location.1,
+            parameters: &["temperature_2m_mean", "precipitation_sum"],
+        }
+    }
+
+    /// Pair up each day with its weather, using the threshold to determine whether it rained. Days
+    /// that haven't been observed yet are skipped.
+    fn process(self, rain_threshold: f64) -> Vec<(NaiveDate, Weather)> {
+        self.time
+            .into_iter()
+            .zip(self.precipitation_sum)
+            .zip(self.temperature_2m_mean)
+            .filter_map(|((date, rain), temperature)| {
+                Some((
+                    date,
+                    Weather {
+                        rain: rain? > rain_threshold,
+                        temperature: temperature?,
+                    },
+                ))
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ApiSource {
     Forecast,
     Archive,
 }
 
-impl Display for ApiSource {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                ApiSource::Forecast => "https://api.open-meteo.com/v1/forecast",
-                ApiSource::Archive => "https://archive-api.open-meteo.com/v1/archive",
-            }
-        )
+impl ApiSource {
+    /// Short name for the source, used to label metrics.
+    fn name(self) -> &'static str {
+        match self {
+            ApiSource::Forecast => "forecast",
+            ApiSource::Archive => "archive",
+        }
     }
 }
 
@@ -203,3 +375,184 @@ impl IntoIterator for Request {
         .into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration as StdDuration,
+    };
+
+    use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const LOCATION: (f64, f64) = (-37.8, 145.0);
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 1).unwrap()
+    }
+
+    /// Stand-in for the weather API, where the archive never has anything and the forecast fails
+    /// a set number of times before succeeding.
+    #[derive(Default)]
+    struct Stub {
+        failures: AtomicU32,
+        requests: AtomicU32,
+    }
+
+    impl Stub {
+        fn requests(&self) -> u32 {
+            self.requests.load(Ordering::SeqCst)
+        }
+    }
+
+    async fn forecast(State(stub): State<Arc<Stub>>) -> Result<Json<Value>, StatusCode> {
+        stub.requests.fetch_add(1, Ordering::SeqCst);
+
+        let failing = stub
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |failures| {
+                failures.checked_sub(1)
+            })
+            .is_ok();
+        if failing {
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        Ok(Json(json!({
+            "daily": {
+                "time": [date()],
+                "temperature_2m_min": [10.0],
+                "temperature_2m_max": [20.0],
+                "temperature_2m_mean": [15.0],
+                "precipitation_probability_mean": [40.0],
+                "precipitation_sum": [1.0],
+                "weather_code": [61],
+            }
+        })))
+    }
+
+    async fn archive() -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    /// Start the stub, producing an API that uses it.
+    async fn api(config: WeatherConfig) -> (Api, Arc<Stub>) {
+        let stub = Arc::new(Stub::default());
+        let app = Router::new()
+            .route("/forecast", get(forecast))
+            .route("/archive", get(archive))
+            .with_state(stub.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = WeatherConfig {
+            backoff_ms: 1,
+            forecast_url: format!("http://{address}/forecast").parse().unwrap(),
+            archive_url: format!("http://{address}/archive").parse().unwrap(),
+            ..config
+        };
+
+        (Api::new(Client::new(), &config), stub)
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let (api, stub) = api(WeatherConfig {
+            max_retries: 2,
+            ..WeatherConfig::default()
+        })
+        .await;
+
+        stub.failures.store(2, Ordering::SeqCst);
+        let forecast = api.get_daily_forecast(date(), LOCATION).await.unwrap();
+        assert_eq!(forecast.maximum_temperature, 20.0);
+        assert_eq!(stub.requests(), 3);
+
+        stub.failures.store(3, Ordering::SeqCst);
+        assert!(matches!(
+            api.get_daily_forecast(date(), LOCATION).await,
+            Err(WeatherError::Request(_))
+        ));
+        assert_eq!(stub.requests(), 6);
+    }
+
+    #[tokio::test]
+    async fn breaker_opens_after_repeated_failures() {
+        let (api, stub) = api(WeatherConfig {
+            max_retries: 0,
+            breaker_threshold: 2,
+            breaker_cooldown_ms: 100,
+            ..WeatherConfig::default()
+        })
+        .await;
+        stub.failures.store(u32::MAX, Ordering::SeqCst);
+
+        for _ in 0..2 {
+            assert!(matches!(
+                api.get_daily_forecast(date(), LOCATION).await,
+                Err(WeatherError::Request(_))
+            ));
+        }
+
+        // Requests aren't sent whilst the breaker is open
+        assert!(matches!(
+            api.get_daily_forecast(date(), LOCATION).await,
+            Err(WeatherError::Unavailable)
+        ));
+        assert_eq!(stub.requests(), 2);
+
+        // Once the cooldown has passed, a request is let through, and reopens it on failure
+        tokio::time::sleep(StdDuration::from_millis(150)).await;
+        assert!(matches!(
+            api.get_daily_forecast(date(), LOCATION).await,
+            Err(WeatherError::Request(_))
+        ));
+        assert!(matches!(
+            api.get_daily_forecast(date(), LOCATION).await,
+            Err(WeatherError::Unavailable)
+        ));
+        assert_eq!(stub.requests(), 3);
+
+        // A success closes it again
+        tokio::time::sleep(StdDuration::from_millis(150)).await;
+        stub.failures.store(0, Ordering::SeqCst);
+        assert!(api.get_daily_forecast(date(), LOCATION).await.is_ok());
+        assert!(api.get_daily_forecast(date(), LOCATION).await.is_ok());
+        assert_eq!(stub.requests(), 5);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (api, _) = api(WeatherConfig {
+            max_retries: 2,
+            breaker_threshold: 1,
+            ..WeatherConfig::default()
+        })
+        .await;
+
+        for _ in 0..2 {
+            assert!(matches!(
+                api.get_archive(date(), date(), LOCATION).await,
+                Err(WeatherError::Request(e)) if e.status() == Some(StatusCode::BAD_REQUEST)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn fills_historical_weather_from_forecast() {
+        let (api, _) = api(WeatherConfig::default()).await;
+
+        let weather = api.get_historical(date(), date(), LOCATION).await.unwrap();
+        assert!(matches!(
+            weather[..],
+            [(day, Weather { rain: true, temperature }, WeatherSource::Forecast)]
+                if day == date() && temperature == 15.0
+        ));
+    }
+}
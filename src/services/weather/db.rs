@@ -1,135 +1,103 @@
-use chrono::NaiveDate;
-use sqlx::{error::DatabaseError, Error, SqlitePool};
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
 
-use crate::services::weather::WeatherCode;
+use crate::services::DbError;
 
-use super::{Forecast, Weather};
+use super::{Forecast, Weather, WeatherSource};
 
-#[derive(Clone)]
-pub struct Db {
-    pool: SqlitePool,
+/// Forecast as it was saved, along with when it was retrieved.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CachedForecast {
+    /// Date the forecast is for
+    pub date: NaiveDate,
+
+    /// When the forecast was retrieved from the weather API
+    pub date_retrieved: NaiveDateTime,
+
+    #[sqlx(flatten)]
+    pub forecast: Forecast,
 }
 
-impl Db {
-    pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
-    }
+/// Observed weather as it was saved, along with when it was retrieved.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CachedWeather {
+    /// Date the weather was observed
+    pub date: NaiveDate,
+
+    /// When the weather was retrieved from the archive, or imported
+    pub date_retrieved: NaiveDateTime,
+
+    #[sqlx(flatten)]
+    pub weather: Weather,
+
+    /// Where the weather came from
+    pub source: WeatherSource,
 
+    /// Whether the weather may still be replaced
+    pub provisional: bool,
+}
+
+/// Storage for forecasts and observed weather, so the weather API is only hit once per day.
+#[async_trait]
+pub trait WeatherRepository: Send + Sync {
     /// Get the forecast for a given day, as if it were retrieved on the provied date. `date` must
     /// be in the local timezone of the region the forecast is for.
-    pub async fn get_day_forecast(
+    async fn get_day_forecast(
         &self,
         date: NaiveDate,
         retrieval_date: NaiveDate,
-    ) -> Option<Forecast> {
-        sqlx::query_as!(
-            Forecast,
-            "SELECT rain, minimum_temperature, maximum_temperature, weather_code
-                FROM forecasts
-                WHERE date = ? AND DATE(date_retrieved) = ?;",
-            date,
-            retrieval_date
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .unwrap()
-    }
+    ) -> Result<Option<Forecast>, DbError>;
 
     /// Load all forecasts between `start_date` and `end_date` that were retrieved on the provided
     /// date. Both `start_date` and `end_date` must be in the local timezone for the region of the
     /// forecast.
-    pub async fn get_forecast_range(
+    async fn get_forecast_range(
         &self,
         start_date: NaiveDate,
         end_date: NaiveDate,
         retrieval_date: NaiveDate,
-    ) -> Vec<(NaiveDate, Forecast)> {
-        struct Result {
-            date: NaiveDate,
-            rain: f64,
-            minimum_temperature: f64,
-            maximum_temperature: f64,
-            weather_code: WeatherCode,
-        }
-
-        // Try pull the forecasts for the day that was generated today
-        sqlx::query_as!(
-            Result,
-            "SELECT date, rain, minimum_temperature, maximum_temperature, weather_code
-                FROM forecasts
-                WHERE date >= ? AND date <= ? AND DATE(date_retrieved) = ?
-                ORDER BY date;",
-            start_date,
-            end_date,
-            retrieval_date
-        )
-        .map(|result| {
-            (
-                result.date,
-                Forecast {
-                    rain: result.rain,
-                    minimum_temperature: result.minimum_temperature,
-                    maximum_temperature: result.maximum_temperature,
-                    weather_code: result.weather_code,
-                },
-            )
-        })
-        .fetch_all(&self.pool)
-        .await
-        .unwrap()
-    }
-
-    /// Save the forecast for a given day as it was retrived on the provided date.
-    pub async fn save_forecast(&self, date: NaiveDate, forecast: &Forecast) {
-        sqlx::query!(
-                "INSERT INTO forecasts (date, rain, minimum_temperature, maximum_temperature, weather_code)
-                    VALUES (?, ?, ?, ?, ?);",
-                date,
-                forecast.rain,
-                forecast.minimum_temperature,
-                forecast.maximum_temperature,
-                forecast.weather_code,
-            )
-                .execute(&self.pool)
-                .await
-                .unwrap();
-    }
+    ) -> Result<Vec<(NaiveDate, Forecast)>, DbError>;
+
+    /// Get the most recently retrieved forecast for a given day, regardless of when it was
+    /// retrieved. This is marked as stale, and should only be used when a current forecast isn't
+    /// available.
+    async fn get_latest_forecast(&self, date: NaiveDate) -> Result<Option<Forecast>, DbError>;
+
+    /// Save the forecast for a given day as it was retrived on the provided date. If a forecast has
+    /// already been saved today, it will be kept instead.
+    async fn save_forecast(&self, date: NaiveDate, forecast: &Forecast) -> Result<(), DbError>;
+
+    /// List every forecast for dates between `start_date` and `end_date`, no matter when they were
+    /// retrieved. Ordered by date, then by when they were retrieved.
+    async fn list_cached_forecasts(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<CachedForecast>, DbError>;
+
+    /// List the observed weather that has been saved for dates between `start_date` and
+    /// `end_date`, in date order.
+    async fn list_cached_weather(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<CachedWeather>, DbError>;
 
     /// Get the historical weather for some date.
-    pub async fn get_historical_weather(&self, date: NaiveDate) -> Option<Weather> {
-        sqlx::query_as!(
-            Weather,
-            "SELECT rain, temperature
-                FROM historical_weather
-                WHERE date = ?",
-            date
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .unwrap()
-    }
-
-    /// Save historical weather for some day as if it were retrieved on the given day.
-    pub async fn save_historical_weather(
+    async fn get_historical_weather(&self, date: NaiveDate) -> Result<Option<Weather>, DbError>;
+
+    /// Get all of the historical weather that has been saved, in date order.
+    async fn list_historical_weather(&self) -> Result<Vec<(NaiveDate, Weather)>, DbError>;
+
+    /// Save historical weather for some day, keeping the existing weather if it has already been
+    /// saved. The weather is provisional if the source is.
+    async fn save_historical_weather(
         &self,
         date: NaiveDate,
         weather: &Weather,
-    ) -> Result<(), Box<dyn DatabaseError>> {
-        if let Err(Error::Database(e)) = sqlx::query!(
-            "INSERT INTO historical_weather (date, temperature, rain)
-                VALUES (?, ?, ?)",
-            date,
-            weather.temperature,
-            weather.rain,
-        )
-        .execute(&self.pool)
-        .await
-        {
-            if !e.is_unique_violation() {
-                return Err(e);
-            }
-        }
-
-        Ok(())
-    }
+        source: WeatherSource,
+    ) -> Result<(), DbError>;
+
+    /// Find the dates whose observed weather is provisional, in date order.
+    async fn provisional_dates(&self) -> Result<Vec<NaiveDate>, DbError>;
 }
@@ -1,70 +1,126 @@
 mod api;
 mod db;
+mod policy;
+mod single_flight;
+
+use std::sync::Arc;
 
 use chrono::{Duration, NaiveDate, Utc};
 use num_enum::{FromPrimitive, IntoPrimitive};
 use reqwest::Client;
-use sqlx::SqlitePool;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{config::WeatherConfig, MELBOURNE};
+
+use self::api::Api;
+
+pub use self::db::{CachedForecast, CachedWeather, WeatherRepository};
+
+use super::DbError;
 
-use crate::MELBOURNE;
+#[derive(Debug, thiserror::Error)]
+pub enum WeatherError {
+    #[error("weather API request failed: {0}")]
+    Request(#[from] reqwest::Error),
 
-use self::{api::Api, db::Db};
+    #[error("weather API response could not be parsed: {0}")]
+    InvalidResponse(#[from] serde_json::Error),
+
+    #[error("weather API response is missing data for {0}")]
+    MissingData(NaiveDate),
+
+    #[error("weather API is unavailable after repeated failures")]
+    Unavailable,
+
+    /// Error from a request that was shared with other callers.
+    #[error(transparent)]
+    Shared(Arc<WeatherError>),
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
 
 #[derive(Clone)]
 pub struct WeatherService {
     api: Api,
-    db: Db,
+    db: Arc<dyn WeatherRepository>,
 }
 
 impl WeatherService {
     /// Create a new instance of the weather service.
-    pub fn new(pool: SqlitePool, client: Client) -> Self {
+    pub fn new(db: Arc<dyn WeatherRepository>, client: Client, config: &WeatherConfig) -> Self {
         Self {
-            api: Api::new(client),
-            db: Db::new(pool),
+            api: Api::new(client, config),
+            db,
         }
     }
 
     /// Get the forecast for the provided date. Given that forecasts change over time, only one
-    /// forecast is generated per day.
-    pub async fn get_daily_forecast(&self, date: NaiveDate, location: (f64, f64)) -> Forecast {
+    /// forecast is generated per day. If the weather API is unavailable, the most recent stored
+    /// forecast will be used instead.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_daily_forecast(
+        &self,
+        date: NaiveDate,
+        location: (f64, f64),
+    ) -> Result<Forecast, WeatherError> {
         // Check if a forecast already exists for this date
         if let Some(forecast) = self
             .db
             .get_day_forecast(date, Utc::now().date_naive())
-            .await
+            .await?
         {
-            forecast
+            metrics::counter!("weather_cache_lookups_total", "result" => "hit").increment(1);
+
+            Ok(forecast)
         } else {
+            metrics::counter!("weather_cache_lookups_total", "result" => "miss").increment(1);
+
             // Fetch the forecast from the weather API
-            let forecast = self.api.get_daily_forecast(date, location).await;
+            let forecast = match self.api.get_daily_forecast(date, location).await {
+                Ok(forecast) => forecast,
+                Err(WeatherError::Db(e)) => return Err(e.into()),
+                Err(e) => {
+                    tracing::warn!("falling back to stale forecast for {date}: {e}");
+                    metrics::counter!("weather_cache_lookups_total", "result" => "stale")
+                        .increment(1);
+
+                    return self.db.get_latest_forecast(date).await?.ok_or(e);
+                }
+            };
 
             // Save it into the DB
-            self.db.save_forecast(date, &forecast).await;
+            self.db.save_forecast(date, &forecast).await?;
 
             // Return the forecast
-            forecast
+            Ok(forecast)
         }
     }
 
     /// Get the forecast for some date range
+    #[tracing::instrument(skip(self))]
     pub async fn get_forecast(
         &self,
         start: NaiveDate,
         end: NaiveDate,
-    ) -> Vec<(NaiveDate, Forecast)> {
+    ) -> Result<Vec<(NaiveDate, Forecast)>, WeatherError> {
         // Fetch the saved forecast for this date range
         let mut forecast = self
             .db
             .get_forecast_range(start, end, Utc::now().date_naive())
-            .await;
+            .await?;
 
         // If all the days are present, then no need to continue
-        let days_inclusive = (end - start).num_days().abs() as usize + 1;
+        let days_inclusive = (end - start).num_days().unsigned_abs() as usize + 1;
         if forecast.len() == days_inclusive {
-            return forecast;
+            metrics::counter!("weather_cache_lookups_total", "result" => "hit").increment(1);
+
+            return Ok(forecast);
         }
 
+        metrics::counter!("weather_cache_lookups_total", "result" => "miss").increment(1);
+
         // Find the first missing date
         let mut filter_start = start;
         for (date, _) in &forecast {
@@ -86,19 +142,52 @@ impl WeatherService {
             }
         }
 
-        // Get the missing days, and add them to the forecast
-        for (date, day_forecast) in self
+        // Get the missing days, falling back to stale forecasts if the API is unavailable
+        let missing = match self
             .api
             .get_forecast(filter_start, filter_end, MELBOURNE)
             .await
         {
+            Ok(missing) => missing,
+            Err(WeatherError::Db(e)) => return Err(e.into()),
+            Err(e) => {
+                tracing::warn!(
+                    "falling back to stale forecasts from {filter_start} to {filter_end}: {e}"
+                );
+
+                let mut date = filter_start;
+                while date <= filter_end {
+                    if !forecast.iter().any(|d| d.0 == date) {
+                        if let Some(day_forecast) = self.db.get_latest_forecast(date).await? {
+                            forecast.push((date, day_forecast));
+                        }
+                    }
+
+                    date += Duration::days(1);
+                }
+
+                // Nothing at all to show
+                if forecast.is_empty() {
+                    return Err(e);
+                }
+
+                metrics::counter!("weather_cache_lookups_total", "result" => "stale").increment(1);
+
+                forecast.sort_unstable_by_key(|&(date, _)| date);
+
+                return Ok(forecast);
+            }
+        };
+
+        // Add the missing days to the forecast
+        for (date, day_forecast) in missing {
             // See if the day is already in the provided forecast
             if forecast.iter().find(|d| d.0 == date).is_some() {
                 continue;
             }
 
             // Save the collection in the DB
-            self.db.save_forecast(date, &day_forecast).await;
+            self.db.save_forecast(date, &day_forecast).await?;
 
             // Add the forecast to the collection
             forecast.push((date, day_forecast));
@@ -107,43 +196,154 @@ impl WeatherService {
         // Sort the forecast by the date again
         forecast.sort_unstable_by_key(|&(date, _)| date);
 
-        forecast
+        Ok(forecast)
     }
 
-    pub async fn get_historical_weather(&self, date: NaiveDate) -> Option<Weather> {
+    #[tracing::instrument(skip(self))]
+    pub async fn get_historical_weather(&self, date: NaiveDate) -> Result<Weather, WeatherError> {
         // Check if it's in the DB
-        if let Some(weather) = self.db.get_historical_weather(date).await {
-            return Some(weather);
+        if let Some(weather) = self.db.get_historical_weather(date).await? {
+            return Ok(weather);
         }
 
         // Get the weather from the API
-        let (_, weather) = self.api.get_historical(date, date, MELBOURNE).await.pop()?;
+        let (_, weather, source) = self
+            .api
+            .get_historical(date, date, MELBOURNE)
+            .await?
+            .pop()
+            .ok_or(WeatherError::MissingData(date))?;
 
-        // Save it in the DB for later
+        // Save it in the DB for later. Weather from the forecast is provisional, and will be
+        // replaced once the archive has the day.
         self.db
-            .save_historical_weather(date, &weather)
-            .await
-            .unwrap();
+            .save_historical_weather(date, &weather, source)
+            .await?;
+
+        Ok(weather)
+    }
+
+    /// Save the observed weather for a range of dates from the archive, skipping any that the
+    /// archive doesn't have yet. Returns the number of days that were saved.
+    pub async fn backfill_historical_weather(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<usize, WeatherError> {
+        let weather = self.get_archive(start, end).await?;
 
-        Some(weather)
+        for (date, weather) in &weather {
+            self.db
+                .save_historical_weather(*date, weather, WeatherSource::Archive)
+                .await?;
+        }
+
+        Ok(weather.len())
+    }
+
+    /// Get the observed weather for a range of dates from the archive, without saving it. The
+    /// archive lags behind by a few days, so recent dates may be missing.
+    pub async fn get_archive(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, Weather)>, WeatherError> {
+        self.api.get_archive(start, end, MELBOURNE).await
+    }
+
+    /// Save observed weather from another source, such as for days the archive is missing. Dates
+    /// that already have observed weather are left as they are.
+    pub async fn import_historical_weather(
+        &self,
+        weather: &[(NaiveDate, Weather)],
+    ) -> Result<(), DbError> {
+        for (date, weather) in weather {
+            self.db
+                .save_historical_weather(*date, weather, WeatherSource::Import)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Find the dates whose observed weather is provisional, in date order.
+    pub async fn provisional_dates(&self) -> Result<Vec<NaiveDate>, DbError> {
+        self.db.provisional_dates().await
+    }
+
+    /// Get everything that has been saved for dates between `start` and `end`, including forecasts
+    /// that have since been replaced.
+    pub async fn cached(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<(Vec<CachedForecast>, Vec<CachedWeather>), DbError> {
+        Ok((
+            self.db.list_cached_forecasts(start, end).await?,
+            self.db.list_cached_weather(start, end).await?,
+        ))
+    }
+
+    /// Get all of the observed weather that has been saved, in date order.
+    pub async fn list_historical_weather(&self) -> Result<Vec<(NaiveDate, Weather)>, DbError> {
+        self.db.list_historical_weather().await
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, ToSchema, sqlx::FromRow)]
 pub struct Forecast {
     pub rain: f64,
     pub minimum_temperature: f64,
     pub maximum_temperature: f64,
     pub weather_code: WeatherCode,
+
+    /// Whether this forecast was retrieved on an earlier day, as a current one isn't available
+    pub stale: bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
 pub struct Weather {
     pub rain: bool,
     pub temperature: f64,
 }
 
-#[derive(Clone, Copy, Debug, IntoPrimitive, FromPrimitive, sqlx::Type)]
+/// Where observed weather came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum WeatherSource {
+    /// Observations from the historical archive
+    Archive,
+
+    /// The forecast for the day, used when the archive doesn't have the day yet
+    Forecast,
+
+    /// Imported by an operator
+    Import,
+
+    /// Saved before the source was tracked
+    Unknown,
+}
+
+impl WeatherSource {
+    /// Whether weather from this source may change, and should be checked against the archive
+    /// later.
+    pub fn is_provisional(self) -> bool {
+        matches!(self, Self::Forecast | Self::Unknown)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Archive => "archive",
+            Self::Forecast => "forecast",
+            Self::Import => "import",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, IntoPrimitive, FromPrimitive, Serialize, ToSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
 #[repr(i64)]
 pub enum WeatherCode {
     #[num_enum(alternatives = [1])]
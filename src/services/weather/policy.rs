@@ -0,0 +1,111 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::config::WeatherConfig;
+
+use super::WeatherError;
+
+/// How requests to the weather API should be made.
+#[derive(Clone, Debug)]
+pub struct FetchPolicy {
+    /// Maximum time a single request may take
+    pub timeout: Duration,
+
+    /// Number of times a failed request will be retried
+    pub max_retries: u32,
+
+    /// Delay before the first retry, doubling for each subsequent retry
+    pub backoff: Duration,
+
+    /// Number of consecutive failures before requests stop being sent
+    pub breaker_threshold: u32,
+
+    /// How long requests stop being sent for once the breaker has tripped
+    pub breaker_cooldown: Duration,
+}
+
+impl FetchPolicy {
+    pub fn new(config: &WeatherConfig) -> Self {
+        Self {
+            timeout: Duration::from_millis(config.timeout_ms),
+            max_retries: config.max_retries,
+            backoff: Duration::from_millis(config.backoff_ms),
+            breaker_threshold: config.breaker_threshold,
+            breaker_cooldown: Duration::from_millis(config.breaker_cooldown_ms),
+        }
+    }
+
+    /// Delay before the given retry attempt (starting from 0).
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(attempt))
+    }
+}
+
+impl WeatherError {
+    /// Whether the error indicates that the upstream is unhealthy, and so the request is worth
+    /// retrying.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            WeatherError::Request(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.is_request()
+                    || e.status()
+                        .is_some_and(|status| status.is_server_error() || status.as_u16() == 429)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    /// Number of failures since the last success
+    failures: u32,
+
+    /// Requests won't be sent until this time
+    open_until: Option<Instant>,
+}
+
+/// Stops requests being sent to an upstream that is continually failing, giving it time to
+/// recover.
+#[derive(Clone, Debug, Default)]
+pub struct CircuitBreaker {
+    state: Arc<Mutex<BreakerState>>,
+}
+
+impl CircuitBreaker {
+    /// Whether requests may currently be sent.
+    pub fn allow(&self) -> bool {
+        let state = self.state.lock().unwrap();
+
+        state
+            .open_until
+            .map(|open_until| Instant::now() >= open_until)
+            .unwrap_or(true)
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::default();
+    }
+
+    /// Record a failure, tripping the breaker if too many have occurred.
+    pub fn record_failure(&self, policy: &FetchPolicy) {
+        let mut state = self.state.lock().unwrap();
+
+        state.failures += 1;
+
+        if state.failures >= policy.breaker_threshold {
+            if state.open_until.is_none() {
+                tracing::warn!(
+                    "weather API has failed {} times, pausing requests",
+                    state.failures
+                );
+            }
+
+            state.open_until = Some(Instant::now() + policy.breaker_cooldown);
+        }
+    }
+}
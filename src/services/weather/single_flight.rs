@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt, TryFutureExt,
+};
+
+type Flight<V, E> = Shared<BoxFuture<'static, Result<V, Arc<E>>>>;
+
+/// Ensures that only one instance of a piece of work is in flight for a given key at a time.
+/// Anything requesting the same key whilst it is in flight will wait for, and share, the result.
+pub struct SingleFlight<K, V, E> {
+    flights: Arc<Mutex<HashMap<K, Flight<V, E>>>>,
+}
+
+impl<K, V, E> Clone for SingleFlight<K, V, E> {
+    fn clone(&self) -> Self {
+        Self {
+            flights: Arc::clone(&self.flights),
+        }
+    }
+}
+
+impl<K, V, E> Default for SingleFlight<K, V, E> {
+    fn default() -> Self {
+        Self {
+            flights: Default::default(),
+        }
+    }
+}
+
+impl<K, V, E> SingleFlight<K, V, E>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + Send + Sync + 'static,
+    E: Send + Sync + 'static,
+{
+    /// Run the work produced by `f`, unless work for the key is already in flight.
+    pub async fn run<F, Fut>(&self, key: K, f: F) -> Result<V, Arc<E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>> + Send + 'static,
+    {
+        let flight = self
+            .flights
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| f().map_err(Arc::new).boxed().shared())
+            .clone();
+
+        let result = flight.clone().await;
+
+        // Clear out the flight, so the next request will perform the work again
+        let mut flights = self.flights.lock().unwrap();
+        if flights
+            .get(&key)
+            .is_some_and(|existing| existing.ptr_eq(&flight))
+        {
+            flights.remove(&key);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use tokio::sync::Semaphore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn shares_work_in_flight() {
+        let flights = SingleFlight::<&str, u32, ()>::default();
+        let calls = AtomicU32::new(0);
+        let release = Arc::new(Semaphore::new(0));
+
+        let work = |key| {
+            let release = release.clone();
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+
+            flights.run(key, move || async move {
+                let _ = release.acquire().await.unwrap();
+                Ok(call)
+            })
+        };
+
+        // Every request joins or starts a flight before any of them are allowed to finish
+        let (first, second, other, ()) =
+            tokio::join!(work("key"), work("key"), work("other"), async {
+                release.add_permits(2);
+            });
+
+        assert_eq!(first.unwrap(), 0);
+        assert_eq!(second.unwrap(), 0);
+        assert_eq!(other.unwrap(), 2);
+
+        // Finished work isn't shared with later requests
+        assert_eq!(flights.run("key", || async { Ok(10) }).await.unwrap(), 10);
+    }
+
+    #[tokio::test]
+    async fn shares_errors() {
+        let flights = SingleFlight::<&str, u32, &str>::default();
+
+        let (first, second) = tokio::join!(
+            flights.run("key", || async {
+                tokio::task::yield_now().await;
+                Err("failed")
+            }),
+            flights.run("key", || async { Ok(1) }),
+        );
+
+        assert_eq!(*first.unwrap_err(), "failed");
+        assert_eq!(*second.unwrap_err(), "failed");
+    }
+}
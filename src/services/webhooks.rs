@@ -0,0 +1,409 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, RngCore};
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use url::Url;
+
+use crate::{config::WebhookConfig, user::UserId};
+
+use super::{
+    bet::{Bet, BetOutcome, Payout},
+    outbound::{self, UrlError},
+    DbError,
+};
+
+/// Prefix for all generated secrets, making them easy to identify.
+const SECRET_PREFIX: &str = "whsec_";
+
+/// Most webhooks a single user can have.
+pub const MAX_WEBHOOKS: usize = 5;
+
+/// Header containing the name of the event.
+const EVENT_HEADER: &str = "x-casino-event";
+
+/// Header containing the ID of the message, which stays the same between retries.
+const ID_HEADER: &str = "x-casino-delivery";
+
+/// Header containing the UNIX timestamp that the message was signed at.
+const TIMESTAMP_HEADER: &str = "x-casino-timestamp";
+
+/// Header containing the hex encoded HMAC-SHA256 of `<timestamp>.<body>`, prefixed with `sha256=`.
+const SIGNATURE_HEADER: &str = "x-casino-signature";
+
+/// Maximum time an endpoint has to respond.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a claimed message is reserved for, so that it isn't delivered twice at once.
+const LEASE_SECS: i64 = 60;
+
+/// Delay before the first retry, doubling for each subsequent retry.
+const RETRY_BACKOFF_SECS: i64 = 30;
+
+/// Number of attempts before a message is given up on.
+const MAX_ATTEMPTS: i64 = 8;
+
+/// Number of messages that are delivered at once.
+const BATCH_SIZE: i64 = 20;
+
+/// Number of delivery attempts shown to the user.
+const DELIVERY_LOG_LEN: i64 = 20;
+
+/// How often the outbox is checked for messages that are due to be retried.
+const POLL_PERIOD: Duration = Duration::from_secs(5);
+
+/// Event that is posted to a user's webhooks.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "data")]
+pub enum WebhookEvent {
+    #[serde(rename = "bet.placed")]
+    BetPlaced {
+        date: NaiveDate,
+        bet: Bet,
+        payout: Payout,
+    },
+
+    #[serde(rename = "bet.replaced")]
+    BetReplaced {
+        date: NaiveDate,
+        bet: Bet,
+        payout: Payout,
+    },
+
+    #[serde(rename = "bet.settled")]
+    BetSettled {
+        date: NaiveDate,
+        outcome: BetOutcome,
+    },
+
+    #[serde(rename = "balance.changed")]
+    BalanceChanged { balance: f64 },
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::BetPlaced { .. } => "bet.placed",
+            WebhookEvent::BetReplaced { .. } => "bet.replaced",
+            WebhookEvent::BetSettled { .. } => "bet.settled",
+            WebhookEvent::BalanceChanged { .. } => "balance.changed",
+        }
+    }
+}
+
+/// Body that is posted to webhooks.
+#[derive(Serialize)]
+struct Payload<'a> {
+    /// User that the event happened to
+    user: i64,
+
+    /// When the event happened
+    created: NaiveDateTime,
+
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("webhook URL must be an absolute `http` or `https` URL")]
+    InvalidUrl,
+
+    #[error("webhook URL must use `https`")]
+    Insecure,
+
+    #[error("webhook URL must be for a public address")]
+    PrivateAddress,
+
+    #[error("at most {MAX_WEBHOOKS} webhooks can be created")]
+    TooMany,
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+impl From<UrlError> for WebhookError {
+    fn from(url_error: UrlError) -> Self {
+        match url_error {
+            UrlError::Invalid => Self::InvalidUrl,
+            UrlError::Insecure => Self::Insecure,
+            UrlError::PrivateAddress => Self::PrivateAddress,
+        }
+    }
+}
+
+/// Webhook, as it appears in the database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct WebhookRecord {
+    /// Unique ID of the webhook
+    pub id: i64,
+
+    /// URL that events are posted to
+    pub url: String,
+
+    /// When the webhook was created
+    pub created: NaiveDateTime,
+}
+
+/// Message in the outbox that is due to be delivered.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct OutboxMessage {
+    pub id: i64,
+
+    /// URL of the webhook
+    pub url: String,
+
+    /// Secret of the webhook
+    pub secret: String,
+
+    /// Name of the event
+    pub event: String,
+
+    /// JSON body to post
+    pub payload: String,
+
+    /// Number of previous attempts
+    pub attempts: i64,
+}
+
+/// Attempt at delivering a message, as it appears in the database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DeliveryRecord {
+    /// Webhook that the message was for
+    pub webhook: i64,
+
+    /// Name of the event
+    pub event: String,
+
+    /// When delivery was attempted
+    pub attempted: NaiveDateTime,
+
+    /// HTTP status that the endpoint responded with, if it responded
+    pub status_code: Option<i64>,
+
+    /// Why delivery failed, if it did
+    pub error: Option<String>,
+}
+
+/// Storage for webhooks, along with the outbox of messages waiting to be delivered to them.
+#[async_trait]
+pub trait WebhookRepository: Send + Sync {
+    async fn insert(&self, user: UserId, url: &str, secret: &str) -> Result<(), DbError>;
+
+    /// List all webhooks belonging to a user, most recently created first.
+    async fn list(&self, user: UserId) -> Result<Vec<WebhookRecord>, DbError>;
+
+    /// Remove one of the user's webhooks, along with its messages. Returns whether the webhook
+    /// existed.
+    async fn remove(&self, user: UserId, id: i64) -> Result<bool, DbError>;
+
+    /// Add a message to the outbox for each of the user's webhooks, returning how many were added.
+    async fn enqueue(&self, user: UserId, event: &str, payload: &str) -> Result<u64, DbError>;
+
+    /// Claim up to `limit` messages that are due, delaying their next attempt by `lease_secs` so
+    /// that they aren't attempted again whilst being delivered.
+    async fn claim_due(&self, limit: i64, lease_secs: i64) -> Result<Vec<OutboxMessage>, DbError>;
+
+    /// Record an attempt at delivering a message. The message is retried after `retry_secs`, or
+    /// completed if it is `None`.
+    async fn record_attempt(
+        &self,
+        message: i64,
+        status_code: Option<i64>,
+        error: Option<&str>,
+        retry_secs: Option<i64>,
+    ) -> Result<(), DbError>;
+
+    /// List the most recent delivery attempts for the user's webhooks.
+    async fn deliveries(&self, user: UserId, limit: i64) -> Result<Vec<DeliveryRecord>, DbError>;
+}
+
+/// Posts events to user's webhooks. Events are added to an outbox, which is delivered in the
+/// background so that slow or failing endpoints don't hold anything up. The outbox is stored in
+/// the database, so events from the CLI or other replicas are also delivered.
+#[derive(Clone)]
+pub struct WebhookService {
+    db: Arc<dyn WebhookRepository>,
+    client: Client,
+    allow_insecure: bool,
+    queued: Arc<Notify>,
+}
+
+impl WebhookService {
+    pub fn new(db: Arc<dyn WebhookRepository>, config: &WebhookConfig) -> Self {
+        Self {
+            db,
+            client: outbound::client(config.allow_insecure),
+            allow_insecure: config.allow_insecure,
+            queued: Default::default(),
+        }
+    }
+
+    /// Create a webhook for the user. The returned secret is the only time it is shown.
+    pub async fn create(&self, user: UserId, url: &str) -> Result<String, WebhookError> {
+        let url = Url::parse(url).map_err(|_| WebhookError::InvalidUrl)?;
+        outbound::check_url_resolved(&url, self.allow_insecure).await?;
+
+        if self.db.list(user).await?.len() >= MAX_WEBHOOKS {
+            return Err(WebhookError::TooMany);
+        }
+
+        let secret = {
+            let mut bytes = [0u8; 32];
+            thread_rng().fill_bytes(&mut bytes);
+
+            format!("{SECRET_PREFIX}{}", URL_SAFE_NO_PAD.encode(bytes))
+        };
+
+        self.db.insert(user, url.as_str(), &secret).await?;
+
+        Ok(secret)
+    }
+
+    pub async fn list(&self, user: UserId) -> Result<Vec<WebhookRecord>, DbError> {
+        self.db.list(user).await
+    }
+
+    /// Remove one of the user's webhooks. Returns whether the webhook existed.
+    pub async fn remove(&self, user: UserId, id: i64) -> Result<bool, DbError> {
+        self.db.remove(user, id).await
+    }
+
+    /// List the most recent delivery attempts for the user's webhooks.
+    pub async fn deliveries(&self, user: UserId) -> Result<Vec<DeliveryRecord>, DbError> {
+        self.db.deliveries(user, DELIVERY_LOG_LEN).await
+    }
+
+    /// Queue the event for each of the user's webhooks. Whatever caused the event has already
+    /// happened, so a failure is logged rather than returned.
+    pub async fn send(&self, user: UserId, event: WebhookEvent) {
+        let payload = Payload {
+            user: user.into(),
+            created: Utc::now().naive_utc(),
+            event: &event,
+        };
+
+        let result = match serde_json::to_string(&payload) {
+            Ok(payload) => self.db.enqueue(user, event.name(), &payload).await,
+            Err(e) => {
+                tracing::error!("unable to serialise {} webhook: {e}", event.name());
+                return;
+            }
+        };
+
+        match result {
+            Ok(0) => {}
+            Ok(_) => self.queued.notify_one(),
+            Err(e) => tracing::error!("unable to queue {} webhook: {e}", event.name()),
+        }
+    }
+
+    /// Deliver messages as they're queued, and retry failed messages once they're due, until
+    /// `shutdown` is cancelled.
+    pub async fn continuously_deliver(self, shutdown: CancellationToken) {
+        while !shutdown.is_cancelled() {
+            match self.deliver_due().await {
+                // There may be more waiting
+                Ok(count) if count == BATCH_SIZE as usize => continue,
+                Ok(_) => {}
+                Err(e) => tracing::error!("unable to deliver webhooks: {e}"),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_PERIOD) => {}
+                _ = self.queued.notified() => {}
+                _ = shutdown.cancelled() => {}
+            }
+        }
+    }
+
+    /// Attempt to deliver every message that is due, returning how many were attempted.
+    async fn deliver_due(&self) -> Result<usize, DbError> {
+        let messages = self.db.claim_due(BATCH_SIZE, LEASE_SECS).await?;
+        let count = messages.len();
+
+        futures::future::try_join_all(messages.into_iter().map(|message| async move {
+            let (status_code, error) = self.deliver(&message).await;
+
+            let retry_secs = if error.is_some() && message.attempts + 1 < MAX_ATTEMPTS {
+                Some(RETRY_BACKOFF_SECS << message.attempts)
+            } else {
+                None
+            };
+
+            metrics::counter!(
+                "webhook_deliveries_total",
+                "outcome" => if error.is_none() { "delivered" } else if retry_secs.is_some() { "retrying" } else { "failed" }
+            )
+            .increment(1);
+
+            self.db
+                .record_attempt(message.id, status_code, error.as_deref(), retry_secs)
+                .await
+        }))
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Post the message to its webhook, returning the status it responded with and why it failed.
+    async fn deliver(&self, message: &OutboxMessage) -> (Option<i64>, Option<String>) {
+        // Webhooks created before addresses were checked may not be allowed any more
+        if let Err(e) = Url::parse(&message.url)
+            .map_err(|_| UrlError::Invalid)
+            .and_then(|url| outbound::check_url(&url, self.allow_insecure))
+        {
+            return (None, Some(WebhookError::from(e).to_string()));
+        }
+
+        let timestamp = Utc::now().timestamp().to_string();
+
+        let response = self
+            .client
+            .post(&message.url)
+            .timeout(REQUEST_TIMEOUT)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &message.event)
+            .header(ID_HEADER, message.id.to_string())
+            .header(
+                SIGNATURE_HEADER,
+                format!(
+                    "sha256={}",
+                    sign(&message.secret, &timestamp, &message.payload)
+                ),
+            )
+            .header(TIMESTAMP_HEADER, timestamp)
+            .body(message.payload.clone())
+            .send()
+            .await;
+
+        match response {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16() as i64), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16() as i64),
+                Some(format!("endpoint responded with {}", response.status())),
+            ),
+            Err(e) => (None, Some(e.without_url().to_string())),
+        }
+    }
+}
+
+/// Sign the payload with the secret, so the endpoint can check that it came from us.
+fn sign(secret: &str, timestamp: &str, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
@@ -0,0 +1,112 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::HeaderName,
+    middleware::Next,
+    response::Response,
+};
+use metrics::{describe_counter, describe_histogram, Unit};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+/// Header that request IDs are read from and written to.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Filter used for logging when `RUST_LOG` isn't set.
+const DEFAULT_LOG_FILTER: &str = "cloud_casino=info,tower_http=info";
+
+/// Set up logging to stderr, using `RUST_LOG` to filter events. Logging to stderr keeps the output
+/// of CLI commands clean.
+pub fn init_tracing() {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER)),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+/// Install the global metrics recorder, returning a handle that can render the metrics for
+/// Prometheus.
+pub fn init_metrics() -> PrometheusHandle {
+    let handle = PrometheusBuilder::new()
+        .install_recorder()
+        .expect("metrics recorder can only be installed once");
+
+    describe_counter!(
+        "http_requests_total",
+        "HTTP requests that have been handled"
+    );
+    describe_histogram!(
+        "http_request_duration_seconds",
+        Unit::Seconds,
+        "Time taken to handle HTTP requests"
+    );
+    describe_counter!(
+        "weather_api_requests_total",
+        "Requests made to the weather API, by source and outcome"
+    );
+    describe_counter!(
+        "weather_cache_lookups_total",
+        "Forecast lookups, by whether they were served from the database"
+    );
+    describe_counter!(
+        "bets_placed_total",
+        "Bets that have been placed or replaced"
+    );
+    describe_histogram!("bet_wager_dollars", "Amount wagered on each bet placed");
+    describe_counter!("payouts_total", "Bets that have been paid out");
+    describe_histogram!("payout_dollars", "Amount paid out for each bet");
+    describe_counter!(
+        "bets_resettled_total",
+        "Paid out bets whose outcome changed after the observed weather was corrected"
+    );
+
+    handle
+}
+
+/// Create a span for each request, tagged with its request ID. Only the path is included, as the
+/// query may contain secrets such as OAuth codes.
+pub fn make_span(request: &Request) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        request_id,
+    )
+}
+
+/// Record the latency and status of each request against its route.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let method = request.method().to_string();
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("path", path),
+        ("status", response.status().as_u16().to_string()),
+    ];
+
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels)
+        .record(start.elapsed().as_secs_f64());
+
+    response
+}
@@ -1,26 +1,67 @@
+use std::{
+    fmt::{self, Display},
+    num::ParseIntError,
+    str::FromStr,
+};
+
 use axum::{
     async_trait,
-    extract::FromRequestParts,
-    http::request::Parts,
+    extract::{FromRef, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts},
     response::{IntoResponse, Redirect, Response},
 };
+use chrono::Utc;
 use tower_sessions::Session;
 
-#[derive(sqlx::Type, Clone, Copy, Debug)]
+use crate::{app::error::AppError, services::DbError, Ctx};
+
+#[derive(sqlx::Type, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[sqlx(transparent)]
 pub struct UserId(i64);
 
+impl From<i64> for UserId {
+    fn from(id: i64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<UserId> for i64 {
+    fn from(id: UserId) -> Self {
+        id.0
+    }
+}
+
+impl FromStr for UserId {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.parse()?))
+    }
+}
+
+impl Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl UserId {
     const SESSION_KEY: &'static str = "user_id";
+    const LAST_SEEN_KEY: &'static str = "last_seen";
 
-    pub async fn from_session(session: Session) -> Option<Self> {
-        Some(Self(session.get::<i64>(Self::SESSION_KEY).await.unwrap()?))
+    /// Activity is only recorded this often, rather than writing the session on every request.
+    /// Whether the session has been revoked is still checked every time.
+    const LAST_SEEN_INTERVAL_SECS: i64 = 60;
+
+    pub async fn from_session(session: &Session) -> Result<Option<Self>, DbError> {
+        Ok(session.get::<i64>(Self::SESSION_KEY).await?.map(Self))
     }
 }
 
 #[async_trait]
 impl<S> FromRequestParts<S> for UserId
 where
+    Ctx: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = Response;
@@ -31,8 +72,86 @@ where
             .await
             .map_err(|e| e.into_response())?;
 
-        Self::from_session(session)
+        let user_id = Self::from_session(&session)
+            .await
+            .map_err(|e| AppError::from(e).into_response())?
+            .ok_or(Redirect::temporary("/login").into_response())?;
+
+        let now = Utc::now().timestamp();
+        let last_seen = session
+            .get::<i64>(Self::LAST_SEEN_KEY)
+            .await
+            .map_err(|e| AppError::from(DbError::from(e)).into_response())?;
+        let seen_recently =
+            last_seen.is_some_and(|last_seen| now - last_seen < Self::LAST_SEEN_INTERVAL_SECS);
+
+        // Keep track of the session against the user
+        if let Some(session_id) = session.id() {
+            let sessions = &Ctx::from_ref(state).services.session;
+
+            let active = if seen_recently {
+                sessions.is_active(session_id, user_id).await
+            } else {
+                let user_agent = parts
+                    .headers
+                    .get(USER_AGENT)
+                    .and_then(|user_agent| user_agent.to_str().ok());
+
+                sessions.touch(session_id, user_id, user_agent).await
+            }
+            .map_err(|e| AppError::from(e).into_response())?;
+
+            // The session was revoked, but saved again by a request that was already in flight
+            if !active {
+                session
+                    .flush()
+                    .await
+                    .map_err(|e| AppError::from(DbError::from(e)).into_response())?;
+
+                return Err(Redirect::temporary("/login").into_response());
+            }
+        }
+
+        if seen_recently {
+            return Ok(user_id);
+        }
+
+        // Modify the session so that the inactivity expiry is pushed back
+        session
+            .insert(Self::LAST_SEEN_KEY, now)
             .await
-            .ok_or(Redirect::temporary("/login").into_response())
+            .map_err(|e| AppError::from(DbError::from(e)).into_response())?;
+
+        Ok(user_id)
+    }
+}
+
+/// User that has the admin role.
+#[derive(Clone, Copy, Debug)]
+pub struct AdminUser(pub UserId);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    Ctx: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user_id = UserId::from_request_parts(parts, state).await?;
+
+        let is_admin = Ctx::from_ref(state)
+            .services
+            .user
+            .is_admin(user_id)
+            .await
+            .map_err(|e| AppError::from(e).into_response())?;
+
+        if is_admin {
+            Ok(Self(user_id))
+        } else {
+            Err(AppError::Forbidden.into_response())
+        }
     }
 }
@@ -1,7 +1,66 @@
-window.addEventListener("load", () => {
-  // Ensure all icons are loaded
-  lucide.createIcons();
+if ("serviceWorker" in navigator) {
+  navigator.serviceWorker.register("/sw.js");
+}
 
-  // Any time HTMX finishes modifying the DOM, check if any icons need to be inserted
-  document.body.addEventListener("htmx:afterSwap", lucide.createIcons);
-});
+// Decode a base64url string, as used for the server's push key.
+function decodeBase64Url(value) {
+  const base64 = value.replace(/-/g, "+").replace(/_/g, "/");
+
+  return Uint8Array.from(atob(base64), (c) => c.charCodeAt(0));
+}
+
+// Headers that htmx sends with every request, which include the CSRF token.
+function pageHeaders() {
+  return JSON.parse(document.body.getAttribute("hx-headers") ?? "{}");
+}
+
+// Show whether push is enabled for this device on the settings page, and toggle it when clicked
+async function setupPushToggle() {
+  const button = document.getElementById("push-toggle");
+  if (!button || !("serviceWorker" in navigator) || !("PushManager" in window)) {
+    return;
+  }
+
+  const registration = await navigator.serviceWorker.ready;
+  const update = async () => {
+    const subscription = await registration.pushManager.getSubscription();
+    button.textContent = subscription
+      ? "stop pushing to this device"
+      : "push to this device";
+    button.hidden = false;
+
+    return subscription;
+  };
+
+  let subscription = await update();
+  button.onclick = async () => {
+    button.disabled = true;
+
+    try {
+      if (subscription) {
+        await fetch("/settings/push", {
+          method: "DELETE",
+          headers: { ...pageHeaders(), "Content-Type": "application/json" },
+          body: JSON.stringify({ endpoint: subscription.endpoint }),
+        });
+        await subscription.unsubscribe();
+      } else {
+        const created = await registration.pushManager.subscribe({
+          userVisibleOnly: true,
+          applicationServerKey: decodeBase64Url(button.dataset.publicKey),
+        });
+        await fetch("/settings/push", {
+          method: "POST",
+          headers: { ...pageHeaders(), "Content-Type": "application/json" },
+          body: JSON.stringify(created),
+        });
+      }
+    } finally {
+      subscription = await update();
+      button.disabled = false;
+    }
+  };
+}
+
+window.addEventListener("load", setupPushToggle);
+document.addEventListener("htmx:afterSwap", setupPushToggle);
@@ -0,0 +1,25 @@
+// Service worker, which shows push notifications whilst the app isn't open.
+
+self.addEventListener("push", (event) => {
+  const message = event.data?.json() ?? { title: "Cloud Casino", body: "" };
+
+  event.waitUntil(
+    self.registration.showNotification(message.title, {
+      body: message.body,
+      icon: "/icons/192.png",
+    }),
+  );
+});
+
+// Focus the app if it's already open, otherwise open it
+self.addEventListener("notificationclick", (event) => {
+  event.notification.close();
+
+  event.waitUntil(
+    self.clients.matchAll({ type: "window" }).then((clients) => {
+      const client = clients.find((client) => "focus" in client);
+
+      return client ? client.focus() : self.clients.openWindow("/");
+    }),
+  );
+});